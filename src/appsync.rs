@@ -0,0 +1,206 @@
+//! IAM authorization of [AWS AppSync](https://docs.aws.amazon.com/appsync/latest/devguide/real-time-websocket-client.html)
+//! real-time subscriptions
+//!
+//! A client connects to the real-time endpoint of a GraphQL API with the signed headers of a
+//! `POST` to `/graphql/connect`, encoded in the `header` query parameter of the WebSocket URL.
+//! Each subscription it starts carries the signed headers of a `POST` of the subscription to
+//! `/graphql` as its `extensions.authorization`.
+//!
+//! Queries and mutations are plain HTTP requests, signed with
+//! [`sign_request`](crate::client::sign_request) and [`SERVICE`].
+use std::fmt;
+
+use log::info;
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::Region;
+use serde::{Deserialize, Serialize};
+
+/// Signing name of AppSync APIs
+pub const SERVICE: &str = "appsync";
+
+/// Part of the host names of GraphQL endpoints that differs from real-time endpoints
+const API_HOST_LABEL: &str = ".appsync-api.";
+
+/// Part of the host names of real-time endpoints that differs from GraphQL endpoints
+const REALTIME_HOST_LABEL: &str = ".appsync-realtime-api.";
+
+/// Signed headers authorizing a real-time connection or subscription
+///
+/// The `Debug` output redacts the `Authorization` and `X-Amz-Security-Token` headers.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct AppSyncAuthorization {
+    /// `Accept` header of the signed request
+    pub accept: String,
+    /// `Content-Encoding` header of the signed request
+    #[serde(rename = "content-encoding")]
+    pub content_encoding: String,
+    /// `Content-Type` header of the signed request
+    #[serde(rename = "content-type")]
+    pub content_type: String,
+    /// Host name of the GraphQL endpoint of the API
+    pub host: String,
+    /// Time the request was signed, as `%Y%m%dT%H%M%SZ`
+    #[serde(rename = "x-amz-date")]
+    pub date: String,
+    /// Session token of temporary credentials
+    #[serde(
+        rename = "X-Amz-Security-Token",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub security_token: Option<String>,
+    /// The signature
+    #[serde(rename = "Authorization")]
+    pub authorization: String,
+}
+
+impl AppSyncAuthorization {
+    /// Authorize a connection to the real-time endpoint of the API whose GraphQL endpoint is
+    /// at `host`
+    pub fn for_connection(credentials: &AwsCredentials, region: &Region, host: &str) -> Self {
+        info!("Signing AppSync real-time connection to {}", host);
+        Self::sign(credentials, region, host, "/graphql/connect", "{}")
+    }
+
+    /// Authorize starting a subscription through a real-time connection to the API whose
+    /// GraphQL endpoint is at `host`
+    ///
+    /// `data` is the JSON of the `data` of the `start` message, holding the `query` and
+    /// `variables` of the subscription, and must be sent exactly as it was signed.
+    pub fn for_subscription(
+        credentials: &AwsCredentials,
+        region: &Region,
+        host: &str,
+        data: &str,
+    ) -> Self {
+        info!("Signing AppSync subscription to {}", host);
+        Self::sign(credentials, region, host, "/graphql", data)
+    }
+
+    fn sign(
+        credentials: &AwsCredentials,
+        region: &Region,
+        host: &str,
+        path: &str,
+        body: &str,
+    ) -> Self {
+        let mut request = SignedRequest::new("POST", SERVICE, region, path);
+        request.set_hostname(Some(host.to_string()));
+        request.add_header("accept", "application/json, text/javascript");
+        request.add_header("content-encoding", "amz-1.0");
+        request.set_content_type("application/json; charset=UTF-8".to_string());
+        request.set_payload(Some(body.as_bytes().to_vec()));
+        request.sign(credentials);
+
+        let header = |name: &str| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|values| values.first())
+                .map(|value| String::from_utf8_lossy(value).into_owned())
+        };
+        Self {
+            accept: header("accept").unwrap_or_default(),
+            content_encoding: header("content-encoding").unwrap_or_default(),
+            content_type: header("content-type").unwrap_or_default(),
+            host: host.to_string(),
+            date: header("x-amz-date").unwrap_or_default(),
+            security_token: header("x-amz-security-token"),
+            authorization: header("authorization").unwrap_or_default(),
+        }
+    }
+
+    /// Serialize the headers to JSON, as sent in `extensions.authorization` of a `start`
+    /// message
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("headers to serialize")
+    }
+}
+
+impl fmt::Debug for AppSyncAuthorization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppSyncAuthorization")
+            .field("accept", &self.accept)
+            .field("content_encoding", &self.content_encoding)
+            .field("content_type", &self.content_type)
+            .field("host", &self.host)
+            .field("date", &self.date)
+            .field(
+                "security_token",
+                &self.security_token.as_ref().map(|_| "<redacted>"),
+            )
+            .field("authorization", &"<redacted>")
+            .finish()
+    }
+}
+
+/// The URL to open a real-time WebSocket connection to the API whose GraphQL endpoint is at
+/// `host`, authorized with `credentials`
+///
+/// Connect with the `graphql-ws` subprotocol. APIs on custom domain names are served from
+/// `/graphql/realtime` of the domain.
+pub fn connection_url(credentials: &AwsCredentials, region: &Region, host: &str) -> String {
+    let authorization = AppSyncAuthorization::for_connection(credentials, region, host);
+    let (realtime_host, path) = if host.contains(API_HOST_LABEL) {
+        (
+            host.replacen(API_HOST_LABEL, REALTIME_HOST_LABEL, 1),
+            "/graphql",
+        )
+    } else {
+        (host.to_string(), "/graphql/realtime")
+    };
+    let query = serde_urlencoded::to_string([
+        ("header", base64::encode(authorization.to_json())),
+        ("payload", base64::encode("{}")),
+    ])
+    .expect("query to be encodable");
+    format!("wss://{}{}?{}", realtime_host, path, query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn connection_urls_carry_signed_headers() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let host = "abc123.appsync-api.us-west-2.amazonaws.com";
+
+        let url = connection_url(&credentials, &Region::UsWest2, host);
+        let url = url::Url::parse(&url).unwrap();
+        assert_eq!(
+            url.host_str(),
+            Some("abc123.appsync-realtime-api.us-west-2.amazonaws.com")
+        );
+        assert_eq!(url.path(), "/graphql");
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["payload"], "e30=");
+        let header: serde_json::Value =
+            serde_json::from_slice(&base64::decode(&params["header"]).unwrap())?;
+        assert_eq!(header["host"], host);
+        assert_eq!(header["content-encoding"], "amz-1.0");
+        assert!(header["Authorization"]
+            .as_str()
+            .unwrap()
+            .contains("/us-west-2/appsync/aws4_request"));
+
+        let custom = connection_url(&credentials, &Region::UsWest2, "api.example.com");
+        assert!(custom.starts_with("wss://api.example.com/graphql/realtime?"));
+
+        let subscription = AppSyncAuthorization::for_subscription(
+            &credentials,
+            &Region::UsWest2,
+            host,
+            r#"{"query":"subscription { onCreate { id } }","variables":{}}"#,
+        );
+        assert_ne!(
+            subscription.authorization,
+            serde_json::from_value::<AppSyncAuthorization>(header)?.authorization
+        );
+        assert!(!format!("{:?}", subscription).contains(&subscription.authorization));
+        Ok(())
+    }
+}
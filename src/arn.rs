@@ -0,0 +1,265 @@
+//! Parsing of Amazon Resource Names (ARNs)
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Error;
+
+/// An Amazon Resource Name of the form `arn:partition:service:region:account:resource`
+///
+/// See the [AWS documentation](https://docs.aws.amazon.com/IAM/latest/UserGuide/reference-arns.html)
+/// for more information. ARNs (de)serialize as their string representation.
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub struct Arn {
+    /// Partition the resource is in, such as `aws` or `aws-cn`
+    pub partition: String,
+    /// Service namespace, such as `iam` or `sts`
+    pub service: String,
+    /// Region the resource resides in. Empty for global resources like IAM roles
+    pub region: String,
+    /// ID of the AWS account that owns the resource. Empty for some resources like S3 buckets
+    pub account: String,
+    /// Service specific resource identifier, such as `role/my-role`
+    pub resource: String,
+}
+
+/// The kind of IAM principal identified by an ARN, as returned by `GetCallerIdentity`
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub enum Principal {
+    /// The root user of the account: `arn:aws:iam::123456789012:root`
+    Root,
+    /// An IAM user: `arn:aws:iam::123456789012:user/path/name`
+    User {
+        /// Path of the user, `/` unless the user was created with one
+        path: String,
+        /// Name of the user
+        name: String,
+    },
+    /// An IAM role: `arn:aws:iam::123456789012:role/path/name`
+    Role {
+        /// Path of the role, `/` unless the role was created with one
+        path: String,
+        /// Name of the role
+        name: String,
+    },
+    /// A session of an assumed role: `arn:aws:sts::123456789012:assumed-role/role/session`
+    ///
+    /// The path of the role is not part of the session ARN.
+    AssumedRole {
+        /// Name of the role that was assumed
+        role_name: String,
+        /// Name of the role session
+        session_name: String,
+    },
+    /// A federated user session: `arn:aws:sts::123456789012:federated-user/name`
+    FederatedUser {
+        /// Name of the federated user
+        name: String,
+    },
+}
+
+impl Arn {
+    /// Decompose the resource of an IAM or STS ARN into the principal it identifies
+    ///
+    /// Returns `None` for ARNs that do not identify a principal.
+    pub fn principal(&self) -> Option<Principal> {
+        let (kind, rest) = match self.resource.find('/') {
+            Some(index) => (&self.resource[..index], &self.resource[index + 1..]),
+            None => (self.resource.as_str(), ""),
+        };
+
+        let path_and_name = |rest: &str| {
+            let (path, name) = match rest.rfind('/') {
+                Some(index) => (format!("/{}/", &rest[..index]), &rest[index + 1..]),
+                None => ("/".to_string(), rest),
+            };
+            if name.is_empty() {
+                None
+            } else {
+                Some((path, name.to_string()))
+            }
+        };
+
+        match (self.service.as_str(), kind) {
+            ("iam", "root") if rest.is_empty() => Some(Principal::Root),
+            ("iam", "user") => {
+                path_and_name(rest).map(|(path, name)| Principal::User { path, name })
+            }
+            ("iam", "role") => {
+                path_and_name(rest).map(|(path, name)| Principal::Role { path, name })
+            }
+            ("sts", "assumed-role") => {
+                let mut parts = rest.splitn(2, '/');
+                match (parts.next(), parts.next()) {
+                    (Some(role_name), Some(session_name))
+                        if !role_name.is_empty() && !session_name.is_empty() =>
+                    {
+                        Some(Principal::AssumedRole {
+                            role_name: role_name.to_string(),
+                            session_name: session_name.to_string(),
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            ("sts", "federated-user") if !rest.is_empty() => Some(Principal::FederatedUser {
+                name: rest.to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The ARN of the IAM role behind an assumed role session
+    ///
+    /// `arn:aws:sts::123456789012:assumed-role/my-role/my-session` becomes
+    /// `arn:aws:iam::123456789012:role/my-role`. Because session ARNs do not carry the path of the
+    /// role, the result will not match the ARN of a role that has a path. Returns `None` for ARNs
+    /// that are not assumed role sessions.
+    pub fn role_arn(&self) -> Option<Arn> {
+        match self.principal()? {
+            Principal::AssumedRole { role_name, .. } => Some(Arn {
+                partition: self.partition.clone(),
+                service: "iam".to_string(),
+                region: String::new(),
+                account: self.account.clone(),
+                resource: format!("role/{}", role_name),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Arn {
+    type Err = Error;
+
+    fn from_str(arn: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| Error::InvalidArn {
+            arn: arn.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let parts: Vec<&str> = arn.splitn(6, ':').collect();
+        if parts.len() != 6 {
+            return Err(invalid("expected six colon separated components"));
+        }
+        if parts[0] != "arn" {
+            return Err(invalid("ARNs must start with `arn:`"));
+        }
+        if parts[1].is_empty() {
+            return Err(invalid("partition is empty"));
+        }
+        if parts[2].is_empty() {
+            return Err(invalid("service is empty"));
+        }
+        if parts[5].is_empty() {
+            return Err(invalid("resource is empty"));
+        }
+
+        Ok(Arn {
+            partition: parts[1].to_string(),
+            service: parts[2].to_string(),
+            region: parts[3].to_string(),
+            account: parts[4].to_string(),
+            resource: parts[5].to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Arn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "arn:{}:{}:{}:{}:{}",
+            self.partition, self.service, self.region, self.account, self.resource
+        )
+    }
+}
+
+impl Serialize for Arn {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Arn {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let arn = String::deserialize(deserializer)?;
+        arn.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arns_are_parsed_and_displayed() -> Result<(), crate::Error> {
+        let arn: Arn = "arn:aws:eks:us-west-2:123456789012:cluster/my-cluster".parse()?;
+
+        assert_eq!(arn.partition, "aws");
+        assert_eq!(arn.service, "eks");
+        assert_eq!(arn.region, "us-west-2");
+        assert_eq!(arn.account, "123456789012");
+        assert_eq!(arn.resource, "cluster/my-cluster");
+        assert_eq!(
+            arn.to_string(),
+            "arn:aws:eks:us-west-2:123456789012:cluster/my-cluster"
+        );
+        assert_eq!(arn.principal(), None);
+
+        assert!("arn:aws:iam::123456789012".parse::<Arn>().is_err());
+        assert!("urn:aws:iam::123456789012:root".parse::<Arn>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn principals_are_decomposed() -> Result<(), crate::Error> {
+        let user: Arn = "arn:aws:iam::123456789012:user/division/eng/alice".parse()?;
+        assert_eq!(
+            user.principal(),
+            Some(Principal::User {
+                path: "/division/eng/".to_string(),
+                name: "alice".to_string(),
+            })
+        );
+
+        let role: Arn = "arn:aws-cn:iam::123456789012:role/deployer".parse()?;
+        assert_eq!(
+            role.principal(),
+            Some(Principal::Role {
+                path: "/".to_string(),
+                name: "deployer".to_string(),
+            })
+        );
+
+        let root: Arn = "arn:aws:iam::123456789012:root".parse()?;
+        assert_eq!(root.principal(), Some(Principal::Root));
+        Ok(())
+    }
+
+    #[test]
+    fn assumed_roles_are_normalized() -> Result<(), crate::Error> {
+        let session: Arn =
+            "arn:aws:sts::123456789012:assumed-role/deployer/i-0123456789abcdef0".parse()?;
+
+        assert_eq!(
+            session.principal(),
+            Some(Principal::AssumedRole {
+                role_name: "deployer".to_string(),
+                session_name: "i-0123456789abcdef0".to_string(),
+            })
+        );
+        assert_eq!(
+            session.role_arn().map(|arn| arn.to_string()),
+            Some("arn:aws:iam::123456789012:role/deployer".to_string())
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+//! Reusing tokens that are still valid across invocations, for `--cache-dir`
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+use aws_auth_payload::sink::FileSink;
+use aws_auth_payload::Error;
+use chrono::{DateTime, Duration, Utc};
+use clap::Args;
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Name of the cache directory in `$XDG_CACHE_HOME`
+const CACHE_DIRECTORY: &str = "aws-auth-payload";
+
+/// Cached tokens expiring sooner than this are not reused
+const EXPIRY_MARGIN_SECONDS: i64 = 60;
+
+#[derive(Clone, Debug, Args)]
+pub struct CacheArgs {
+    /// Directory to reuse tokens that are still valid from, instead of
+    /// `$XDG_CACHE_HOME/aws-auth-payload` or `~/.cache/aws-auth-payload`
+    #[arg(long, env = "AWS_AUTH_PAYLOAD_CACHE_DIR", value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+    /// Always get a new token, without reading or writing the cache
+    #[arg(long)]
+    pub no_cache: bool,
+}
+
+/// A cached value and when it expires
+#[derive(Debug, Deserialize, Serialize)]
+struct Entry<T> {
+    expires_at: DateTime<Utc>,
+    value: T,
+}
+
+impl CacheArgs {
+    /// The value cached for `key` if it is still valid, or else the value `generate` returns,
+    /// which is cached until the expiry it returns with it if any
+    ///
+    /// The entry is locked while it is read and generated, so concurrent invocations with the
+    /// same key wait for the first one and reuse its value instead of generating their own.
+    pub async fn get_or_insert<T, F, Fut>(&self, key: &[String], generate: F) -> Result<T, Error>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(T, Option<DateTime<Utc>>), Error>>,
+    {
+        let directory = match self.directory() {
+            Some(directory) => directory,
+            None => return Ok(generate().await?.0),
+        };
+        create_directory(&directory)?;
+        let name = hex::encode(Sha256::digest(key.join("\0").as_bytes()));
+        let path = directory.join(format!("{}.json", name));
+        let lock = lock(directory.join(format!("{}.lock", name))).await?;
+
+        if let Some(value) = read(&path) {
+            debug!("Reusing the token cached in {}", path.display());
+            return Ok(value);
+        }
+        let (value, expires_at) = generate().await?;
+        if let Some(expires_at) = expires_at {
+            let entry = Entry {
+                expires_at,
+                value: &value,
+            };
+            FileSink::new(&path).write(&serde_json::to_vec(&entry)?)?;
+        }
+        drop(lock);
+        Ok(value)
+    }
+
+    /// `--cache-dir`, or the default directory, unless caching is turned off
+    fn directory(&self) -> Option<PathBuf> {
+        if self.no_cache {
+            return None;
+        }
+        self.cache_dir.clone().or_else(|| {
+            env::var_os("XDG_CACHE_HOME")
+                .filter(|directory| !directory.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+                .map(|directory| directory.join(CACHE_DIRECTORY))
+        })
+    }
+}
+
+/// The value of the entry at `path`, unless it is missing, unreadable or about to expire
+fn read<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let contents = fs::read(path).ok()?;
+    let entry: Entry<T> = match serde_json::from_slice(&contents) {
+        Ok(entry) => entry,
+        Err(e) => {
+            debug!("Ignoring the invalid cache entry {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let margin = Duration::seconds(EXPIRY_MARGIN_SECONDS);
+    if entry.expires_at - margin > Utc::now() {
+        Some(entry.value)
+    } else {
+        None
+    }
+}
+
+/// Create the cache directory, only accessible to the user on Unix as it holds tokens
+fn create_directory(directory: &Path) -> Result<(), Error> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    Ok(builder.create(directory)?)
+}
+
+/// Wait for an exclusive lock of the file at `path`, which is released when it is dropped
+async fn lock(path: PathBuf) -> Result<File, Error> {
+    tokio::task::spawn_blocking(move || {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+        debug!("Waiting for the lock of {}", path.display());
+        file.lock()?;
+        Ok(file)
+    })
+    .await
+    .map_err(|e| Error::GenericError(format!("Unable to lock the cache: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn values_are_reused_until_they_expire() {
+        let directory =
+            env::temp_dir().join(format!("aws-auth-payload-cache-{}", std::process::id()));
+        let cache = CacheArgs {
+            cache_dir: Some(directory.clone()),
+            no_cache: false,
+        };
+        let key = ["vault".to_string(), "https://vault.example.com".to_string()];
+        let valid = Utc::now() + Duration::hours(1);
+
+        let first: String = cache
+            .get_or_insert(&key, || async { Ok(("first".to_string(), Some(valid))) })
+            .await
+            .unwrap();
+        let second: String = cache
+            .get_or_insert(&key, || async { Ok(("second".to_string(), Some(valid))) })
+            .await
+            .unwrap();
+        assert_eq!((first.as_str(), second.as_str()), ("first", "first"));
+
+        // Entries about to expire are replaced
+        let expiring = Utc::now() + Duration::seconds(10);
+        let other = ["eks".to_string()];
+        for expected in ["first", "second"] {
+            let value: String = cache
+                .get_or_insert(&other, || async {
+                    Ok((expected.to_string(), Some(expiring)))
+                })
+                .await
+                .unwrap();
+            assert_eq!(value, expected);
+        }
+
+        let uncached = CacheArgs {
+            no_cache: true,
+            ..cache
+        };
+        let value: String = uncached
+            .get_or_insert(&key, || async { Ok(("third".to_string(), Some(valid))) })
+            .await
+            .unwrap();
+        assert_eq!(value, "third");
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
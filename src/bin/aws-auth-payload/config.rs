@@ -0,0 +1,165 @@
+//! Defaults for named targets, read from `~/.config/aws-auth-payload.toml`
+//!
+//! ```toml
+//! [defaults]
+//! profile = "sso"
+//!
+//! [vault.prod]
+//! addr = "https://vault.example.com:8200"
+//! role = "my-role"
+//! server_id = "vault.example.com"
+//!
+//! [eks.prod]
+//! cluster_name = "prod"
+//! region = "eu-west-1"
+//! ```
+//!
+//! Options given on the command line or in environment variables win over the target, which wins
+//! over the `[defaults]`.
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use aws_auth_payload::Error;
+use log::debug;
+use serde::Deserialize;
+
+/// Name of the configuration file in the configuration directory
+const CONFIG_FILE: &str = "aws-auth-payload.toml";
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    /// Defaults of every subcommand
+    #[serde(default)]
+    pub defaults: Defaults,
+    /// Targets of `payload`
+    #[serde(default)]
+    pub payload: HashMap<String, PayloadTarget>,
+    /// Targets of `vault login`
+    #[serde(default)]
+    pub vault: HashMap<String, VaultTarget>,
+    /// Targets of `eks token`
+    #[serde(default)]
+    pub eks: HashMap<String, EksTarget>,
+}
+
+/// Defaults of the options shared by all subcommands
+#[derive(Debug, Default, Deserialize)]
+pub struct Defaults {
+    pub region: Option<String>,
+    pub profile: Option<String>,
+    pub role_arn: Option<String>,
+    pub endpoint_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PayloadTarget {
+    #[serde(flatten)]
+    pub defaults: Defaults,
+    pub server_id: Option<String>,
+    /// Additional headers to sign
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VaultTarget {
+    #[serde(flatten)]
+    pub defaults: Defaults,
+    pub addr: Option<String>,
+    pub mount: Option<String>,
+    pub role: Option<String>,
+    pub server_id: Option<String>,
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EksTarget {
+    #[serde(flatten)]
+    pub defaults: Defaults,
+    pub cluster_name: Option<String>,
+}
+
+impl Config {
+    /// Read `file`, or the default file if it exists
+    pub fn load(file: Option<&Path>) -> Result<Self, Error> {
+        let (file, required) = match file {
+            Some(file) => (file.to_path_buf(), true),
+            None => match default_file() {
+                Some(file) => (file, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        match fs::read_to_string(&file) {
+            Ok(contents) => {
+                debug!("Reading configuration from {}", file.display());
+                Self::parse(&contents).map_err(|e| {
+                    Error::GenericError(format!("Invalid configuration {}: {}", file.display(), e))
+                })
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, Error> {
+        toml::from_str(contents).map_err(|e| Error::GenericError(e.message().to_string()))
+    }
+}
+
+/// The target `name` of the `kind` subcommand
+pub fn target<'a, T>(
+    targets: &'a HashMap<String, T>,
+    kind: &str,
+    name: &str,
+) -> Result<&'a T, Error> {
+    targets.get(name).ok_or_else(|| {
+        Error::GenericError(format!(
+            "No [{}.{}] target in the configuration",
+            kind, name
+        ))
+    })
+}
+
+/// `$XDG_CONFIG_HOME/aws-auth-payload.toml`, or `~/.config/aws-auth-payload.toml`
+fn default_file() -> Option<PathBuf> {
+    let directory = env::var_os("XDG_CONFIG_HOME")
+        .filter(|directory| !directory.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(directory.join(CONFIG_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_are_parsed() {
+        let config = Config::parse(
+            r#"
+            [defaults]
+            profile = "sso"
+
+            [vault.prod]
+            addr = "https://vault.example.com:8200"
+            role = "my-role"
+            region = "eu-west-1"
+
+            [payload.vault]
+            server_id = "vault.example.com"
+            headers = { X-Custom = "value" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.defaults.profile.as_deref(), Some("sso"));
+        let prod = target(&config.vault, "vault", "prod").unwrap();
+        assert_eq!(prod.role.as_deref(), Some("my-role"));
+        assert_eq!(prod.defaults.region.as_deref(), Some("eu-west-1"));
+        assert_eq!(config.payload["vault"].headers["X-Custom"], "value");
+        assert!(target(&config.eks, "eks", "prod").is_err());
+        assert!(Config::parse("vault = 1").is_err());
+    }
+}
@@ -0,0 +1,80 @@
+//! The `consul` subcommands
+use aws_auth_payload::consul::{Client, ConsulLoginData, ConsulTokenOptions};
+use aws_auth_payload::Error;
+use clap::Subcommand;
+
+use crate::context::Context;
+use crate::output::{self, Output};
+
+#[derive(Debug, Subcommand)]
+pub enum ConsulCommand {
+    /// Log in with an AWS IAM auth method and print the ACL token
+    Login {
+        /// Address of the Consul agent, `http://` unless it has a scheme
+        #[arg(long, env = "CONSUL_HTTP_ADDR")]
+        addr: String,
+        /// Name of the auth method of type `aws-iam` to log in with
+        #[arg(long)]
+        auth_method: String,
+        /// Sign the `X-Consul-IAM-ServerID` header with this value, as the auth method may
+        /// require
+        #[arg(long)]
+        server_id: Option<String>,
+        /// Also sign the `iam:GetRole` or `iam:GetUser` request that auth methods with
+        /// `EnableIAMEntityDetails` require
+        #[arg(long)]
+        include_entity: bool,
+        /// Print a shell `export CONSUL_HTTP_TOKEN=...` statement instead of the bare token, like
+        /// `--output env`
+        #[arg(long, conflicts_with = "output")]
+        export: bool,
+    },
+}
+
+pub async fn run(command: ConsulCommand, context: &Context) -> Result<(), Error> {
+    match command {
+        ConsulCommand::Login {
+            addr,
+            auth_method,
+            server_id,
+            include_entity,
+            export,
+        } => {
+            let client = Client::new(&address(&addr))?;
+            let options = ConsulTokenOptions {
+                region: context.region.clone(),
+                server_id,
+                include_entity,
+            };
+            let credentials = context.credentials().await?;
+            let data = ConsulLoginData::new(&credentials, &options).await?;
+            let token = client.login(&auth_method, &data).await?;
+            let output = if export { Output::Env } else { Output::Raw };
+            output::print(&token, context.output(output))?;
+        }
+    }
+    Ok(())
+}
+
+/// `CONSUL_HTTP_ADDR` is usually only a host and port, which Consul talks plain HTTP on
+fn address(addr: &str) -> String {
+    if addr.contains("://") {
+        addr.to_string()
+    } else {
+        format!("http://{}", addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_default_to_http() {
+        assert_eq!(address("127.0.0.1:8500"), "http://127.0.0.1:8500");
+        assert_eq!(
+            address("https://consul.example.com:8501"),
+            "https://consul.example.com:8501"
+        );
+    }
+}
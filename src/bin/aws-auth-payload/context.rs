@@ -0,0 +1,315 @@
+//! Options shared by all subcommands
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use aws_auth_payload::profile::AwsConfig;
+use aws_auth_payload::{region, AssumeRoleOptions, Error};
+use chrono::{DateTime, Utc};
+use clap::Args;
+use rusoto_core::credential::{
+    AwsCredentials, ContainerProvider, CredentialsError, EnvironmentProvider,
+    InstanceMetadataProvider, ProfileProvider, ProvideAwsCredentials,
+};
+use rusoto_core::Region;
+
+use crate::config::Defaults;
+use crate::output::Output;
+
+#[derive(Clone, Debug, Args)]
+pub struct Context {
+    /// Region of the STS endpoint to sign for, instead of the global endpoint
+    #[arg(long, global = true, env = "AWS_REGION", value_parser = parse_region)]
+    pub region: Option<Region>,
+    /// STS endpoint to send requests to and sign for instead of the endpoint of the region, such
+    /// as a VPC endpoint or a local emulator
+    #[arg(long, global = true, env = "AWS_ENDPOINT_URL_STS", value_name = "URL")]
+    pub endpoint_url: Option<String>,
+    /// Named profile of the AWS CLI configuration to take credentials from, including SSO and
+    /// role profiles
+    #[arg(long, global = true, env = "AWS_PROFILE")]
+    pub profile: Option<String>,
+    /// Sign with this access key, secret key and session token instead of looking credentials
+    /// up, such as in tests
+    #[arg(
+        long = "credentials",
+        global = true,
+        value_name = "KEY:SECRET[:TOKEN]",
+        value_parser = parse_credentials
+    )]
+    pub static_credentials: Option<AwsCredentials>,
+    /// Sign `payload`, `presign` and `eks token` as if it were this RFC 3339 time, so that the
+    /// same credentials always produce the same output
+    #[arg(long, global = true, value_name = "TIME", value_parser = parse_time)]
+    pub sign_time: Option<DateTime<Utc>>,
+    #[command(flatten)]
+    pub role: RoleArgs,
+    /// Format of the output, which defaults to the most common use of each subcommand
+    #[arg(long, global = true, value_enum)]
+    pub output: Option<Output>,
+    /// Configuration file with the defaults of named targets, instead of
+    /// `~/.config/aws-auth-payload.toml`
+    #[arg(
+        long,
+        global = true,
+        env = "AWS_AUTH_PAYLOAD_CONFIG",
+        value_name = "FILE"
+    )]
+    pub config: Option<PathBuf>,
+}
+
+/// A role to assume with the credentials, to sign as the role instead
+#[derive(Clone, Debug, Args)]
+pub struct RoleArgs {
+    /// ARN of a role to assume and sign as
+    #[arg(long, global = true)]
+    pub role_arn: Option<String>,
+    /// External ID the trust policy of the role requires
+    #[arg(long, global = true, requires = "role_arn")]
+    pub external_id: Option<String>,
+    /// Name of the role session
+    #[arg(long, global = true, requires = "role_arn")]
+    pub session_name: Option<String>,
+    /// How long the credentials of the role remain valid, in seconds
+    #[arg(long, global = true, requires = "role_arn", value_name = "SECONDS", value_parser = parse_seconds)]
+    pub duration: Option<Duration>,
+    /// Serial number or ARN of the MFA device the role requires, whose code is prompted for
+    #[arg(long, global = true, requires = "role_arn")]
+    pub mfa_serial: Option<String>,
+}
+
+impl Context {
+    /// Fill in the options that were not given from `defaults`
+    pub fn with_defaults(mut self, defaults: &Defaults) -> Result<Self, Error> {
+        if self.region.is_none() {
+            self.region = defaults
+                .region
+                .as_deref()
+                .map(region::parse_region)
+                .transpose()?;
+        }
+        if self.profile.is_none() {
+            self.profile = defaults.profile.clone();
+        }
+        if self.role.role_arn.is_none() {
+            self.role.role_arn = defaults.role_arn.clone();
+        }
+        if self.endpoint_url.is_none() {
+            self.endpoint_url = defaults.endpoint_url.clone();
+        }
+        Ok(self)
+    }
+
+    /// Point the region at `--endpoint-url`, keeping its name for the credential scope of
+    /// signatures, or `us-east-1` if none was given
+    pub fn with_endpoint(mut self) -> Result<Self, Error> {
+        if let Some(ref endpoint) = self.endpoint_url {
+            let name = self.region.as_ref().map_or("us-east-1", Region::name);
+            self.region = Some(region::custom_region(name, endpoint)?);
+        }
+        Ok(self)
+    }
+
+    /// The format asked for, or `default`
+    pub fn output(&self, default: Output) -> Output {
+        self.output.unwrap_or(default)
+    }
+
+    /// The credentials to sign with, from `--credentials`, the profile or the default AWS
+    /// credentials priority, and then of the role if one is given
+    pub async fn credentials(&self) -> Result<AwsCredentials, Error> {
+        let credentials = match (&self.static_credentials, &self.profile) {
+            (Some(credentials), _) => credentials.clone(),
+            (None, Some(profile)) => profile_credentials(profile).await?,
+            (None, None) => aws_auth_payload::get_aws_credentials().await?,
+        };
+        self.assume_role(credentials).await
+    }
+
+    /// Like [`credentials`](Self::credentials), and where the credentials were taken from, before
+    /// the role was assumed with them
+    pub async fn credentials_with_source(
+        &self,
+    ) -> Result<(AwsCredentials, CredentialSource), Error> {
+        let (credentials, source) = match (&self.static_credentials, &self.profile) {
+            (Some(credentials), _) => (credentials.clone(), CredentialSource::CommandLine),
+            (None, Some(profile)) => (
+                profile_credentials(profile).await?,
+                CredentialSource::Profile(profile.clone()),
+            ),
+            (None, None) => chained_credentials().await?,
+        };
+        Ok((self.assume_role(credentials).await?, source))
+    }
+
+    /// What decides whose credentials are signed with, to keep tokens cached for different
+    /// identities apart without looking the credentials up
+    pub fn identity_key(&self) -> Vec<String> {
+        let mut key = vec![
+            format!("{:?}", self.region),
+            format!("{:?}", self.profile),
+            format!(
+                "{:?}",
+                self.static_credentials
+                    .as_ref()
+                    .map(AwsCredentials::aws_access_key_id)
+            ),
+            format!("{:?}", self.role.role_arn),
+            format!("{:?}", self.role.external_id),
+            format!("{:?}", self.role.session_name),
+        ];
+        for name in IDENTITY_VARIABLES {
+            key.push(format!("{:?}", std::env::var_os(name)));
+        }
+        key
+    }
+
+    async fn assume_role(&self, credentials: AwsCredentials) -> Result<AwsCredentials, Error> {
+        match self.role.options()? {
+            Some(options) => {
+                let region = self.region.clone().unwrap_or_default();
+                aws_auth_payload::get_role_credentials(&credentials, &options, region).await
+            }
+            None => Ok(credentials),
+        }
+    }
+}
+
+/// Environment variables the default AWS credentials priority takes the identity from
+const IDENTITY_VARIABLES: &[&str] = &[
+    "AWS_ACCESS_KEY_ID",
+    "AWS_ROLE_ARN",
+    "AWS_SHARED_CREDENTIALS_FILE",
+    "AWS_CONTAINER_CREDENTIALS_RELATIVE_URI",
+    "AWS_CONTAINER_CREDENTIALS_FULL_URI",
+];
+
+/// Where credentials were taken from
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CredentialSource {
+    /// `--credentials`
+    CommandLine,
+    /// A profile of the AWS CLI configuration named with `--profile`
+    Profile(String),
+    /// `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`
+    Environment,
+    /// A profile of the shared credentials file
+    CredentialsFile(String),
+    /// The ECS or Fargate container credentials endpoint
+    Container,
+    /// The EC2 instance metadata service
+    InstanceMetadata,
+}
+
+impl CredentialSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CredentialSource::CommandLine => "command_line",
+            CredentialSource::Profile(_) => "profile",
+            CredentialSource::Environment => "environment",
+            CredentialSource::CredentialsFile(_) => "credentials_file",
+            CredentialSource::Container => "container",
+            CredentialSource::InstanceMetadata => "instance_metadata",
+        }
+    }
+
+    /// The name of the profile the credentials were taken from, if any
+    pub fn profile(&self) -> Option<&str> {
+        match self {
+            CredentialSource::Profile(profile) | CredentialSource::CredentialsFile(profile) => {
+                Some(profile)
+            }
+            _ => None,
+        }
+    }
+}
+
+async fn profile_credentials(profile: &str) -> Result<AwsCredentials, Error> {
+    AwsConfig::load()?
+        .with_mfa_prompt(Arc::new(prompt_mfa_code))
+        .credentials(profile)
+        .await
+}
+
+/// The credentials of the first provider of the default AWS credentials priority that has any,
+/// trying them in the order rusoto does
+async fn chained_credentials() -> Result<(AwsCredentials, CredentialSource), Error> {
+    if let Ok(credentials) = EnvironmentProvider::default().credentials().await {
+        return Ok((credentials, CredentialSource::Environment));
+    }
+    if let Ok(provider) = ProfileProvider::new() {
+        if let Ok(credentials) = provider.credentials().await {
+            let profile = provider.profile().to_string();
+            return Ok((credentials, CredentialSource::CredentialsFile(profile)));
+        }
+    }
+    if let Ok(credentials) = ContainerProvider::new().credentials().await {
+        return Ok((credentials, CredentialSource::Container));
+    }
+    if let Ok(credentials) = InstanceMetadataProvider::new().credentials().await {
+        return Ok((credentials, CredentialSource::InstanceMetadata));
+    }
+    Err(CredentialsError::new(
+        "Couldn't find AWS credentials in environment, credentials file, or IAM role.",
+    )
+    .into())
+}
+
+impl RoleArgs {
+    fn options(&self) -> Result<Option<AssumeRoleOptions>, Error> {
+        let role_arn = match self.role_arn {
+            Some(ref role_arn) => role_arn,
+            None => return Ok(None),
+        };
+        let mut options = AssumeRoleOptions::new(role_arn);
+        options.session_name = self.session_name.clone();
+        options.external_id = self.external_id.clone();
+        options.duration = self.duration;
+        if let Some(ref serial) = self.mfa_serial {
+            options = options.with_mfa(serial, &prompt_mfa_code(serial)?);
+        }
+        Ok(Some(options))
+    }
+}
+
+/// Ask for the code of the MFA device `serial` on the terminal
+fn prompt_mfa_code(serial: &str) -> Result<String, Error> {
+    eprint!("MFA code for {}: ", serial);
+    io::stderr().flush()?;
+    let mut code = String::new();
+    if io::stdin().lock().read_line(&mut code)? == 0 {
+        return Err(Error::GenericError(format!(
+            "No MFA code was entered for {}",
+            serial
+        )));
+    }
+    Ok(code.trim().to_string())
+}
+
+fn parse_region(name: &str) -> Result<Region, String> {
+    region::parse_region(name).map_err(|e| e.to_string())
+}
+
+fn parse_credentials(credentials: &str) -> Result<AwsCredentials, String> {
+    let mut parts = credentials.splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(key), Some(secret), token) if !key.is_empty() && !secret.is_empty() => Ok(
+            AwsCredentials::new(key, secret, token.map(str::to_string), None),
+        ),
+        _ => Err("credentials are not of the form KEY:SECRET[:TOKEN]".to_string()),
+    }
+}
+
+fn parse_time(time: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| format!("{:?} is not an RFC 3339 time: {}", time, e))
+}
+
+pub fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    seconds
+        .parse()
+        .map(Duration::from_secs)
+        .map_err(|_| format!("{:?} is not a number of seconds", seconds))
+}
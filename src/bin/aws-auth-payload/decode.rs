@@ -0,0 +1,226 @@
+//! The `decode` subcommand
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use aws_auth_payload::client::{AwsAuthIamPayload, EKS_TOKEN_PREFIX};
+use aws_auth_payload::kubernetes::EksToken;
+use aws_auth_payload::verify::SignedIdentityRequest;
+use aws_auth_payload::Error;
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use clap::Args;
+use serde::Serialize;
+
+use crate::context::Context;
+use crate::output::{self, Output, Render};
+use crate::verify;
+
+/// How long STS accepts requests signed with headers
+const SIGNATURE_VALIDITY_MINUTES: i64 = 15;
+
+#[derive(Debug, Args)]
+pub struct DecodeArgs {
+    /// File holding the JSON payload, pre-signed URL or EKS token, or `-` for standard input
+    #[arg(long, default_value = "-")]
+    file: PathBuf,
+}
+
+/// The signed request inside a payload, pre-signed URL or EKS token
+#[derive(Debug, Serialize)]
+pub struct DecodedRequest {
+    /// `payload`, `presigned-url` or `eks-token`
+    pub kind: &'static str,
+    pub method: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Headers sent with the request, which pre-signed URLs have none of
+    pub headers: BTreeMap<String, String>,
+    /// The access key ID, date, region and service the request is signed for
+    pub credential: Option<String>,
+    pub signed_headers: Vec<String>,
+    pub signed_at: Option<DateTime<Utc>>,
+    /// When the request stops being accepted
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Seconds left until `expires_at`, zero once it has passed
+    pub remaining_seconds: Option<i64>,
+}
+
+/// Print what is inside the signed request, without checking or sending it
+pub fn run(args: DecodeArgs, context: &Context) -> Result<(), Error> {
+    let input = verify::read_input(&args.file)?;
+    let decoded = decode(input.trim())?;
+    output::print(&decoded, context.output(Output::Json))
+}
+
+/// Decode a JSON payload, a pre-signed URL or an EKS token
+pub fn decode(input: &str) -> Result<DecodedRequest, Error> {
+    let mut decoded = match verify::signed_request(input)? {
+        SignedIdentityRequest::Payload(payload) => decode_payload(&payload)?,
+        SignedIdentityRequest::PresignedUrl(url) => decode_url(url)?,
+    };
+    // The authenticator accepts tokens for longer than the URL inside is valid for
+    if input.starts_with(EKS_TOKEN_PREFIX) {
+        let token: EksToken = input.parse()?;
+        decoded.kind = "eks-token";
+        decoded.expires_at = Some(token.expiration_timestamp());
+    }
+    decoded.remaining_seconds = decoded
+        .expires_at
+        .map(|expires_at| (expires_at - Utc::now()).num_seconds().max(0));
+    Ok(decoded)
+}
+
+fn decode_payload(payload: &AwsAuthIamPayload) -> Result<DecodedRequest, Error> {
+    let decode = |field: &str, value: &str| {
+        base64::decode(value)
+            .ok()
+            .and_then(|value| String::from_utf8(value).ok())
+            .ok_or_else(|| Error::InvalidAuthPayload(format!("{} is not base64", field)))
+    };
+    let headers: BTreeMap<String, String> = payload
+        .iam_request_headers
+        .iter()
+        .map(|(name, values)| (name.to_lowercase(), values.join(",")))
+        .collect();
+
+    // AWS4-HMAC-SHA256 Credential=..., SignedHeaders=..., Signature=...
+    let authorization: BTreeMap<&str, &str> = headers
+        .get("authorization")
+        .and_then(|authorization| authorization.split_once(' '))
+        .map(|(_, fields)| {
+            fields
+                .split(',')
+                .filter_map(|field| field.trim().split_once('='))
+                .collect()
+        })
+        .unwrap_or_default();
+    let signed_at = headers
+        .get("x-amz-date")
+        .map(|date| parse_date(date))
+        .transpose()?;
+
+    Ok(DecodedRequest {
+        kind: "payload",
+        method: payload.iam_http_request_method.clone(),
+        url: decode("iam_request_url", &payload.iam_request_url)?,
+        body: Some(decode("iam_request_body", &payload.iam_request_body)?),
+        credential: authorization.get("Credential").map(|c| c.to_string()),
+        signed_headers: split_signed_headers(authorization.get("SignedHeaders").copied()),
+        signed_at,
+        expires_at: signed_at.map(|at| at + Duration::minutes(SIGNATURE_VALIDITY_MINUTES)),
+        remaining_seconds: None,
+        headers,
+    })
+}
+
+fn decode_url(url: String) -> Result<DecodedRequest, Error> {
+    let query = url.split_once('?').map_or("", |(_, query)| query);
+    let params: BTreeMap<String, String> = serde_urlencoded::from_str(query)
+        .map_err(|_| Error::InvalidAuthPayload("the query of the URL is malformed".to_string()))?;
+    let signed_at = params
+        .get("X-Amz-Date")
+        .map(|date| parse_date(date))
+        .transpose()?;
+    let expires_in = params
+        .get("X-Amz-Expires")
+        .and_then(|expires| expires.parse().ok())
+        .map(Duration::seconds);
+
+    Ok(DecodedRequest {
+        kind: "presigned-url",
+        method: "GET".to_string(),
+        body: None,
+        headers: BTreeMap::new(),
+        credential: params.get("X-Amz-Credential").cloned(),
+        signed_headers: split_signed_headers(params.get("X-Amz-SignedHeaders").map(String::as_str)),
+        signed_at,
+        expires_at: signed_at
+            .zip(expires_in)
+            .map(|(at, expires_in)| at + expires_in),
+        remaining_seconds: None,
+        url,
+    })
+}
+
+fn split_signed_headers(signed_headers: Option<&str>) -> Vec<String> {
+    signed_headers
+        .map(|headers| headers.split(';').map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn parse_date(date: &str) -> Result<DateTime<Utc>, Error> {
+    let date = NaiveDateTime::parse_from_str(date, "%Y%m%dT%H%M%SZ")
+        .map_err(|_| Error::InvalidAuthPayload(format!("{:?} is not an X-Amz-Date", date)))?;
+    Ok(Utc.from_utc_datetime(&date))
+}
+
+impl Render for DecodedRequest {
+    /// The request as it would be sent over HTTP
+    fn raw(&self) -> String {
+        let mut request = format!("{} {}", self.method, self.url);
+        for (name, value) in &self.headers {
+            request.push_str(&format!("\n{}: {}", name, value));
+        }
+        if let Some(ref body) = self.body {
+            request.push_str(&format!("\n\n{}", body));
+        }
+        request
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("SIGNED_REQUEST_METHOD", self.method.clone()),
+            ("SIGNED_REQUEST_URL", self.url.clone()),
+        ];
+        if let Some(ref body) = self.body {
+            env.push(("SIGNED_REQUEST_BODY", body.clone()));
+        }
+        if let Some(expires_at) = self.expires_at {
+            env.push(("SIGNED_REQUEST_EXPIRES_AT", expires_at.to_rfc3339()));
+        }
+        env
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use aws_auth_payload::client;
+    use rusoto_core::credential::AwsCredentials;
+    use rusoto_core::Region;
+
+    #[test]
+    fn signed_requests_are_decoded() {
+        let credentials = AwsCredentials::new("AKIDEXAMPLE", "secret", None, None);
+        let mut headers = HashMap::new();
+        headers.insert("X-Vault-AWS-IAM-Server-ID", "vault.example.com");
+        let payload = AwsAuthIamPayload::new(&credentials, Some(Region::EuWest1), headers);
+
+        let decoded = decode(&serde_json::to_string(&payload).unwrap()).unwrap();
+        assert_eq!(decoded.kind, "payload");
+        assert_eq!(decoded.method, "POST");
+        assert_eq!(decoded.url, "https://sts.eu-west-1.amazonaws.com/");
+        assert_eq!(
+            decoded.body.as_deref(),
+            Some("Action=GetCallerIdentity&Version=2011-06-15")
+        );
+        assert!(decoded
+            .signed_headers
+            .contains(&"x-vault-aws-iam-server-id".to_string()));
+        assert!(decoded.credential.unwrap().starts_with("AKIDEXAMPLE/"));
+        assert!(decoded.remaining_seconds.unwrap() > 0);
+
+        let token = client::eks_token(&credentials, "prod", Some(Region::EuWest1));
+        let decoded = decode(token.as_str()).unwrap();
+        assert_eq!(decoded.kind, "eks-token");
+        assert_eq!(decoded.signed_headers, vec!["host", "x-k8s-aws-id"]);
+        assert_eq!(decoded.signed_at, Some(token.signed_at()));
+        assert_eq!(decoded.expires_at, Some(token.expiration_timestamp()));
+        assert!(decoded
+            .raw()
+            .starts_with("GET https://sts.eu-west-1.amazonaws.com/?"));
+    }
+}
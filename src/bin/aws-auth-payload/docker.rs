@@ -0,0 +1,133 @@
+//! The `docker-credential` subcommands, answering Docker as a credential helper for ECR
+//!
+//! Docker runs `docker-credential-<name> get|store|erase`, so the binary also accepts the
+//! subcommands directly when it is installed or linked under a `docker-credential-` name.
+use std::ffi::OsString;
+use std::io::{self, Read};
+use std::path::Path;
+
+use aws_auth_payload::ecr::EcrCredentials;
+use aws_auth_payload::{region, Error};
+use clap::Subcommand;
+use rusoto_core::Region;
+
+use crate::context::Context;
+
+/// What Docker expects on standard output for registries a helper has no credentials for, so that
+/// it carries on without credentials
+const NOT_FOUND: &str = "credentials not found in native keychain";
+
+#[derive(Debug, Subcommand)]
+pub enum DockerCredentialCommand {
+    /// Print the credentials of the ECR registry whose URL is on standard input
+    Get,
+    /// Accept credentials Docker asks to store, which are never needed for ECR
+    Store,
+    /// Accept credentials Docker asks to erase, of which none are stored
+    Erase,
+}
+
+pub async fn run(command: DockerCredentialCommand, context: &Context) -> Result<(), Error> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    match command {
+        DockerCredentialCommand::Get => {
+            // Docker reads the reason of failures from standard output
+            match credential(input.trim(), context).await {
+                Ok(credential) => println!("{}", credential),
+                Err(e) => {
+                    println!("{}", e);
+                    return Err(e);
+                }
+            }
+        }
+        DockerCredentialCommand::Store | DockerCredentialCommand::Erase => {}
+    }
+    Ok(())
+}
+
+async fn credential(server_url: &str, context: &Context) -> Result<String, Error> {
+    let region =
+        registry_region(server_url)?.ok_or_else(|| Error::GenericError(NOT_FOUND.to_string()))?;
+    let credentials = context.credentials().await?;
+    // Authorization tokens are valid for every registry the caller has access to
+    let mut credential = EcrCredentials::fetch(&credentials, &region)
+        .await?
+        .docker_credential();
+    credential.server_url = server_url.to_string();
+    Ok(credential.to_json())
+}
+
+/// The region of an ECR registry such as `123456789012.dkr.ecr.eu-west-1.amazonaws.com`, or
+/// `None` for other registries
+fn registry_region(server_url: &str) -> Result<Option<Region>, Error> {
+    let host = server_url
+        .trim_start_matches("https://")
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    match host.split('.').collect::<Vec<_>>()[..] {
+        [account, "dkr" | "dkr-fips", "ecr", region, _, ..]
+            if account.len() == 12 && account.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            region::parse_region(region).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The command line, with `docker-credential` inserted when the binary is run by Docker as a
+/// credential helper
+pub fn helper_args<I>(args: I) -> Vec<OsString>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut args: Vec<OsString> = args.into_iter().collect();
+    let is_helper = args.first().is_some_and(|program| {
+        Path::new(program)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("docker-credential-"))
+    });
+    if is_helper {
+        args.insert(1, "docker-credential".into());
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registries_are_recognized() {
+        assert_eq!(
+            registry_region("https://123456789012.dkr.ecr.eu-west-1.amazonaws.com").unwrap(),
+            Some(Region::EuWest1)
+        );
+        assert_eq!(
+            registry_region("123456789012.dkr.ecr.cn-north-1.amazonaws.com.cn/v2/").unwrap(),
+            Some(Region::CnNorth1)
+        );
+        assert_eq!(
+            registry_region("https://index.docker.io/v1/").unwrap(),
+            None
+        );
+        assert_eq!(registry_region("public.ecr.aws").unwrap(), None);
+        assert!(registry_region("123456789012.dkr.ecr.moon-1.amazonaws.com").is_err());
+
+        assert_eq!(
+            helper_args(["/usr/bin/docker-credential-aws-auth-payload", "get"].map(OsString::from)),
+            [
+                "/usr/bin/docker-credential-aws-auth-payload",
+                "docker-credential",
+                "get"
+            ]
+            .map(OsString::from)
+        );
+        assert_eq!(
+            helper_args(["aws-auth-payload", "presign"].map(OsString::from)).len(),
+            2
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! The `ecr` subcommands
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use aws_auth_payload::ecr::EcrCredentials;
+use aws_auth_payload::region::Partition;
+use aws_auth_payload::Error;
+use chrono::{DateTime, Utc};
+use clap::Subcommand;
+use log::info;
+use rusoto_core::Region;
+use serde::Serialize;
+
+use crate::context::Context;
+use crate::output::{self, Output, Render};
+
+#[derive(Debug, Subcommand)]
+pub enum EcrCommand {
+    /// Print the password of the ECR registry of `--region`, like `aws ecr get-login-password`,
+    /// or log Docker in to it
+    Login {
+        /// Account ID of the registry, instead of the account of the credentials
+        ///
+        /// Passwords are valid for every registry the credentials have access to.
+        #[arg(long, value_name = "ACCOUNT_ID", value_parser = parse_registry_id)]
+        registry_id: Option<String>,
+        /// Run `docker login --password-stdin` with the password instead of printing it
+        #[arg(long, conflicts_with = "output")]
+        docker_login: bool,
+    },
+}
+
+/// Credentials for `docker login`
+#[derive(Debug, Serialize)]
+pub struct RegistryLogin {
+    pub registry: String,
+    pub username: String,
+    pub password: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Render for RegistryLogin {
+    /// The password, to pipe to `docker login --password-stdin`
+    fn raw(&self) -> String {
+        self.password.clone()
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("ECR_REGISTRY", self.registry.clone()),
+            ("ECR_USERNAME", self.username.clone()),
+            ("ECR_PASSWORD", self.password.clone()),
+        ]
+    }
+}
+
+pub async fn run(command: EcrCommand, context: &Context) -> Result<(), Error> {
+    match command {
+        EcrCommand::Login {
+            registry_id,
+            docker_login,
+        } => {
+            let region = context.region.clone().unwrap_or_default();
+            let credentials = context.credentials().await?;
+            let ecr = EcrCredentials::fetch(&credentials, &region).await?;
+            let registry = match registry_id {
+                Some(ref id) => registry_host(id, &region),
+                None => ecr.registry().to_string(),
+            };
+            let login = RegistryLogin {
+                registry,
+                username: ecr.username,
+                password: ecr.password,
+                expires_at: ecr.expires_at,
+            };
+            if docker_login {
+                self::docker_login(&login)?;
+            } else {
+                output::print(&login, context.output(Output::Raw))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Host name of the registry of the account `registry_id` in `region`
+fn registry_host(registry_id: &str, region: &Region) -> String {
+    let partition = Partition::of_region(region.name());
+    format!(
+        "{}.dkr.ecr.{}.{}",
+        registry_id,
+        region.name(),
+        partition.dns_suffix()
+    )
+}
+
+/// Log Docker in with the password on standard input, so it never appears in the process list
+fn docker_login(login: &RegistryLogin) -> Result<(), Error> {
+    info!("Logging Docker in to {}", login.registry);
+    let mut docker = Command::new("docker")
+        .args(["login", "--username", &login.username, "--password-stdin"])
+        .arg(&login.registry)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    docker
+        .stdin
+        .take()
+        .expect("stdin to be piped")
+        .write_all(login.password.as_bytes())?;
+    let status = docker.wait()?;
+    if !status.success() {
+        return Err(Error::GenericError(format!(
+            "docker login to {} failed with {}",
+            login.registry, status
+        )));
+    }
+    Ok(())
+}
+
+fn parse_registry_id(id: &str) -> Result<String, String> {
+    if id.len() == 12 && id.bytes().all(|b| b.is_ascii_digit()) {
+        Ok(id.to_string())
+    } else {
+        Err(format!("{:?} is not a 12 digit account ID", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registries_are_named_after_their_account() {
+        assert_eq!(
+            registry_host("123456789012", &Region::EuWest1),
+            "123456789012.dkr.ecr.eu-west-1.amazonaws.com"
+        );
+        assert_eq!(
+            registry_host("123456789012", &Region::CnNorth1),
+            "123456789012.dkr.ecr.cn-north-1.amazonaws.com.cn"
+        );
+        assert!(parse_registry_id("1234").is_err());
+    }
+}
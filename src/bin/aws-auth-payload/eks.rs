@@ -0,0 +1,249 @@
+//! The `eks` subcommands
+use aws_auth_payload::client;
+use aws_auth_payload::kubernetes::{ClusterRequest, EksCluster, ExecCredential, ExecInfo};
+use aws_auth_payload::{AssumeRoleOptions, Error};
+use chrono::Utc;
+use clap::Subcommand;
+use rusoto_core::Region;
+
+use crate::cache::CacheArgs;
+use crate::config::{self, Config, Defaults};
+use crate::context::Context;
+use crate::output::{self, Output};
+use crate::watch::{self, WatchArgs};
+
+#[derive(Debug, Subcommand)]
+pub enum EksCommand {
+    /// Print an `ExecCredential` for a cluster, like `aws eks get-token`
+    ///
+    /// This can be the `exec` command of a kubeconfig user. Without `--cluster-name` and
+    /// `--role-arn`, they are taken from the cluster information kubectl passes in
+    /// `KUBERNETES_EXEC_INFO`, and the API version of the credential is the one kubectl asked
+    /// for.
+    Token {
+        /// Target of the configuration file to take the other options from
+        target: Option<String>,
+        /// Name or ARN of the cluster
+        #[arg(long, value_parser = parse_cluster)]
+        cluster_name: Option<EksCluster>,
+        #[command(flatten)]
+        watch: WatchArgs,
+        #[command(flatten)]
+        cache: CacheArgs,
+    },
+}
+
+impl EksCommand {
+    /// Fill in the options that were not given from the target, and return its defaults
+    pub fn apply_target<'a>(&mut self, config: &'a Config) -> Result<Option<&'a Defaults>, Error> {
+        match self {
+            EksCommand::Token {
+                target: Some(name),
+                cluster_name,
+                ..
+            } => {
+                let target = config::target(&config.eks, "eks", name)?;
+                if cluster_name.is_none() {
+                    *cluster_name = target.cluster_name.as_deref().map(str::parse).transpose()?;
+                }
+                Ok(Some(&target.defaults))
+            }
+            EksCommand::Token { target: None, .. } => Ok(None),
+        }
+    }
+}
+
+pub async fn run(command: EksCommand, context: &Context) -> Result<(), Error> {
+    match command {
+        EksCommand::Token {
+            target: _,
+            cluster_name,
+            watch,
+            cache,
+        } => {
+            let info = ExecInfo::from_env()?;
+            let mut request = cluster_request(cluster_name, context.region.clone(), info.as_ref())?;
+            // The credentials of the context are those of the role of the command line already
+            if context.role.role_arn.is_some() {
+                request.role_arn = None;
+            }
+            match watch.watch {
+                Some(file) => {
+                    let context = context.clone();
+                    watch::keep_fresh(file, watch.refresh_margin, move || {
+                        let (request, context) = (request.clone(), context.clone());
+                        async move {
+                            let credential = credential(&request, &context).await?;
+                            let ttl = credential
+                                .status
+                                .expiration_timestamp
+                                .and_then(|expiration| (expiration - Utc::now()).to_std().ok());
+                            Ok((credential.status.token, ttl))
+                        }
+                    })
+                    .await?;
+                }
+                None => {
+                    let credential = cached_credential(&cache, &request, context).await?;
+                    let credential = match info {
+                        Some(info) => info.respond(credential)?,
+                        None => credential,
+                    };
+                    output::print(&credential, context.output(Output::Json))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The credential for `request` from the cache, or a new one that is then cached
+async fn cached_credential(
+    cache: &CacheArgs,
+    request: &ClusterRequest,
+    context: &Context,
+) -> Result<ExecCredential, Error> {
+    // Tokens signed at `--sign-time` differ from those signed now
+    let mut key = vec![
+        "eks".to_string(),
+        format!("{:?}", request.cluster),
+        format!("{:?}", request.role_arn),
+        format!("{:?}", context.sign_time),
+    ];
+    key.extend(context.identity_key());
+    cache
+        .get_or_insert(&key, || async {
+            let credential = credential(request, context).await?;
+            let expires_at = credential.status.expiration_timestamp;
+            Ok((credential, expires_at))
+        })
+        .await
+}
+
+async fn credential(request: &ClusterRequest, context: &Context) -> Result<ExecCredential, Error> {
+    let mut credentials = context.credentials().await?;
+    let signed_at = match context.sign_time {
+        Some(signed_at) => signed_at,
+        None => {
+            return ExecCredential::for_clusters(&credentials, std::slice::from_ref(request))
+                .await
+                .remove(0)
+        }
+    };
+    let region = request.cluster.region.clone();
+    if let Some(ref role_arn) = request.role_arn {
+        let options = AssumeRoleOptions::new(role_arn);
+        let region = region.clone().unwrap_or_default();
+        credentials =
+            aws_auth_payload::get_role_credentials(&credentials, &options, region).await?;
+    }
+    let token = client::eks_token_at(&credentials, &request.cluster.name, region, signed_at);
+    Ok(ExecCredential::for_eks_token(&token))
+}
+
+fn parse_cluster(cluster: &str) -> Result<EksCluster, String> {
+    cluster.parse().map_err(|e: Error| e.to_string())
+}
+
+/// The cluster from the arguments, falling back to what kubectl passed, and the role kubectl
+/// passed
+fn cluster_request(
+    cluster: Option<EksCluster>,
+    region: Option<Region>,
+    info: Option<&ExecInfo>,
+) -> Result<ClusterRequest, Error> {
+    let mut cluster = match (cluster, info.and_then(ExecInfo::cluster_name)) {
+        (Some(cluster), _) => cluster,
+        (None, Some(name)) => name.parse()?,
+        (None, None) => {
+            return Err(Error::GenericError(
+                "No --cluster-name given and none in KUBERNETES_EXEC_INFO".to_string(),
+            ))
+        }
+    };
+    // The region of an ARN wins over the region of the command line
+    if cluster.region.is_none() {
+        cluster.region = region;
+    }
+
+    let mut request = ClusterRequest::new(cluster);
+    request.role_arn = info.and_then(ExecInfo::role_arn).map(str::to_string);
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clusters_fall_back_to_the_exec_info() {
+        let info: ExecInfo = r#"{
+            "apiVersion": "client.authentication.k8s.io/v1",
+            "kind": "ExecCredential",
+            "spec": {
+                "interactive": false,
+                "cluster": {
+                    "server": "https://example.eks.amazonaws.com",
+                    "config": {
+                        "clusterName": "arn:aws:eks:eu-west-1:123456789012:cluster/prod",
+                        "roleArn": "arn:aws:iam::123456789012:role/admin"
+                    }
+                }
+            }
+        }"#
+        .parse()
+        .unwrap();
+
+        let request = cluster_request(None, Some(Region::UsEast1), Some(&info)).unwrap();
+        assert_eq!(request.cluster.name, "prod");
+        assert_eq!(request.cluster.region, Some(Region::EuWest1));
+        assert_eq!(
+            request.role_arn.as_deref(),
+            Some("arn:aws:iam::123456789012:role/admin")
+        );
+
+        let cluster = "dev".parse().ok();
+        let request = cluster_request(cluster, Some(Region::UsEast1), None).unwrap();
+        assert_eq!(request.cluster.region, Some(Region::UsEast1));
+        assert_eq!(request.role_arn, None);
+        assert!(cluster_request(None, None, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn tokens_signed_at_other_times_are_not_reused() {
+        use clap::Parser;
+
+        let directory =
+            std::env::temp_dir().join(format!("aws-auth-payload-eks-{}", std::process::id()));
+        let cache = CacheArgs {
+            cache_dir: Some(directory.clone()),
+            no_cache: false,
+        };
+        let request = cluster_request("prod".parse().ok(), Some(Region::EuWest1), None).unwrap();
+
+        // Sign in the future, so that the tokens are still valid and cached
+        let now = Utc::now();
+        let mut tokens = Vec::new();
+        for hours in [1, 1, 2] {
+            let signed_at = (now + chrono::Duration::hours(hours)).to_rfc3339();
+            let cli = crate::Cli::try_parse_from([
+                "aws-auth-payload",
+                "eks",
+                "token",
+                "--credentials",
+                "AKIDEXAMPLE:secret",
+                "--sign-time",
+                &signed_at,
+            ])
+            .unwrap();
+            let credential = cached_credential(&cache, &request, &cli.context)
+                .await
+                .unwrap();
+            tokens.push(credential.status.token);
+        }
+        std::fs::remove_dir_all(directory).unwrap();
+
+        assert_eq!(tokens[0], tokens[1]);
+        assert_ne!(tokens[1], tokens[2]);
+    }
+}
@@ -0,0 +1,160 @@
+//! Exit codes and reports of the errors subcommands fail with
+//!
+//! The exit codes are stable, and listed by [`EXIT_CODES`] in the help of the binary.
+use aws_auth_payload::sts::StsErrorCode;
+use aws_auth_payload::Error;
+use rusoto_core::credential::CredentialsError;
+use rusoto_core::RusotoError;
+use serde::Serialize;
+
+use crate::output::Output;
+
+/// The exit codes, for the long help and the manual page
+pub const EXIT_CODES: &str = "\
+Exit codes:
+  1  Any other error
+  2  Invalid command line arguments
+  3  No credentials were found
+  4  The credentials have expired
+  5  A request could not be sent, or no response was received
+  6  A payload, pre-signed URL or token failed verification
+
+With --output json, errors are printed on standard error as a JSON object with the fields \
+error, exit_code and message, and sts_error_code when AWS STS rejected a request.";
+
+/// The class of an error, which decides the exit code
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Failure {
+    Other,
+    NoCredentials,
+    ExpiredCredentials,
+    Network,
+    VerificationFailed,
+}
+
+impl Failure {
+    /// The class of `error`, where STS rejecting a request or a malformed payload is a failed
+    /// verification if `verifying`, and not a problem of the credentials of the command line
+    pub fn of(error: &Error, verifying: bool) -> Self {
+        match error {
+            Error::CredentialsError(error) => Self::of_credentials(error),
+            Error::AssumeRoleError(error) => match **error {
+                RusotoError::Credentials(ref error) => Self::of_credentials(error),
+                RusotoError::HttpDispatch(_) => Failure::Network,
+                RusotoError::Unknown(ref response)
+                    if String::from_utf8_lossy(&response.body).contains("ExpiredToken") =>
+                {
+                    Failure::ExpiredCredentials
+                }
+                _ => Failure::Other,
+            },
+            Error::StsError { code, .. } if verifying && code.is_authentication_failure() => {
+                Failure::VerificationFailed
+            }
+            Error::JsonError(_) if verifying => Failure::VerificationFailed,
+            Error::StsError {
+                code: StsErrorCode::ExpiredToken,
+                ..
+            } => Failure::ExpiredCredentials,
+            Error::HttpDispatchError(_)
+            | Error::TlsError(_)
+            | Error::HttpError(_)
+            | Error::HttpTimeout => Failure::Network,
+            Error::InvalidAuthPayload(_)
+            | Error::AuthPayloadExpired
+            | Error::AuthPayloadReplayed
+            | Error::AuthPayloadDuplicateParameter(_)
+            | Error::AuthPayloadMixedCaseHost(_)
+            | Error::AuthPayloadPathTraversal(_)
+            | Error::AuthPayloadPort(_)
+            | Error::AuthPayloadUnsignedHeader(_)
+            | Error::InvalidEksToken(_)
+            | Error::EksTokenExpired => Failure::VerificationFailed,
+            _ => Failure::Other,
+        }
+    }
+
+    /// rusoto only describes why credentials could not be obtained in the message
+    fn of_credentials(error: &CredentialsError) -> Self {
+        if error.message.to_lowercase().contains("expired") {
+            Failure::ExpiredCredentials
+        } else {
+            Failure::NoCredentials
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Failure::Other => 1,
+            Failure::NoCredentials => 3,
+            Failure::ExpiredCredentials => 4,
+            Failure::Network => 5,
+            Failure::VerificationFailed => 6,
+        }
+    }
+}
+
+/// An error as printed on standard error with `--output json`
+#[derive(Debug, Serialize)]
+struct Report<'a> {
+    error: Failure,
+    exit_code: i32,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sts_error_code: Option<&'a str>,
+}
+
+/// Print `error` on standard error, as JSON for `--output json`, and return the exit code
+pub fn report(error: &Error, output: Option<Output>, verifying: bool) -> i32 {
+    let failure = Failure::of(error, verifying);
+    match output {
+        Some(Output::Json) => eprintln!("{}", json_report(error, failure)),
+        _ => eprintln!("Error: {}", error),
+    }
+    failure.exit_code()
+}
+
+fn json_report(error: &Error, failure: Failure) -> String {
+    let report = Report {
+        error: failure,
+        exit_code: failure.exit_code(),
+        message: error.to_string(),
+        sts_error_code: match error {
+            Error::StsError { code, .. } => Some(code.as_str()),
+            _ => None,
+        },
+    };
+    serde_json::to_string(&report).expect("a report to be serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_classified() {
+        let expired = Error::StsError {
+            status: 403,
+            code: StsErrorCode::ExpiredToken,
+            message: "The security token included in the request is expired".to_string(),
+        };
+        assert_eq!(Failure::of(&expired, false), Failure::ExpiredCredentials);
+        assert_eq!(Failure::of(&expired, true), Failure::VerificationFailed);
+        assert_eq!(
+            Failure::of(&CredentialsError::new("No credentials found").into(), false),
+            Failure::NoCredentials
+        );
+        assert_eq!(Failure::of(&Error::HttpTimeout, false), Failure::Network);
+        assert_eq!(
+            Failure::of(&Error::GenericError("x".to_string()), true),
+            Failure::Other
+        );
+
+        let report: serde_json::Value =
+            serde_json::from_str(&json_report(&expired, Failure::ExpiredCredentials)).unwrap();
+        assert_eq!(report["error"], "expired_credentials");
+        assert_eq!(report["exit_code"], 4);
+        assert_eq!(report["sts_error_code"], "ExpiredToken");
+    }
+}
@@ -0,0 +1,33 @@
+//! Shell completions and man pages, for packaging the binary
+use std::io::{self, Write};
+use std::path::Path;
+
+use aws_auth_payload::Error;
+use clap_complete::Shell;
+use clap_mangen::Man;
+
+/// Print the completion script of `shell` for `command`
+pub fn completions(shell: Shell, mut command: clap::Command) -> Result<(), Error> {
+    let name = command.get_name().to_string();
+    let mut script = vec![];
+    clap_complete::generate(shell, &mut command, name, &mut script);
+    io::stdout().write_all(&script)?;
+    Ok(())
+}
+
+/// Print the man page of `command`, or write the pages of `command` and all its subcommands to
+/// `directory`
+pub fn man_pages(command: clap::Command, directory: Option<&Path>) -> Result<(), Error> {
+    match directory {
+        Some(directory) => {
+            std::fs::create_dir_all(directory)?;
+            clap_mangen::generate_to(command, directory)?;
+        }
+        None => {
+            let mut page = vec![];
+            Man::new(command).render(&mut page)?;
+            io::stdout().write_all(&page)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,444 @@
+//! Command line interface to generate, use and verify AWS authentication payloads
+//!
+//! This binary requires the `cli` feature. Credentials are obtained with the default AWS
+//! credentials priority, and log messages are controlled with `RUST_LOG`. Failures exit with the
+//! stable codes of [`exit::EXIT_CODES`].
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use aws_auth_payload::client::{self, AwsAuthIamPayload, VAULT_SERVER_ID_HEADER};
+use aws_auth_payload::Error;
+use chrono::{DateTime, Utc};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use rusoto_core::Region;
+
+use crate::config::{Config, Defaults};
+use crate::consul::ConsulCommand;
+use crate::context::Context;
+use crate::decode::DecodeArgs;
+use crate::docker::DockerCredentialCommand;
+use crate::ecr::EcrCommand;
+use crate::eks::EksCommand;
+use crate::output::{Output, PresignedUrl, ProcessCredentials};
+use crate::rds::RdsCommand;
+use crate::serve::ServeArgs;
+use crate::sign::SignArgs;
+use crate::vault::VaultCommand;
+use crate::verify::VerifyArgs;
+
+mod cache;
+mod config;
+mod consul;
+mod context;
+mod decode;
+mod docker;
+mod ecr;
+mod eks;
+mod exit;
+mod generate;
+mod output;
+mod rds;
+mod serve;
+mod sign;
+mod vault;
+mod verify;
+mod watch;
+mod whoami;
+
+/// Generate, use and verify AWS authentication payloads
+#[derive(Debug, Parser)]
+#[command(name = "aws-auth-payload", version, after_long_help = exit::EXIT_CODES)]
+struct Cli {
+    #[command(flatten)]
+    context: Context,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print a signed POST payload as JSON
+    Payload(PayloadArgs),
+    /// Print a pre-signed `GetCallerIdentity` URL
+    Presign(PresignArgs),
+    /// Sign a request to any AWS service and print its headers or pre-signed URL
+    Sign(SignArgs),
+    /// Log in to HashiCorp Vault
+    #[command(subcommand)]
+    Vault(VaultCommand),
+    /// Log in to HashiCorp Consul
+    #[command(subcommand)]
+    Consul(ConsulCommand),
+    /// Generate tokens for Amazon EKS
+    #[command(subcommand)]
+    Eks(EksCommand),
+    /// Log in to Amazon ECR registries
+    #[command(subcommand)]
+    Ecr(EcrCommand),
+    /// Generate tokens for Amazon RDS
+    #[command(subcommand)]
+    Rds(RdsCommand),
+    /// Print the identity of the credentials, from AWS STS `GetCallerIdentity`
+    ///
+    /// The output also tells where the credentials were taken from, which is the first thing to
+    /// check when a login fails.
+    Whoami,
+    /// Print the credentials in the format of the `credential_process` of AWS CLI profiles
+    ///
+    /// Profiles can use this to get credentials from any configured identity or role. The profile
+    /// given with `--profile` must not be the one running this command.
+    CredentialProcess,
+    /// Hand out payloads, EKS tokens and Vault tokens over HTTP on a local port, until
+    /// interrupted or terminated
+    ///
+    /// `GET /payload?server_id=...`, `GET /eks/token?cluster=...` and `GET /vault/token` answer
+    /// JSON, so local tools and sidecars can fetch them like from the instance metadata service.
+    Serve(ServeArgs),
+    /// Print the completion script of a shell
+    Completions {
+        /// Shell to complete commands of
+        shell: Shell,
+    },
+    /// Print the man page, or write the man pages of all subcommands to a directory
+    Man {
+        /// Directory to write the man pages to
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// Verify a payload, pre-signed URL or EKS token against STS and print its signer
+    Verify(VerifyArgs),
+    /// Print the method, URL, body, headers and validity inside a payload, pre-signed URL or EKS
+    /// token, without verifying it
+    Decode(DecodeArgs),
+    /// Answer Docker as a credential helper for ECR registries
+    ///
+    /// Link the binary as `docker-credential-aws-auth-payload` and set `credsStore` or
+    /// `credHelpers` of `~/.docker/config.json` to `aws-auth-payload` to use it.
+    #[command(subcommand)]
+    DockerCredential(DockerCredentialCommand),
+}
+
+#[derive(Debug, Args)]
+struct PayloadArgs {
+    /// Target of the configuration file to take the other options from
+    target: Option<String>,
+    /// Sign the `X-Vault-AWS-IAM-Server-ID` header with this value
+    #[arg(long)]
+    server_id: Option<String>,
+    /// Sign an additional header, as `NAME=VALUE`
+    #[arg(long = "header", value_name = "NAME=VALUE", value_parser = parse_header)]
+    headers: Vec<(String, String)>,
+}
+
+#[derive(Debug, Args)]
+struct PresignArgs {
+    /// How long the URL is valid for, in seconds, 60 by default
+    #[arg(long, value_name = "SECONDS", value_parser = context::parse_seconds)]
+    expires: Option<Duration>,
+    /// Sign an additional header, as `NAME=VALUE`, such as `X-K8S-AWS-ID=my-cluster`
+    #[arg(long = "header", value_name = "NAME=VALUE", value_parser = parse_header)]
+    headers: Vec<(String, String)>,
+    /// Print the parts of the URL, like `decode` does, instead of the URL
+    #[arg(long)]
+    inspect: bool,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let cli = Cli::parse_from(docker::helper_args(std::env::args_os()));
+    let output = cli.context.output;
+    let verifying = matches!(cli.command, Command::Verify(_));
+    if let Err(e) = run(cli).await {
+        process::exit(exit::report(&e, output, verifying));
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Error> {
+    let config = Config::load(cli.context.config.as_deref())?;
+    let mut command = cli.command;
+    let mut context = cli.context;
+    if let Some(defaults) = command.apply_target(&config)? {
+        context = context.with_defaults(defaults)?;
+    }
+    let context = context.with_defaults(&config.defaults)?.with_endpoint()?;
+    let region = context.region.as_ref();
+    match command {
+        Command::Payload(args) => {
+            let credentials = context.credentials().await?;
+            let payload = signed_payload(&credentials, region, &args, context.sign_time);
+            output::print(&payload, context.output(Output::Json))?;
+        }
+        Command::Presign(args) => {
+            let credentials = context.credentials().await?;
+            let headers = args
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            let url = match context.sign_time {
+                Some(signed_at) => client::presigned_url_at(
+                    &credentials,
+                    region,
+                    headers,
+                    args.expires.as_ref(),
+                    signed_at,
+                ),
+                None => client::presigned_url(&credentials, region, headers, args.expires.as_ref()),
+            };
+            if args.inspect {
+                output::print(&decode::decode(&url)?, context.output(Output::Json))?;
+            } else {
+                output::print(&PresignedUrl { url }, context.output(Output::Raw))?;
+            }
+        }
+        Command::Sign(args) => sign::run(args, &context).await?,
+        Command::Vault(command) => vault::run(command, &context).await?,
+        Command::Consul(command) => consul::run(command, &context).await?,
+        Command::Eks(command) => eks::run(command, &context).await?,
+        Command::Ecr(command) => ecr::run(command, &context).await?,
+        Command::Rds(command) => rds::run(command, &context).await?,
+        Command::Whoami => whoami::run(&context).await?,
+        Command::CredentialProcess => {
+            let credentials = ProcessCredentials::from(&context.credentials().await?);
+            output::print(&credentials, context.output(Output::Json))?;
+        }
+        Command::Serve(args) => serve::run(args, &context).await?,
+        Command::Completions { shell } => generate::completions(shell, Cli::command())?,
+        Command::Man { dir } => generate::man_pages(Cli::command(), dir.as_deref())?,
+        Command::Verify(args) => verify::run(args, &context).await?,
+        Command::Decode(args) => decode::run(args, &context)?,
+        Command::DockerCredential(command) => docker::run(command, &context).await?,
+    }
+    Ok(())
+}
+
+impl Command {
+    /// Fill in the options that were not given from the target of the configuration named on the
+    /// command line, and return its defaults
+    fn apply_target<'a>(&mut self, config: &'a Config) -> Result<Option<&'a Defaults>, Error> {
+        match self {
+            Command::Payload(PayloadArgs {
+                target: Some(name),
+                server_id,
+                headers,
+            }) => {
+                let target = config::target(&config.payload, "payload", name)?;
+                *server_id = server_id.take().or_else(|| target.server_id.clone());
+                for (name, value) in &target.headers {
+                    if !headers
+                        .iter()
+                        .any(|(given, _)| given.eq_ignore_ascii_case(name))
+                    {
+                        headers.push((name.clone(), value.clone()));
+                    }
+                }
+                Ok(Some(&target.defaults))
+            }
+            Command::Vault(command) => command.apply_target(config),
+            Command::Eks(command) => command.apply_target(config),
+            _ => Ok(None),
+        }
+    }
+}
+
+fn signed_payload(
+    credentials: &rusoto_core::credential::AwsCredentials,
+    region: Option<&Region>,
+    args: &PayloadArgs,
+    signed_at: Option<DateTime<Utc>>,
+) -> AwsAuthIamPayload {
+    let mut headers: HashMap<&str, &str> = args
+        .headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    if let Some(ref server_id) = args.server_id {
+        headers.insert(VAULT_SERVER_ID_HEADER, server_id);
+    }
+    match signed_at {
+        Some(signed_at) => AwsAuthIamPayload::new_at(credentials, region, headers, signed_at),
+        None => AwsAuthIamPayload::new(credentials, region, headers),
+    }
+}
+
+pub fn parse_header(header: &str) -> Result<(String, String), String> {
+    match header.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("{:?} is not of the form NAME=VALUE", header)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments_are_parsed() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "aws-auth-payload",
+            "payload",
+            "--region",
+            "eu-west-1",
+            "--server-id",
+            "vault.example.com",
+            "--header",
+            "X-Custom=a=b",
+        ])
+        .unwrap();
+        assert_eq!(cli.context.region, Some(Region::EuWest1));
+        match cli.command {
+            Command::Payload(args) => {
+                assert_eq!(args.server_id.as_deref(), Some("vault.example.com"));
+                assert_eq!(
+                    args.headers,
+                    vec![("X-Custom".to_string(), "a=b".to_string())]
+                );
+            }
+            command => panic!("Unexpected command {:?}", command),
+        }
+        assert!(Cli::try_parse_from(["aws-auth-payload", "payload", "--header", "x"]).is_err());
+
+        let cli = Cli::try_parse_from([
+            "aws-auth-payload",
+            "eks",
+            "token",
+            "--cluster-name",
+            "prod",
+            "--role-arn",
+            "arn:aws:iam::123456789012:role/admin",
+            "--duration",
+            "900",
+        ])
+        .unwrap();
+        assert_eq!(cli.context.role.duration, Some(Duration::from_secs(900)));
+
+        let cli = Cli::try_parse_from([
+            "aws-auth-payload",
+            "presign",
+            "--region",
+            "eu-west-1",
+            "--endpoint-url",
+            "http://localhost:4566/",
+            "--expires",
+            "900",
+            "--header",
+            "X-K8S-AWS-ID=my-cluster",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::Presign(ref args) => {
+                assert_eq!(args.expires, Some(Duration::from_secs(900)));
+                assert_eq!(args.headers[0].1, "my-cluster");
+            }
+            ref command => panic!("Unexpected command {:?}", command),
+        }
+        assert_eq!(
+            cli.context.with_endpoint().unwrap().region,
+            Some(Region::Custom {
+                name: "eu-west-1".to_string(),
+                endpoint: "http://localhost:4566".to_string(),
+            })
+        );
+        assert!(
+            Cli::try_parse_from(["aws-auth-payload", "presign", "--external-id", "x"]).is_err()
+        );
+        assert!(Cli::try_parse_from([
+            "aws-auth-payload",
+            "eks",
+            "token",
+            "--refresh-margin",
+            "30"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn payloads_can_be_reproduced() {
+        let payload = || {
+            let cli = Cli::try_parse_from([
+                "aws-auth-payload",
+                "payload",
+                "--credentials",
+                "AKIDEXAMPLE:secret:token",
+                "--sign-time",
+                "2024-01-01T02:00:00+02:00",
+            ])
+            .unwrap();
+            let context = cli.context;
+            let credentials = context.static_credentials.clone().unwrap();
+            assert_eq!(credentials.token().as_deref(), Some("token"));
+            match cli.command {
+                Command::Payload(args) => {
+                    signed_payload(&credentials, None, &args, context.sign_time)
+                }
+                command => panic!("Unexpected command {:?}", command),
+            }
+        };
+        let first = payload();
+        assert_eq!(
+            serde_json::to_string(&first).unwrap(),
+            serde_json::to_string(&payload()).unwrap()
+        );
+        assert_eq!(
+            first.iam_request_headers["x-amz-date"],
+            ["20240101T000000Z"]
+        );
+        assert!(
+            Cli::try_parse_from(["aws-auth-payload", "payload", "--credentials", "key"]).is_err()
+        );
+    }
+
+    #[test]
+    fn targets_fill_in_the_arguments() {
+        let config = Config::parse(
+            r#"
+            [defaults]
+            region = "us-east-1"
+
+            [vault.prod]
+            addr = "https://vault.example.com:8200"
+            role = "my-role"
+            region = "eu-west-1"
+            "#,
+        )
+        .unwrap();
+
+        let cli = Cli::try_parse_from([
+            "aws-auth-payload",
+            "vault",
+            "login",
+            "prod",
+            "--role",
+            "admin",
+        ])
+        .unwrap();
+        let mut command = cli.command;
+        let defaults = command.apply_target(&config).unwrap().unwrap();
+        let context = cli
+            .context
+            .with_defaults(defaults)
+            .unwrap()
+            .with_defaults(&config.defaults)
+            .unwrap();
+        assert_eq!(context.region, Some(Region::EuWest1));
+        match command {
+            Command::Vault(VaultCommand::Login { addr, role, .. }) => {
+                assert_eq!(addr.as_deref(), Some("https://vault.example.com:8200"));
+                assert_eq!(role.as_deref(), Some("admin"));
+            }
+            command => panic!("Unexpected command {:?}", command),
+        }
+
+        let mut cli = Cli::try_parse_from(["aws-auth-payload", "eks", "token", "dev"]).unwrap();
+        assert!(cli.command.apply_target(&config).is_err());
+    }
+}
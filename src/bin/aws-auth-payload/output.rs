@@ -0,0 +1,216 @@
+//! Printing the results of subcommands in the format asked for with `--output`
+use aws_auth_payload::client::AwsAuthIamPayload;
+use aws_auth_payload::consul::AclToken;
+use aws_auth_payload::kubernetes::ExecCredential;
+use aws_auth_payload::sts::CallerIdentity;
+use aws_auth_payload::vault::Auth;
+use aws_auth_payload::Error;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use rusoto_core::credential::AwsCredentials;
+use serde::Serialize;
+
+/// Format of what subcommands print
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Output {
+    /// Pretty-printed JSON, for machines
+    Json,
+    /// YAML
+    Yaml,
+    /// Shell `export` statements, for `eval "$(...)"`
+    Env,
+    /// The bare token, URL or identity, for piping
+    Raw,
+}
+
+/// A result that can be printed in every [`Output`] format
+pub trait Render: Serialize {
+    /// The bare value, such as a token
+    fn raw(&self) -> String;
+
+    /// The environment variables to export
+    fn env(&self) -> Vec<(&'static str, String)>;
+}
+
+/// Print `value` as `output`
+pub fn print<T: Render>(value: &T, output: Output) -> Result<(), Error> {
+    println!("{}", render(value, output)?);
+    Ok(())
+}
+
+fn render<T: Render>(value: &T, output: Output) -> Result<String, Error> {
+    Ok(match output {
+        Output::Json => serde_json::to_string_pretty(value)?,
+        Output::Yaml => serde_yaml::to_string(value)
+            .map_err(|e| Error::GenericError(format!("Unable to serialize YAML: {}", e)))?
+            .trim_end()
+            .to_string(),
+        Output::Env => value
+            .env()
+            .iter()
+            .map(|(name, value)| export_statement(name, value))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Output::Raw => value.raw(),
+    })
+}
+
+/// A POSIX shell statement exporting `value` as `name`, quoted so it is never expanded
+fn export_statement(name: &str, value: &str) -> String {
+    format!("export {}='{}'", name, value.replace('\'', r"'\''"))
+}
+
+/// A pre-signed `GetCallerIdentity` URL
+#[derive(Debug, Serialize)]
+pub struct PresignedUrl {
+    pub url: String,
+}
+
+impl Render for PresignedUrl {
+    fn raw(&self) -> String {
+        self.url.clone()
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        vec![("AWS_PRESIGNED_URL", self.url.clone())]
+    }
+}
+
+/// Credentials as the `credential_process` of an AWS CLI profile prints them
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ProcessCredentials {
+    /// Always 1
+    pub version: u32,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_token: Option<String>,
+    /// Temporary credentials without an expiry are cached for as long as the processes using
+    /// them live
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiration: Option<DateTime<Utc>>,
+}
+
+impl From<&AwsCredentials> for ProcessCredentials {
+    fn from(credentials: &AwsCredentials) -> Self {
+        Self {
+            version: 1,
+            access_key_id: credentials.aws_access_key_id().to_string(),
+            secret_access_key: credentials.aws_secret_access_key().to_string(),
+            session_token: credentials.token().clone(),
+            expiration: *credentials.expires_at(),
+        }
+    }
+}
+
+impl Render for ProcessCredentials {
+    fn raw(&self) -> String {
+        serde_json::to_string(self).expect("credentials to be serializable")
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("AWS_ACCESS_KEY_ID", self.access_key_id.clone()),
+            ("AWS_SECRET_ACCESS_KEY", self.secret_access_key.clone()),
+        ];
+        if let Some(ref token) = self.session_token {
+            env.push(("AWS_SESSION_TOKEN", token.clone()));
+        }
+        env
+    }
+}
+
+impl Render for AwsAuthIamPayload {
+    fn raw(&self) -> String {
+        serde_json::to_string(self).expect("a payload to be serializable")
+    }
+
+    /// The parameters of a Vault login, with the headers as base64 encoded JSON like Vault
+    /// accepts them
+    fn env(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (
+                "IAM_HTTP_REQUEST_METHOD",
+                self.iam_http_request_method.clone(),
+            ),
+            ("IAM_REQUEST_URL", self.iam_request_url.clone()),
+            ("IAM_REQUEST_BODY", self.iam_request_body.clone()),
+            ("IAM_REQUEST_HEADERS", self.encoded_headers()),
+        ]
+    }
+}
+
+impl Render for Auth {
+    fn raw(&self) -> String {
+        self.client_token.clone()
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        vec![("VAULT_TOKEN", self.client_token.clone())]
+    }
+}
+
+impl Render for AclToken {
+    fn raw(&self) -> String {
+        self.secret_id.clone()
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        vec![("CONSUL_HTTP_TOKEN", self.secret_id.clone())]
+    }
+}
+
+impl Render for ExecCredential {
+    fn raw(&self) -> String {
+        self.status.token.clone()
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        vec![("EKS_TOKEN", self.status.token.clone())]
+    }
+}
+
+impl Render for CallerIdentity {
+    fn raw(&self) -> String {
+        self.arn.to_string()
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("AWS_CALLER_ARN", self.arn.to_string()),
+            ("AWS_ACCOUNT_ID", self.account.clone()),
+            ("AWS_USER_ID", self.user_id.clone()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_are_rendered_in_every_format() {
+        let url = PresignedUrl {
+            url: "https://sts.amazonaws.com/?Action=GetCallerIdentity&a='b'".to_string(),
+        };
+        assert_eq!(render(&url, Output::Raw).unwrap(), url.url);
+        assert_eq!(
+            render(&url, Output::Env).unwrap(),
+            r"export AWS_PRESIGNED_URL='https://sts.amazonaws.com/?Action=GetCallerIdentity&a='\''b'\'''"
+        );
+        assert_eq!(
+            render(&url, Output::Yaml).unwrap(),
+            "url: https://sts.amazonaws.com/?Action=GetCallerIdentity&a='b'"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&url, Output::Json).unwrap()).unwrap();
+        assert_eq!(json["url"], url.url.as_str());
+
+        let credentials = AwsCredentials::new("AKIDEXAMPLE", "secret", None, None);
+        assert_eq!(
+            render(&ProcessCredentials::from(&credentials), Output::Raw).unwrap(),
+            r#"{"Version":1,"AccessKeyId":"AKIDEXAMPLE","SecretAccessKey":"secret"}"#
+        );
+    }
+}
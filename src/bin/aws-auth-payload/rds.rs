@@ -0,0 +1,101 @@
+//! The `rds` subcommands
+use aws_auth_payload::client::{self, RDS_TOKEN_EXPIRES};
+use aws_auth_payload::{region, Error};
+use chrono::{DateTime, Duration, Utc};
+use clap::Subcommand;
+use serde::Serialize;
+
+use crate::context::Context;
+use crate::output::{self, Output, Render};
+
+#[derive(Debug, Subcommand)]
+pub enum RdsCommand {
+    /// Print a token to use as the password of a database user with IAM database authentication,
+    /// like `aws rds generate-db-auth-token`
+    ///
+    /// The token is signed for the region of the host name of RDS instances and clusters, or
+    /// else `--region`.
+    Token {
+        /// Host name of the database, its proxy or its cluster endpoint
+        #[arg(long)]
+        host: String,
+        /// Port the database listens on
+        #[arg(long)]
+        port: u16,
+        /// Database user to log in as
+        #[arg(long)]
+        user: String,
+    },
+}
+
+/// An IAM database authentication token
+#[derive(Debug, Serialize)]
+pub struct RdsToken {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Render for RdsToken {
+    fn raw(&self) -> String {
+        self.token.clone()
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        vec![("RDS_AUTH_TOKEN", self.token.clone())]
+    }
+}
+
+pub async fn run(command: RdsCommand, context: &Context) -> Result<(), Error> {
+    match command {
+        RdsCommand::Token { host, port, user } => {
+            let region = match host_region(&host) {
+                Some(region) => region::parse_region(region)?,
+                None => context.region.clone().ok_or_else(|| {
+                    Error::GenericError(format!("No --region given for {}", host))
+                })?,
+            };
+            let credentials = context.credentials().await?;
+            let token = RdsToken {
+                token: client::rds_auth_token(&credentials, &region, &host, port, &user),
+                expires_at: Utc::now()
+                    + Duration::from_std(RDS_TOKEN_EXPIRES).expect("expiry to be in range"),
+            };
+            output::print(&token, context.output(Output::Raw))?;
+        }
+    }
+    Ok(())
+}
+
+/// The region in the host name of an RDS endpoint such as
+/// `db.abcdefghijkl.eu-west-1.rds.amazonaws.com`
+fn host_region(host: &str) -> Option<&str> {
+    let labels: Vec<&str> = host.split('.').collect();
+    let rds = labels.iter().rposition(|label| *label == "rds")?;
+    match labels[rds + 1..] {
+        ["amazonaws", "com"] | ["amazonaws", "com", "cn"] if rds >= 3 => Some(labels[rds - 1]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_are_read_from_host_names() {
+        assert_eq!(
+            host_region("db.abcdefghijkl.eu-west-1.rds.amazonaws.com"),
+            Some("eu-west-1")
+        );
+        assert_eq!(
+            host_region("proxy.proxy-abcdefghijkl.cn-north-1.rds.amazonaws.com.cn"),
+            Some("cn-north-1")
+        );
+        assert_eq!(
+            host_region("rds.abcdefghijkl.eu-west-1.rds.amazonaws.com"),
+            Some("eu-west-1")
+        );
+        assert_eq!(host_region("db.example.com"), None);
+        assert_eq!(host_region("rds.amazonaws.com"), None);
+    }
+}
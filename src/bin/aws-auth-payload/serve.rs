@@ -0,0 +1,362 @@
+//! The `serve` subcommand, handing out tokens over HTTP like the instance metadata service
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use aws_auth_payload::client::{AwsAuthIamPayload, VAULT_SERVER_ID_HEADER};
+use aws_auth_payload::kubernetes::{ClusterRequest, ExecCredential};
+use aws_auth_payload::sink::DEFAULT_REFRESH_MARGIN;
+use aws_auth_payload::vault::{Auth, Client, LoginOptions, MountPath};
+use aws_auth_payload::Error;
+use chrono::Utc;
+use clap::Args;
+use hyper::http::uri::Authority;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use log::{info, warn};
+use rusoto_core::credential::AwsCredentials;
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::context::Context;
+use crate::{vault, watch};
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to listen on, which other hosts must not be able to reach
+    #[arg(long, default_value = "127.0.0.1:9911")]
+    listen: SocketAddr,
+    /// Address of the Vault server to log in to for `/vault/token`
+    #[arg(long)]
+    vault_addr: Option<String>,
+    /// Path the AWS auth method is mounted at
+    #[arg(long, requires = "vault_addr", default_value = "aws", value_parser = vault::parse_mount)]
+    vault_mount: MountPath,
+    /// Vault role to log in as, instead of the role named after the IAM principal
+    #[arg(long, requires = "vault_addr")]
+    vault_role: Option<String>,
+    /// Sign the `X-Vault-AWS-IAM-Server-ID` header of Vault logins with this value
+    #[arg(long, requires = "vault_addr")]
+    vault_server_id: Option<String>,
+    /// Vault Enterprise namespace to log in to
+    #[arg(long, requires = "vault_addr")]
+    vault_namespace: Option<String>,
+}
+
+/// What a request asks for
+#[derive(Debug, Eq, PartialEq)]
+enum Route {
+    /// `GET /payload?server_id=...`
+    Payload { server_id: Option<String> },
+    /// `GET /eks/token?cluster=...`
+    EksToken { cluster: String },
+    /// `GET /vault/token`
+    VaultToken,
+}
+
+struct VaultLogin {
+    client: Client,
+    options: LoginOptions,
+    server_id: Option<String>,
+    /// The token of the last login, and when it expires
+    token: Mutex<Option<(Auth, Option<Instant>)>>,
+}
+
+struct Server {
+    listen: SocketAddr,
+    context: Context,
+    credentials: Mutex<Option<AwsCredentials>>,
+    vault: Option<VaultLogin>,
+}
+
+/// Hand out payloads, EKS tokens and Vault tokens until the process is interrupted or terminated
+pub async fn run(args: ServeArgs, context: &Context) -> Result<(), Error> {
+    let vault = match args.vault_addr {
+        Some(ref addr) => {
+            let mut client = Client::new(addr)?;
+            if let Some(ref namespace) = args.vault_namespace {
+                client = client.with_namespace(namespace);
+            }
+            Some(VaultLogin {
+                client,
+                options: LoginOptions::new(args.vault_mount, args.vault_role.as_deref()),
+                server_id: args.vault_server_id,
+                token: Mutex::new(None),
+            })
+        }
+        None => None,
+    };
+    let server = Arc::new(Server {
+        listen: args.listen,
+        context: context.clone(),
+        credentials: Mutex::new(None),
+        vault,
+    });
+    // Resolve the credentials before serving, so that MFA codes are prompted for up front
+    server.credentials().await?;
+
+    let make_service = make_service_fn(move |_| {
+        let server = Arc::clone(&server);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let server = Arc::clone(&server);
+                async move { Ok::<_, Infallible>(server.respond(request).await) }
+            }))
+        }
+    });
+    let http = hyper::Server::try_bind(&args.listen)?.serve(make_service);
+    info!("Serving tokens on http://{}", http.local_addr());
+    http.with_graceful_shutdown(async {
+        if let Err(e) = watch::shutdown_signal().await {
+            warn!("Unable to wait for signals: {}", e);
+        }
+    })
+    .await?;
+    Ok(())
+}
+
+impl Server {
+    async fn respond(&self, request: Request<Body>) -> Response<Body> {
+        let result = match route(&request, self.listen) {
+            Ok(route) => self.handle(route).await.map_err(|e| {
+                warn!("Unable to serve {}: {}", request.uri().path(), e);
+                (StatusCode::BAD_GATEWAY, e.to_string())
+            }),
+            Err(rejection) => Err(rejection),
+        };
+        let (status, body) = match result {
+            Ok(body) => (StatusCode::OK, body),
+            Err((status, error)) => (status, serde_json::json!({ "error": error }).to_string()),
+        };
+        Response::builder()
+            .status(status)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .expect("a response with valid headers")
+    }
+
+    async fn handle(&self, route: Route) -> Result<String, Error> {
+        let credentials = self.credentials().await?;
+        let region = self.context.region.as_ref();
+        match route {
+            Route::Payload { server_id } => {
+                let mut headers = HashMap::new();
+                if let Some(ref server_id) = server_id {
+                    headers.insert(VAULT_SERVER_ID_HEADER, server_id.as_str());
+                }
+                to_json(&AwsAuthIamPayload::new(&credentials, region, headers))
+            }
+            Route::EksToken { cluster } => {
+                let mut cluster: aws_auth_payload::kubernetes::EksCluster = cluster.parse()?;
+                if cluster.region.is_none() {
+                    cluster.region = region.cloned();
+                }
+                let credential =
+                    ExecCredential::for_clusters(&credentials, &[ClusterRequest::new(cluster)])
+                        .await
+                        .remove(0)?;
+                to_json(&credential)
+            }
+            Route::VaultToken => {
+                let vault = self.vault.as_ref().ok_or_else(|| {
+                    Error::GenericError("No --vault-addr to log in to".to_string())
+                })?;
+                let mut token = vault.token.lock().await;
+                let fresh = |expires_at: &Option<Instant>| {
+                    expires_at.is_none_or(|at| at > Instant::now() + DEFAULT_REFRESH_MARGIN)
+                };
+                match *token {
+                    Some((ref auth, ref expires_at)) if fresh(expires_at) => to_json(auth),
+                    _ => {
+                        let auth = vault::login(
+                            &vault.client,
+                            &vault.options,
+                            vault.server_id.as_deref(),
+                            &credentials,
+                            region,
+                        )
+                        .await?;
+                        let lease = auth.lease_duration();
+                        let expires_at = Some(lease)
+                            .filter(|lease| *lease > Duration::default())
+                            .map(|lease| Instant::now() + lease);
+                        let json = to_json(&auth);
+                        *token = Some((auth, expires_at));
+                        json
+                    }
+                }
+            }
+        }
+    }
+
+    /// The credentials of the context, resolved again shortly before they expire
+    async fn credentials(&self) -> Result<AwsCredentials, Error> {
+        let margin = chrono::Duration::from_std(DEFAULT_REFRESH_MARGIN).expect("margin in range");
+        let fresh = |credentials: &AwsCredentials| {
+            credentials
+                .expires_at()
+                .is_none_or(|at| at > Utc::now() + margin)
+        };
+        let mut cached = self.credentials.lock().await;
+        match *cached {
+            Some(ref credentials) if fresh(credentials) => Ok(credentials.clone()),
+            _ => {
+                let credentials = self.context.credentials().await?;
+                *cached = Some(credentials.clone());
+                Ok(credentials)
+            }
+        }
+    }
+}
+
+/// Find what `request` to the server listening on `listen` asks for
+///
+/// Requests from browsers, which carry an `Origin`, are refused so that web pages cannot fetch
+/// tokens from the server. So are requests for any host but a loopback address or `listen`,
+/// which is what web pages send after rebinding their own name to a loopback address, even when
+/// they do not send an `Origin`.
+fn route<B>(request: &Request<B>, listen: SocketAddr) -> Result<Route, (StatusCode, String)> {
+    if request.headers().contains_key(hyper::header::ORIGIN) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Requests from browsers are refused".to_string(),
+        ));
+    }
+    let host = request
+        .headers()
+        .get(hyper::header::HOST)
+        .and_then(|host| host.to_str().ok());
+    if !host.is_some_and(|host| is_local_host(host, listen)) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!(
+                "Requests for the host {:?} are refused",
+                host.unwrap_or_default()
+            ),
+        ));
+    }
+    if request.method() != Method::GET {
+        return Err((
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Only GET requests are served".to_string(),
+        ));
+    }
+    let mut query: HashMap<String, String> =
+        serde_urlencoded::from_str(request.uri().query().unwrap_or_default())
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Malformed query".to_string()))?;
+
+    match request.uri().path() {
+        "/payload" => Ok(Route::Payload {
+            server_id: query.remove("server_id"),
+        }),
+        "/eks/token" => match query.remove("cluster") {
+            Some(cluster) => Ok(Route::EksToken { cluster }),
+            None => Err((
+                StatusCode::BAD_REQUEST,
+                "Missing the cluster query parameter".to_string(),
+            )),
+        },
+        "/vault/token" => Ok(Route::VaultToken),
+        path => Err((
+            StatusCode::NOT_FOUND,
+            format!("Nothing is served at {}", path),
+        )),
+    }
+}
+
+/// Whether the `Host` header `host` names a loopback address, or is the address the server
+/// listens on
+fn is_local_host(host: &str, listen: SocketAddr) -> bool {
+    if host == listen.to_string() {
+        return true;
+    }
+    let authority: Authority = match host.parse() {
+        Ok(authority) => authority,
+        Err(_) => return false,
+    };
+    let name = authority.host();
+    let name = name
+        .strip_prefix('[')
+        .and_then(|name| name.strip_suffix(']'))
+        .unwrap_or(name);
+    name.eq_ignore_ascii_case("localhost")
+        || name
+            .parse::<IpAddr>()
+            .map(|ip| ip.is_loopback())
+            .unwrap_or(false)
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, Error> {
+    Ok(serde_json::to_string_pretty(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTEN: &str = "127.0.0.1:9911";
+
+    fn get(uri: &str) -> Request<()> {
+        Request::get(uri).header("Host", LISTEN).body(()).unwrap()
+    }
+
+    fn route<B>(request: &Request<B>) -> Result<Route, (StatusCode, String)> {
+        super::route(request, LISTEN.parse().unwrap())
+    }
+
+    #[test]
+    fn requests_are_routed() {
+        assert_eq!(
+            route(&get("/payload?server_id=vault.example.com")),
+            Ok(Route::Payload {
+                server_id: Some("vault.example.com".to_string())
+            })
+        );
+        assert_eq!(
+            route(&get("/eks/token?cluster=prod")),
+            Ok(Route::EksToken {
+                cluster: "prod".to_string()
+            })
+        );
+        assert_eq!(route(&get("/vault/token")), Ok(Route::VaultToken));
+        assert_eq!(
+            route(&get("/eks/token")).unwrap_err().0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(route(&get("/latest")).unwrap_err().0, StatusCode::NOT_FOUND);
+
+        let from_browser = Request::get("/payload")
+            .header("Host", LISTEN)
+            .header("Origin", "https://example.com")
+            .body(())
+            .unwrap();
+        assert_eq!(route(&from_browser).unwrap_err().0, StatusCode::FORBIDDEN);
+        for host in [
+            "attacker.example",
+            "attacker.example:9911",
+            "127.0.0.1.nip.io",
+        ] {
+            let rebound = Request::get("/payload")
+                .header("Host", host)
+                .body(())
+                .unwrap();
+            assert_eq!(route(&rebound).unwrap_err().0, StatusCode::FORBIDDEN);
+        }
+        let without_host = Request::get("/payload").body(()).unwrap();
+        assert_eq!(route(&without_host).unwrap_err().0, StatusCode::FORBIDDEN);
+        for host in ["localhost:9911", "[::1]:9911", "127.0.0.2"] {
+            let local = Request::get("/vault/token")
+                .header("Host", host)
+                .body(())
+                .unwrap();
+            assert_eq!(route(&local), Ok(Route::VaultToken));
+        }
+        let post = Request::post("/payload")
+            .header("Host", LISTEN)
+            .body(())
+            .unwrap();
+        assert_eq!(route(&post).unwrap_err().0, StatusCode::METHOD_NOT_ALLOWED);
+    }
+}
@@ -0,0 +1,173 @@
+//! The `sign` subcommand
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::time::Duration;
+
+use aws_auth_payload::client;
+use aws_auth_payload::Error;
+use clap::Args;
+use serde::Serialize;
+
+use crate::context::{self, Context};
+use crate::output::{self, Output, PresignedUrl, Render};
+
+#[derive(Debug, Args)]
+pub struct SignArgs {
+    /// Signing name of the service, such as `es` or `execute-api`
+    #[arg(long)]
+    service: String,
+    /// HTTP method of the request
+    #[arg(long, default_value = "GET")]
+    method: String,
+    /// Absolute URL of the request
+    #[arg(long)]
+    url: String,
+    /// Body of the request, or `@FILE` to read it from a file and `@-` from standard input
+    #[arg(long, conflicts_with = "presign")]
+    body: Option<String>,
+    /// Sign an additional header, as `NAME=VALUE`
+    #[arg(long = "header", value_name = "NAME=VALUE", value_parser = crate::parse_header)]
+    headers: Vec<(String, String)>,
+    /// Print a pre-signed URL instead of the headers, which only GET requests can be
+    #[arg(long)]
+    presign: bool,
+    /// How long the pre-signed URL is valid for, in seconds
+    #[arg(long, requires = "presign", value_name = "SECONDS", default_value = "900", value_parser = context::parse_seconds)]
+    expires: Duration,
+}
+
+/// The headers of a signed request
+#[derive(Debug, Serialize)]
+pub struct SignedHeaders {
+    pub method: String,
+    pub url: String,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Render for SignedHeaders {
+    /// The headers as `NAME: VALUE` lines, which `curl -H @FILE` reads
+    fn raw(&self) -> String {
+        self.headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The headers of the signature
+    fn env(&self) -> Vec<(&'static str, String)> {
+        [
+            ("authorization", "AWS_SIGV4_AUTHORIZATION"),
+            ("x-amz-content-sha256", "AWS_SIGV4_CONTENT_SHA256"),
+            ("x-amz-date", "AWS_SIGV4_DATE"),
+            ("x-amz-security-token", "AWS_SIGV4_SECURITY_TOKEN"),
+        ]
+        .iter()
+        .filter_map(|(header, name)| Some((*name, self.headers.get(*header)?.clone())))
+        .collect()
+    }
+}
+
+/// Sign the request, and print its headers or its pre-signed URL
+pub async fn run(args: SignArgs, context: &Context) -> Result<(), Error> {
+    let region = context.region.clone().unwrap_or_default();
+    let credentials = context.credentials().await?;
+
+    if args.presign {
+        if !args.method.eq_ignore_ascii_case("GET") {
+            return Err(Error::GenericError(
+                "Only GET requests can be pre-signed".to_string(),
+            ));
+        }
+        let uri = args.url.parse().map_err(hyper::http::Error::from)?;
+        let url = client::presign_uri(&credentials, &args.service, &region, &uri, &args.expires)?;
+        return output::print(&PresignedUrl { url }, context.output(Output::Raw));
+    }
+
+    let mut request = request(&args)?;
+    client::sign_request(&credentials, &args.service, &region, &mut request)?;
+    let headers = request
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = value.to_str().unwrap_or_default().to_string();
+            (name.to_string(), value)
+        })
+        .collect();
+    let signed = SignedHeaders {
+        method: args.method,
+        url: args.url,
+        headers,
+    };
+    output::print(&signed, context.output(Output::Raw))
+}
+
+fn request(args: &SignArgs) -> Result<hyper::Request<Vec<u8>>, Error> {
+    let body = match args.body.as_deref() {
+        Some("@-") => {
+            let mut body = Vec::new();
+            io::stdin().read_to_end(&mut body)?;
+            body
+        }
+        Some(body) => match body.strip_prefix('@') {
+            Some(file) => fs::read(file)?,
+            None => body.as_bytes().to_vec(),
+        },
+        None => Vec::new(),
+    };
+    let mut request = hyper::Request::builder()
+        .method(args.method.to_uppercase().as_str())
+        .uri(&args.url);
+    for (name, value) in &args.headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    Ok(request.body(body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: SignArgs,
+    }
+
+    #[test]
+    fn requests_are_built_from_the_arguments() {
+        let cli = Cli::try_parse_from([
+            "sign",
+            "--service",
+            "es",
+            "--method",
+            "post",
+            "--url",
+            "https://search.example.com/_search?q=1",
+            "--body",
+            r#"{"query": {}}"#,
+            "--header",
+            "Content-Type=application/json",
+        ])
+        .unwrap();
+        let request = request(&cli.args).unwrap();
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.uri().query(), Some("q=1"));
+        assert_eq!(request.headers()["content-type"], "application/json");
+        assert_eq!(request.body(), br#"{"query": {}}"#);
+
+        assert!(Cli::try_parse_from([
+            "sign",
+            "--service",
+            "es",
+            "--url",
+            "https://search.example.com/",
+            "--expires",
+            "60",
+        ])
+        .is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! The `vault` subcommands
+use std::collections::HashMap;
+
+use aws_auth_payload::client::{AwsAuthIamPayload, VAULT_SERVER_ID_HEADER};
+use aws_auth_payload::vault::{Auth, Client, LoginOptions, MountPath};
+use aws_auth_payload::Error;
+use chrono::Utc;
+use clap::Subcommand;
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::Region;
+
+use crate::cache::CacheArgs;
+use crate::config::{self, Config, Defaults};
+use crate::context::Context;
+use crate::output::{self, Output};
+use crate::watch::{self, WatchArgs};
+
+#[derive(Debug, Subcommand)]
+pub enum VaultCommand {
+    /// Log in to the AWS auth method and print the Vault token
+    Login {
+        /// Target of the configuration file to take the other options from
+        target: Option<String>,
+        /// Address of the Vault server
+        #[arg(long, env = "VAULT_ADDR")]
+        addr: Option<String>,
+        /// Path the AWS auth method is mounted at, `aws` by default
+        #[arg(long, value_parser = parse_mount)]
+        mount: Option<MountPath>,
+        /// Vault role to log in as, instead of the role named after the IAM principal
+        #[arg(long)]
+        role: Option<String>,
+        /// Sign the `X-Vault-AWS-IAM-Server-ID` header with this value, as the auth method may
+        /// require
+        #[arg(long)]
+        server_id: Option<String>,
+        /// Vault Enterprise namespace to log in to
+        #[arg(long, env = "VAULT_NAMESPACE")]
+        namespace: Option<String>,
+        /// Print a shell `export VAULT_TOKEN=...` statement instead of the bare token, like
+        /// `--output env`
+        #[arg(long, conflicts_with = "output")]
+        export: bool,
+        #[command(flatten)]
+        watch: WatchArgs,
+        #[command(flatten)]
+        cache: CacheArgs,
+    },
+}
+
+impl VaultCommand {
+    /// Fill in the options that were not given from the target, and return its defaults
+    pub fn apply_target<'a>(&mut self, config: &'a Config) -> Result<Option<&'a Defaults>, Error> {
+        match self {
+            VaultCommand::Login {
+                target: Some(name),
+                addr,
+                mount,
+                role,
+                server_id,
+                namespace,
+                ..
+            } => {
+                let target = config::target(&config.vault, "vault", name)?;
+                *addr = addr.take().or_else(|| target.addr.clone());
+                if mount.is_none() {
+                    *mount = target.mount.as_deref().map(MountPath::new).transpose()?;
+                }
+                *role = role.take().or_else(|| target.role.clone());
+                *server_id = server_id.take().or_else(|| target.server_id.clone());
+                *namespace = namespace.take().or_else(|| target.namespace.clone());
+                Ok(Some(&target.defaults))
+            }
+            VaultCommand::Login { target: None, .. } => Ok(None),
+        }
+    }
+}
+
+pub async fn run(command: VaultCommand, context: &Context) -> Result<(), Error> {
+    match command {
+        VaultCommand::Login {
+            target: _,
+            addr,
+            mount,
+            role,
+            server_id,
+            namespace,
+            export,
+            watch,
+            cache,
+        } => {
+            let addr = addr.ok_or_else(|| {
+                Error::GenericError("No --addr, VAULT_ADDR or addr of a target".to_string())
+            })?;
+            let mount = match mount {
+                Some(mount) => mount,
+                None => MountPath::new("aws")?,
+            };
+            let mut client = Client::new(&addr)?;
+            if let Some(ref namespace) = namespace {
+                client = client.with_namespace(namespace);
+            }
+            let options = LoginOptions::new(mount, role.as_deref());
+
+            match watch.watch {
+                // Log in again ahead of the expiry of every token
+                Some(file) => {
+                    let context = context.clone();
+                    watch::keep_fresh(file, watch.refresh_margin, move || {
+                        let (client, options, server_id, context) = (
+                            client.clone(),
+                            options.clone(),
+                            server_id.clone(),
+                            context.clone(),
+                        );
+                        async move {
+                            let credentials = context.credentials().await?;
+                            let region = context.region.as_ref();
+                            let auth = login(
+                                &client,
+                                &options,
+                                server_id.as_deref(),
+                                &credentials,
+                                region,
+                            )
+                            .await?;
+                            let ttl = Some(auth.lease_duration()).filter(|ttl| !ttl.is_zero());
+                            Ok((auth.client_token, ttl))
+                        }
+                    })
+                    .await?;
+                }
+                None => {
+                    let mut key = vec![
+                        "vault".to_string(),
+                        addr.clone(),
+                        format!("{:?}", options),
+                        format!("{:?}", server_id),
+                        format!("{:?}", namespace),
+                    ];
+                    key.extend(context.identity_key());
+                    let auth: Auth = cache
+                        .get_or_insert(&key, || async {
+                            let credentials = context.credentials().await?;
+                            let region = context.region.as_ref();
+                            let auth = login(
+                                &client,
+                                &options,
+                                server_id.as_deref(),
+                                &credentials,
+                                region,
+                            )
+                            .await?;
+                            // Tokens without a lease do not expire, but may still be revoked
+                            let expires_at = Some(auth.lease_duration())
+                                .filter(|ttl| !ttl.is_zero())
+                                .and_then(|ttl| chrono::Duration::from_std(ttl).ok())
+                                .map(|ttl| Utc::now() + ttl);
+                            Ok((auth, expires_at))
+                        })
+                        .await?;
+                    let output = if export { Output::Env } else { Output::Raw };
+                    output::print(&auth, context.output(output))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Log in with a payload signed with `credentials`, for `server_id` if given
+pub async fn login(
+    client: &Client,
+    options: &LoginOptions,
+    server_id: Option<&str>,
+    credentials: &AwsCredentials,
+    region: Option<&Region>,
+) -> Result<Auth, Error> {
+    let mut headers = HashMap::new();
+    if let Some(server_id) = server_id {
+        headers.insert(VAULT_SERVER_ID_HEADER, server_id);
+    }
+    let payload = AwsAuthIamPayload::new(credentials, region, headers);
+    client.login(options, &payload).await
+}
+
+pub fn parse_mount(path: &str) -> Result<MountPath, String> {
+    MountPath::new(path).map_err(|e| e.to_string())
+}
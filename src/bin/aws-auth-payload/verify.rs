@@ -0,0 +1,85 @@
+//! The `verify` subcommand
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use aws_auth_payload::verify::{PayloadVerifier, SignedIdentityRequest};
+use aws_auth_payload::Error;
+use clap::Args;
+
+use crate::context::Context;
+use crate::output::{self, Output};
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// File holding the JSON payload, pre-signed URL or EKS token, or `-` for standard input
+    #[arg(long, default_value = "-")]
+    file: PathBuf,
+    /// Require the request to sign a header with a value, as `NAME=VALUE`
+    #[arg(long = "require-header", value_name = "NAME=VALUE", value_parser = crate::parse_header)]
+    required_headers: Vec<(String, String)>,
+}
+
+/// Verify the signed request like a server would, and print the identity of its signer
+pub async fn run(args: VerifyArgs, context: &Context) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let request = signed_request(input.trim())?;
+
+    let verifier = args
+        .required_headers
+        .iter()
+        .fold(PayloadVerifier::new(), |verifier, (name, value)| {
+            verifier.with_required_header(name, value)
+        });
+    let identity = verifier.verify_request(&request).await?;
+    output::print(&identity, context.output(Output::Json))
+}
+
+/// The contents of `file`, or standard input for `-`
+pub fn read_input(file: &Path) -> Result<String, Error> {
+    if file.as_os_str() == "-" {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        Ok(input)
+    } else {
+        Ok(fs::read_to_string(file)?)
+    }
+}
+
+/// Tell a JSON payload, a pre-signed URL and an EKS token apart
+pub fn signed_request(input: &str) -> Result<SignedIdentityRequest, Error> {
+    if input.starts_with('{') {
+        Ok(SignedIdentityRequest::Payload(serde_json::from_str(input)?))
+    } else if input.starts_with("https://") || input.starts_with("http://") {
+        Ok(SignedIdentityRequest::PresignedUrl(input.to_string()))
+    } else {
+        SignedIdentityRequest::from_eks_token(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inputs_are_recognized() {
+        let url = "https://sts.amazonaws.com/?Action=GetCallerIdentity&Version=2011-06-15";
+        assert!(matches!(
+            signed_request(url),
+            Ok(SignedIdentityRequest::PresignedUrl(ref u)) if u == url
+        ));
+        let token = format!(
+            "k8s-aws-v1.{}",
+            base64::encode_config(url, base64::URL_SAFE_NO_PAD)
+        );
+        assert!(matches!(
+            signed_request(&token),
+            Ok(SignedIdentityRequest::PresignedUrl(ref u)) if u == url
+        ));
+        assert!(matches!(
+            signed_request(r#"{"iam_http_request_method": "POST"}"#),
+            Err(Error::JsonError(_))
+        ));
+        assert!(signed_request("garbage").is_err());
+    }
+}
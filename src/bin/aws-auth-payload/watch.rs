@@ -0,0 +1,55 @@
+//! Keeping a token file fresh until the process is told to stop, for `--watch`
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use aws_auth_payload::sink::{FileSink, TokenFileWriter};
+use aws_auth_payload::Error;
+use clap::Args;
+use log::info;
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// Instead of printing the token, write it to this file and keep replacing it before it
+    /// expires until interrupted or terminated
+    #[arg(long, value_name = "FILE")]
+    pub watch: Option<PathBuf>,
+    /// How long before the token expires it is replaced, in seconds
+    #[arg(long, requires = "watch", value_name = "SECONDS", default_value = "60", value_parser = crate::context::parse_seconds)]
+    pub refresh_margin: Duration,
+}
+
+/// Keep the token `generate` returns, with how long it is valid for, fresh in `file` until the
+/// process receives `SIGINT` or `SIGTERM`
+pub async fn keep_fresh<F, Fut>(file: PathBuf, margin: Duration, generate: F) -> Result<(), Error>
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(String, Option<Duration>), Error>> + Send,
+{
+    let sink = FileSink::new(file);
+    info!("Keeping {} fresh", sink.path().display());
+    let writer = TokenFileWriter::start(sink, margin, generate).await?;
+    shutdown_signal().await?;
+    info!("Shutting down");
+    writer.shutdown().await;
+    Ok(())
+}
+
+/// Wait for `SIGINT` or `SIGTERM`, or Ctrl-C where there are no signals
+#[cfg(unix)]
+pub async fn shutdown_signal() -> Result<(), Error> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        interrupted = tokio::signal::ctrl_c() => interrupted?,
+        _ = terminate.recv() => {}
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn shutdown_signal() -> Result<(), Error> {
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
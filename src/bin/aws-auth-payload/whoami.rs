@@ -0,0 +1,82 @@
+//! The `whoami` subcommand
+use aws_auth_payload::arn::Arn;
+use aws_auth_payload::{sts, Error};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::context::Context;
+use crate::output::{self, Output, Render};
+
+/// The identity of the credentials of the command line, and where they were taken from
+#[derive(Debug, Serialize)]
+pub struct Whoami {
+    pub arn: Arn,
+    pub account: String,
+    pub user_id: String,
+    /// `command_line`, `profile`, `environment`, `credentials_file`, `container` or
+    /// `instance_metadata`
+    pub credential_source: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// When temporary credentials expire
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Render for Whoami {
+    fn raw(&self) -> String {
+        self.arn.to_string()
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("AWS_CALLER_ARN", self.arn.to_string()),
+            ("AWS_ACCOUNT_ID", self.account.clone()),
+            ("AWS_USER_ID", self.user_id.clone()),
+            ("AWS_CREDENTIAL_SOURCE", self.credential_source.to_string()),
+        ];
+        if let Some(ref profile) = self.profile {
+            env.push(("AWS_CREDENTIAL_PROFILE", profile.clone()));
+        }
+        env
+    }
+}
+
+/// Call `GetCallerIdentity` with the credentials, and print who they belong to
+pub async fn run(context: &Context) -> Result<(), Error> {
+    let (credentials, source) = context.credentials_with_source().await?;
+    let identity = sts::whoami(&credentials, context.region.as_ref()).await?;
+    let whoami = Whoami {
+        arn: identity.arn,
+        account: identity.account,
+        user_id: identity.user_id,
+        credential_source: source.name(),
+        profile: source.profile().map(str::to_string),
+        expires_at: *credentials.expires_at(),
+    };
+    output::print(&whoami, context.output(Output::Json))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::CredentialSource;
+
+    #[test]
+    fn identities_are_rendered() {
+        let source = CredentialSource::CredentialsFile("default".to_string());
+        let whoami = Whoami {
+            arn: "arn:aws:iam::123456789012:user/alice".parse().unwrap(),
+            account: "123456789012".to_string(),
+            user_id: "AIDAEXAMPLE".to_string(),
+            credential_source: source.name(),
+            profile: source.profile().map(str::to_string),
+            expires_at: None,
+        };
+        assert_eq!(whoami.raw(), "arn:aws:iam::123456789012:user/alice");
+        let json = serde_json::to_value(&whoami).unwrap();
+        assert_eq!(json["credential_source"], "credentials_file");
+        assert_eq!(json["profile"], "default");
+        assert!(json.get("expires_at").is_none());
+    }
+}
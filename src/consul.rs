@@ -0,0 +1,446 @@
+//! Bearer tokens for the [AWS IAM auth method](https://developer.hashicorp.com/consul/docs/security/acl/auth-methods/aws-iam)
+//! of Consul 1.12 and later
+//!
+//! Logging in with the token requires the `consul-client` feature.
+use std::collections::HashMap;
+use std::fmt;
+
+use log::{debug, info};
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::param::{Params, ServiceParams};
+use rusoto_core::request::{DispatchSignedRequest, HttpClient};
+use rusoto_core::signature::{SignedRequest, SignedRequestPayload};
+use rusoto_core::Region;
+use serde::{Deserialize, Serialize};
+
+use crate::arn::Principal;
+use crate::client::{signed_headers, AwsAuthIamPayload};
+use crate::region::Partition;
+use crate::{sts, Error};
+
+/// Default name of the header Consul expects the server ID in
+pub const SERVER_ID_HEADER: &str = "X-Consul-IAM-ServerID";
+/// Default name of the header carrying the method of the signed `iam:GetRole` or `iam:GetUser`
+/// request
+pub const GET_ENTITY_METHOD_HEADER: &str = "X-Consul-IAM-GetEntity-Method";
+/// Default name of the header carrying the URL of the signed entity request
+pub const GET_ENTITY_URL_HEADER: &str = "X-Consul-IAM-GetEntity-URL";
+/// Default name of the header carrying the JSON encoded headers of the signed entity request
+pub const GET_ENTITY_HEADERS_HEADER: &str = "X-Consul-IAM-GetEntity-Headers";
+/// Default name of the header carrying the body of the signed entity request
+pub const GET_ENTITY_BODY_HEADER: &str = "X-Consul-IAM-GetEntity-Body";
+
+/// How to sign a Consul bearer token
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConsulTokenOptions {
+    /// Region of the STS endpoint to sign for. The global endpoint is used if not set
+    pub region: Option<Region>,
+    /// Value of the [`SERVER_ID_HEADER`] the auth method requires, if any
+    pub server_id: Option<String>,
+    /// Also sign an `iam:GetRole` or `iam:GetUser` request for the caller
+    ///
+    /// This is required by auth methods with `EnableIAMEntityDetails`, which bind tokens to the
+    /// path or tags of the IAM entity. The caller is looked up with `GetCallerIdentity` first.
+    pub include_entity: bool,
+}
+
+/// The signed requests Consul verifies an AWS IAM login with
+///
+/// Use [`bearer_token`](Self::bearer_token) to get the token for `/v1/acl/login` or
+/// `consul login -type aws-iam`. The `Debug` output redacts the signed headers.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct ConsulLoginData {
+    /// HTTP method of the signed request
+    pub iam_http_request_method: String,
+    /// Base64-encoded URL of the signed request
+    pub iam_request_url: String,
+    /// Base64-encoded JSON object of the headers of the signed request
+    pub iam_request_headers: String,
+    /// Base64-encoded body of the signed request
+    pub iam_request_body: String,
+}
+
+impl fmt::Debug for ConsulLoginData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConsulLoginData")
+            .field("iam_http_request_method", &self.iam_http_request_method)
+            .field("iam_request_url", &self.iam_request_url)
+            .field("iam_request_headers", &"<redacted>")
+            .field("iam_request_body", &self.iam_request_body)
+            .finish()
+    }
+}
+
+impl ConsulLoginData {
+    /// Sign a `GetCallerIdentity` request, and optionally an entity request, for Consul
+    pub async fn new(
+        credentials: &AwsCredentials,
+        options: &ConsulTokenOptions,
+    ) -> Result<Self, Error> {
+        let dispatcher = HttpClient::new()?;
+        Self::new_with_dispatcher(&dispatcher, credentials, options).await
+    }
+
+    pub(crate) async fn new_with_dispatcher<D>(
+        dispatcher: &D,
+        credentials: &AwsCredentials,
+        options: &ConsulTokenOptions,
+    ) -> Result<Self, Error>
+    where
+        D: DispatchSignedRequest,
+    {
+        info!("Building Consul login data for AWS IAM authentication");
+        let mut headers: HashMap<&str, String> = HashMap::new();
+        if let Some(server_id) = &options.server_id {
+            headers.insert(SERVER_ID_HEADER, server_id.clone());
+        }
+        if options.include_entity {
+            let identity =
+                sts::whoami_with_dispatcher(dispatcher, credentials, options.region.as_ref())
+                    .await?;
+            let mut entity = entity_request(&identity.arn.principal(), &identity.arn.partition)?;
+            entity.sign(credentials);
+
+            let body = match entity.payload {
+                Some(SignedRequestPayload::Buffer(ref buffer)) => {
+                    String::from_utf8_lossy(buffer).into_owned()
+                }
+                _ => unreachable!("Payload was set when building the request"),
+            };
+            let url = format!(
+                "{}://{}{}",
+                entity.scheme(),
+                entity.hostname(),
+                entity.canonical_path()
+            );
+            headers.insert(GET_ENTITY_METHOD_HEADER, entity.method().to_string());
+            headers.insert(GET_ENTITY_URL_HEADER, url);
+            headers.insert(
+                GET_ENTITY_HEADERS_HEADER,
+                serde_json::to_string(&signed_headers(&entity))?,
+            );
+            headers.insert(GET_ENTITY_BODY_HEADER, body);
+        }
+
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect();
+        let payload = AwsAuthIamPayload::new(credentials, options.region.as_ref(), headers);
+        let data = Self {
+            iam_http_request_method: payload.iam_http_request_method,
+            iam_request_url: payload.iam_request_url,
+            iam_request_headers: base64::encode(serde_json::to_string(
+                &payload.iam_request_headers,
+            )?),
+            iam_request_body: payload.iam_request_body,
+        };
+        debug!("Consul login data: {:#?}", data);
+        Ok(data)
+    }
+
+    /// The bearer token to log in to Consul with
+    pub fn bearer_token(&self) -> String {
+        serde_json::to_string(self).expect("login data to be serializable")
+    }
+}
+
+/// An unsigned `iam:GetRole` or `iam:GetUser` request for `principal`
+fn entity_request(principal: &Option<Principal>, partition: &str) -> Result<SignedRequest, Error> {
+    let partition = Partition::ALL
+        .iter()
+        .copied()
+        .find(|known| known.name() == partition)
+        .unwrap_or(Partition::Aws);
+    let region = Region::Custom {
+        name: partition.iam_region().to_string(),
+        endpoint: format!("https://{}", partition.iam_host()),
+    };
+    let mut params = Params::new();
+    match principal {
+        Some(Principal::Role { name, .. }) => {
+            params.put("Action", "GetRole");
+            params.put("RoleName", name);
+        }
+        Some(Principal::AssumedRole { role_name, .. }) => {
+            params.put("Action", "GetRole");
+            params.put("RoleName", role_name);
+        }
+        Some(Principal::User { name, .. }) => {
+            params.put("Action", "GetUser");
+            params.put("UserName", name);
+        }
+        other => {
+            return Err(Error::GenericError(format!(
+                "Consul cannot look up IAM entity details of {:?}",
+                other
+            )))
+        }
+    }
+    params.put("Version", "2010-05-08");
+
+    let mut request = SignedRequest::new("POST", "iam", &region, "/");
+    request.set_payload(Some(
+        serde_urlencoded::to_string(&params).unwrap().into_bytes(),
+    ));
+    request.set_content_type("application/x-www-form-urlencoded".to_owned());
+    Ok(request)
+}
+
+#[cfg(feature = "consul-client")]
+pub use self::login::{login, AclToken, Client};
+
+#[cfg(feature = "consul-client")]
+mod login {
+    use std::time::Duration;
+
+    use hyper::header::CONTENT_TYPE;
+    use hyper::{Body, Method, Request, Uri};
+    use log::info;
+    use serde::{Deserialize, Serialize};
+
+    use super::ConsulLoginData;
+    use crate::tls::{Connector, TlsConfig};
+    use crate::{http, Error};
+
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// An ACL token issued by a Consul login
+    ///
+    /// `Debug` output redacts the secret.
+    #[derive(Serialize, Deserialize, Eq, PartialEq, Clone)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct AclToken {
+        /// ID of the token, used to look it up or revoke it
+        #[serde(rename = "AccessorID")]
+        pub accessor_id: String,
+        /// The token itself, to send in `X-Consul-Token`
+        #[serde(rename = "SecretID")]
+        pub secret_id: String,
+        /// Name of the auth method that issued the token
+        #[serde(default)]
+        pub auth_method: String,
+        /// Whether the token is only valid in the local datacenter
+        #[serde(default)]
+        pub local: bool,
+        /// When the token expires, in RFC 3339 format, if it does
+        #[serde(default)]
+        pub expiration_time: Option<String>,
+    }
+
+    impl std::fmt::Debug for AclToken {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("AclToken")
+                .field("accessor_id", &self.accessor_id)
+                .field("secret_id", &"<redacted>")
+                .field("auth_method", &self.auth_method)
+                .field("local", &self.local)
+                .field("expiration_time", &self.expiration_time)
+                .finish()
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct LoginRequest<'a> {
+        auth_method: &'a str,
+        bearer_token: String,
+    }
+
+    /// A client for the HTTP API of a Consul agent or server
+    #[derive(Clone, Debug)]
+    pub struct Client {
+        address: String,
+        http: hyper::Client<Connector>,
+        timeout: Duration,
+    }
+
+    impl Client {
+        /// Create a client for the Consul agent at `address`, such as `https://127.0.0.1:8501`
+        pub fn new(address: &str) -> Result<Self, Error> {
+            let address = address.trim_end_matches('/');
+            let uri: Uri = address.parse().map_err(|e| {
+                Error::GenericError(format!("Invalid Consul address {}: {}", address, e))
+            })?;
+            if uri.scheme().is_none() || uri.host().is_none() {
+                return Err(Error::GenericError(format!(
+                    "Consul address {} is not an absolute URL",
+                    address
+                )));
+            }
+
+            Ok(Self {
+                address: address.to_string(),
+                http: hyper::Client::builder().build(TlsConfig::default().connector()?),
+                timeout: DEFAULT_TIMEOUT,
+            })
+        }
+
+        /// Establish TLS connections according to `config`
+        pub fn with_tls(mut self, config: &TlsConfig) -> Result<Self, Error> {
+            self.http = hyper::Client::builder().build(config.connector()?);
+            Ok(self)
+        }
+
+        /// Log in with the AWS IAM auth method named `auth_method`
+        pub async fn login(
+            &self,
+            auth_method: &str,
+            data: &ConsulLoginData,
+        ) -> Result<AclToken, Error> {
+            info!(
+                "Logging in to Consul at {} with auth method {}",
+                self.address, auth_method
+            );
+            let body = serde_json::to_vec(&LoginRequest {
+                auth_method,
+                bearer_token: data.bearer_token(),
+            })?;
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(format!("{}/v1/acl/login", self.address))
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(body))?;
+            let response = http::send(&self.http, request, self.timeout).await?;
+
+            if !response.status.is_success() {
+                return Err(Error::ConsulError {
+                    status: response.status.as_u16(),
+                    message: String::from_utf8_lossy(&response.body).trim().to_string(),
+                });
+            }
+            Ok(serde_json::from_slice(&response.body)?)
+        }
+    }
+
+    /// Log in to the Consul agent at `addr` with the AWS IAM auth method named `auth_method`
+    ///
+    /// This is a shorthand for [`Client::login`].
+    pub async fn login(
+        addr: &str,
+        auth_method: &str,
+        data: &ConsulLoginData,
+    ) -> Result<AclToken, Error> {
+        Client::new(addr)?.login(auth_method, data).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusoto_mock::MockRequestDispatcher;
+
+    fn headers(data: &ConsulLoginData) -> HashMap<String, Vec<String>> {
+        serde_json::from_slice(&base64::decode(&data.iam_request_headers).unwrap()).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn login_data_includes_entity_request() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let dispatcher =
+            MockRequestDispatcher::with_status(200).with_body(sts::tests::CALLER_IDENTITY_RESPONSE);
+        let options = ConsulTokenOptions {
+            server_id: Some("consul.example.com".to_string()),
+            include_entity: true,
+            ..Default::default()
+        };
+
+        let data =
+            ConsulLoginData::new_with_dispatcher(&dispatcher, &credentials, &options).await?;
+        let headers = headers(&data);
+        assert_eq!(
+            headers[&SERVER_ID_HEADER.to_lowercase()],
+            vec!["consul.example.com"]
+        );
+        assert_eq!(
+            headers[&GET_ENTITY_URL_HEADER.to_lowercase()],
+            vec!["https://iam.amazonaws.com/"]
+        );
+        assert_eq!(
+            headers[&GET_ENTITY_BODY_HEADER.to_lowercase()],
+            vec!["Action=GetRole&RoleName=my-role&Version=2010-05-08"]
+        );
+        let entity_headers: HashMap<String, Vec<String>> =
+            serde_json::from_str(&headers[&GET_ENTITY_HEADERS_HEADER.to_lowercase()][0])?;
+        assert!(entity_headers.contains_key("authorization"));
+
+        let token: serde_json::Value = serde_json::from_str(&data.bearer_token())?;
+        assert_eq!(token["iam_http_request_method"], "POST");
+        assert!(!format!("{:?}", data).contains(&data.iam_request_headers));
+        Ok(())
+    }
+
+    #[test]
+    fn entity_requests_are_sent_to_iam_of_the_partition() {
+        let principal: Option<Principal> = None;
+        assert!(entity_request(&principal, "aws").is_err());
+
+        let arn: crate::arn::Arn = "arn:aws-us-gov:iam::123456789012:role/my-role"
+            .parse()
+            .unwrap();
+        let request = entity_request(&arn.principal(), &arn.partition).unwrap();
+        assert_eq!(request.hostname(), "iam.us-gov.amazonaws.com");
+        assert_eq!(request.region.name(), "us-gov-west-1");
+
+        let arn: crate::arn::Arn = "arn:aws-iso-b:iam::123456789012:user/alice"
+            .parse()
+            .unwrap();
+        let request = entity_request(&arn.principal(), &arn.partition).unwrap();
+        assert_eq!(request.hostname(), "iam.us-isob-east-1.sc2s.sgov.gov");
+        assert_eq!(request.region.name(), "us-isob-east-1");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn login_data_without_entity_skips_lookup() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let dispatcher = MockRequestDispatcher::with_status(500);
+
+        let data = ConsulLoginData::new_with_dispatcher(
+            &dispatcher,
+            &credentials,
+            &ConsulTokenOptions::default(),
+        )
+        .await?;
+        assert!(!headers(&data).contains_key(&GET_ENTITY_URL_HEADER.to_lowercase()));
+        Ok(())
+    }
+
+    #[cfg(feature = "consul-client")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn login_returns_acl_token() -> Result<(), crate::Error> {
+        use hyper::StatusCode;
+
+        let address = crate::http::tests::serve(|request| {
+            assert_eq!(request.path, "/v1/acl/login");
+            let body = request.json();
+            assert_eq!(body["AuthMethod"], "aws-iam");
+            assert!(body["BearerToken"]
+                .as_str()
+                .unwrap()
+                .contains("iam_request_url"));
+            (
+                StatusCode::OK,
+                serde_json::json!({
+                    "AccessorID": "accessor",
+                    "SecretID": "secret",
+                    "AuthMethod": "aws-iam",
+                    "Local": true
+                })
+                .to_string(),
+            )
+        });
+        let credentials = crate::client::tests::credentials().await?;
+        let data = ConsulLoginData::new_with_dispatcher(
+            &MockRequestDispatcher::default(),
+            &credentials,
+            &ConsulTokenOptions::default(),
+        )
+        .await?;
+
+        let token = login(&address, "aws-iam", &data).await?;
+        assert_eq!(token.secret_id, "secret");
+        assert!(token.local);
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+//! Payloads for the [EC2 auth method](https://www.vaultproject.io/docs/auth/aws#ec2-auth-method)
+//! of Vault
+//!
+//! Instead of a signed STS request, the EC2 auth method authenticates an instance with its
+//! instance identity document, signed by AWS and retrieved from the instance metadata service.
+use serde::{Deserialize, Serialize};
+
+use crate::imds;
+use crate::Error;
+
+const PKCS7_PATH: &str = "/latest/dynamic/instance-identity/pkcs7";
+const DOCUMENT_PATH: &str = "/latest/dynamic/instance-identity/document";
+const SIGNATURE_PATH: &str = "/latest/dynamic/instance-identity/signature";
+
+/// Signed instance identity of an EC2 instance, for the Vault EC2 auth method
+///
+/// This (de)serializes into the fields expected by Vault's
+/// [login endpoint](https://www.vaultproject.io/api/auth/aws#login).
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+#[serde(untagged)]
+pub enum Ec2AuthPayload {
+    /// The PKCS#7 signature of the identity document
+    Pkcs7 {
+        /// Base64 encoded PKCS#7 signature, without new lines
+        pkcs7: String,
+        /// Nonce to bind the instance to its first login with, if any
+        #[serde(skip_serializing_if = "Option::is_none")]
+        nonce: Option<String>,
+    },
+    /// The identity document and its RSA SHA256 signature
+    Identity {
+        /// Base64 encoded identity document
+        identity: String,
+        /// Base64 encoded signature of the identity document
+        signature: String,
+        /// Nonce to bind the instance to its first login with, if any
+        #[serde(skip_serializing_if = "Option::is_none")]
+        nonce: Option<String>,
+    },
+}
+
+impl Ec2AuthPayload {
+    /// Retrieve the PKCS#7 signature of the instance identity document
+    ///
+    /// This only works on EC2 instances.
+    pub async fn pkcs7() -> Result<Self, Error> {
+        let pkcs7 = imds::get(PKCS7_PATH).await?;
+        Ok(Ec2AuthPayload::Pkcs7 {
+            pkcs7: pkcs7.split_whitespace().collect(),
+            nonce: None,
+        })
+    }
+
+    /// Retrieve the instance identity document and its signature
+    ///
+    /// This only works on EC2 instances.
+    pub async fn identity() -> Result<Self, Error> {
+        let document = imds::get(DOCUMENT_PATH).await?;
+        let signature = imds::get(SIGNATURE_PATH).await?;
+        Ok(Ec2AuthPayload::Identity {
+            identity: base64::encode(document),
+            signature: signature.split_whitespace().collect(),
+            nonce: None,
+        })
+    }
+
+    /// Log in with `nonce`
+    ///
+    /// Vault returns a nonce on the first login of an instance unless one is provided, and
+    /// requires it on subsequent logins to prevent replay of the identity document.
+    pub fn with_nonce(mut self, nonce: &str) -> Self {
+        match &mut self {
+            Ec2AuthPayload::Pkcs7 { nonce: current, .. }
+            | Ec2AuthPayload::Identity { nonce: current, .. } => *current = Some(nonce.to_string()),
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    use hyper::StatusCode;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn payloads_are_retrieved_from_imds() -> Result<(), crate::Error> {
+        let address = crate::http::tests::serve(|request| match request.path.as_str() {
+            "/latest/api/token" => {
+                assert_eq!(request.method, hyper::Method::PUT);
+                assert!(request.body.is_empty());
+                (StatusCode::OK, "token".to_string())
+            }
+            _ if request.headers["x-aws-ec2-metadata-token"] != "token" => {
+                (StatusCode::UNAUTHORIZED, String::new())
+            }
+            PKCS7_PATH => (StatusCode::OK, "MIAGCSqG\nSIb3DQEH".to_string()),
+            DOCUMENT_PATH => (StatusCode::OK, r#"{"instanceId":"i-0123"}"#.to_string()),
+            SIGNATURE_PATH => (StatusCode::OK, "c2lnbmF0\ndXJl\n".to_string()),
+            _ => (StatusCode::NOT_FOUND, String::new()),
+        });
+        env::set_var("AWS_EC2_METADATA_SERVICE_ENDPOINT", address);
+
+        let pkcs7 = Ec2AuthPayload::pkcs7().await?.with_nonce("nonce");
+        assert_eq!(
+            serde_json::to_value(&pkcs7)?,
+            serde_json::json!({ "pkcs7": "MIAGCSqGSIb3DQEH", "nonce": "nonce" })
+        );
+
+        let identity = Ec2AuthPayload::identity().await?;
+        assert_eq!(
+            identity,
+            Ec2AuthPayload::Identity {
+                identity: base64::encode(r#"{"instanceId":"i-0123"}"#),
+                signature: "c2lnbmF0dXJl".to_string(),
+                nonce: None,
+            }
+        );
+        env::remove_var("AWS_EC2_METADATA_SERVICE_ENDPOINT");
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+//! Registry credentials for [Amazon ECR](https://docs.aws.amazon.com/AmazonECR/latest/userguide/registry_auth.html)
+//!
+//! This module requires the `ecr` feature. The credentials are fetched with
+//! `ecr:GetAuthorizationToken`, like `aws ecr get-login-password` does, and can be answered to
+//! Docker as a [credential helper](https://github.com/docker/docker-credential-helpers).
+use std::fmt;
+
+use chrono::{DateTime, TimeZone, Utc};
+use log::{debug, info};
+use rusoto_core::credential::{AwsCredentials, StaticProvider};
+use rusoto_core::request::{DispatchSignedRequest, HttpClient};
+use rusoto_core::Region;
+use rusoto_ecr::{Ecr, EcrClient, GetAuthorizationTokenRequest};
+use serde::{Deserialize, Serialize};
+
+use crate::region::service_region;
+use crate::Error;
+
+/// Credentials for the ECR registries of an account
+///
+/// The `Debug` output redacts the password.
+#[derive(Clone, Eq, PartialEq)]
+pub struct EcrCredentials {
+    /// User name to log in with, always `AWS`
+    pub username: String,
+    /// Password to log in with
+    pub password: String,
+    /// URL of the registry, such as `https://123456789012.dkr.ecr.us-west-2.amazonaws.com`
+    pub proxy_endpoint: String,
+    /// When the credentials expire, 12 hours after they were issued
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl EcrCredentials {
+    /// Fetch credentials for the registry of the account of `credentials` in `region`
+    pub async fn fetch(credentials: &AwsCredentials, region: &Region) -> Result<Self, Error> {
+        let dispatcher = HttpClient::new()?;
+        Self::fetch_with_dispatcher(dispatcher, credentials, region).await
+    }
+
+    pub(crate) async fn fetch_with_dispatcher<D>(
+        dispatcher: D,
+        credentials: &AwsCredentials,
+        region: &Region,
+    ) -> Result<Self, Error>
+    where
+        D: DispatchSignedRequest + Send + Sync + 'static,
+    {
+        info!("Fetching ECR authorization token in {}", region.name());
+        let client = EcrClient::new_with(
+            dispatcher,
+            StaticProvider::from(credentials.clone()),
+            service_region(region, "api.ecr"),
+        );
+        let data = client
+            .get_authorization_token(GetAuthorizationTokenRequest::default())
+            .await?
+            .authorization_data
+            .and_then(|data| data.into_iter().next())
+            .ok_or_else(|| {
+                Error::GenericError("GetAuthorizationToken returned no token".to_string())
+            })?;
+
+        let invalid = || Error::GenericError("Malformed ECR authorization token".to_string());
+        let token = data.authorization_token.ok_or_else(invalid)?;
+        let decoded = base64::decode(&token).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        let (username, password) = decoded.split_once(':').ok_or_else(invalid)?;
+        let expires_at = data
+            .expires_at
+            .and_then(|at| Utc.timestamp_millis_opt((at * 1000.0) as i64).single());
+        debug!("ECR authorization token expires at {:?}", expires_at);
+
+        Ok(Self {
+            username: username.to_string(),
+            password: password.to_string(),
+            proxy_endpoint: data.proxy_endpoint.ok_or_else(invalid)?,
+            expires_at,
+        })
+    }
+
+    /// Host name of the registry, as used in image references
+    pub fn registry(&self) -> &str {
+        self.proxy_endpoint
+            .trim_start_matches("https://")
+            .trim_end_matches('/')
+    }
+
+    /// Whether the credentials have expired
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| at <= Utc::now())
+    }
+
+    /// The credentials as answered to the `get` command of a Docker credential helper
+    pub fn docker_credential(&self) -> DockerCredential {
+        DockerCredential {
+            server_url: self.registry().to_string(),
+            username: self.username.clone(),
+            secret: self.password.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for EcrCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EcrCredentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("proxy_endpoint", &self.proxy_endpoint)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+/// Credentials in the format of the Docker credential helper protocol
+///
+/// The `Debug` output redacts the secret.
+#[derive(Clone, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DockerCredential {
+    /// The registry the credentials are for
+    #[serde(rename = "ServerURL")]
+    pub server_url: String,
+    /// User name to log in with
+    pub username: String,
+    /// Password to log in with
+    pub secret: String,
+}
+
+impl DockerCredential {
+    /// Serialize the credentials to the JSON printed by the `get` command
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("credentials to serialize")
+    }
+}
+
+impl fmt::Debug for DockerCredential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DockerCredential")
+            .field("server_url", &self.server_url)
+            .field("username", &self.username)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusoto_mock::MockRequestDispatcher;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn authorization_tokens_are_decoded() -> Result<(), crate::Error> {
+        let dispatcher = MockRequestDispatcher::with_status(200)
+            .with_body(
+                &serde_json::json!({
+                    "authorizationData": [{
+                        "authorizationToken": base64::encode("AWS:my-password"),
+                        "expiresAt": 1_700_000_000.5,
+                        "proxyEndpoint": "https://123456789012.dkr.ecr.us-west-2.amazonaws.com"
+                    }]
+                })
+                .to_string(),
+            )
+            .with_request_checker(|request| {
+                assert_eq!(
+                    request.headers["x-amz-target"],
+                    vec![b"AmazonEC2ContainerRegistry_V20150921.GetAuthorizationToken".to_vec()]
+                );
+            });
+        let credentials = crate::client::tests::credentials().await?;
+
+        let ecr = EcrCredentials::fetch_with_dispatcher(dispatcher, &credentials, &Region::UsWest2)
+            .await?;
+        assert_eq!(ecr.username, "AWS");
+        assert_eq!(ecr.password, "my-password");
+        assert_eq!(
+            ecr.registry(),
+            "123456789012.dkr.ecr.us-west-2.amazonaws.com"
+        );
+        assert_eq!(
+            ecr.expires_at,
+            Some(Utc.timestamp_millis_opt(1_700_000_000_500).unwrap())
+        );
+        assert!(ecr.is_expired());
+        assert!(!format!("{:?}", ecr).contains("my-password"));
+
+        let json: serde_json::Value = serde_json::from_str(&ecr.docker_credential().to_json())?;
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "ServerURL": "123456789012.dkr.ecr.us-west-2.amazonaws.com",
+                "Username": "AWS",
+                "Secret": "my-password"
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn regions_unknown_to_rusoto_use_ecr_endpoint() -> Result<(), crate::Error> {
+        let dispatcher = MockRequestDispatcher::with_status(200)
+            .with_body(
+                &serde_json::json!({
+                    "authorizationData": [{
+                        "authorizationToken": base64::encode("AWS:my-password"),
+                        "proxyEndpoint": "https://123456789012.dkr.ecr.me-central-1.amazonaws.com"
+                    }]
+                })
+                .to_string(),
+            )
+            .with_request_checker(|request| {
+                assert_eq!(request.hostname(), "api.ecr.me-central-1.amazonaws.com");
+            });
+        let credentials = crate::client::tests::credentials().await?;
+        let region = crate::region::parse_region("me-central-1")?;
+
+        let ecr = EcrCredentials::fetch_with_dispatcher(dispatcher, &credentials, &region).await?;
+        assert_eq!(
+            ecr.registry(),
+            "123456789012.dkr.ecr.me-central-1.amazonaws.com"
+        );
+        Ok(())
+    }
+}
@@ -1,3 +1,6 @@
+// `failure_derive` generates its impls inside anonymous constants
+#![allow(non_local_definitions)]
+
 use failure_derive::Fail;
 
 /// Error type for this library
@@ -6,6 +9,187 @@ pub enum Error {
     /// Errors related to retrieving AWS credentials
     #[fail(display = "Error retrieving AWS credentials: {}", _0)]
     CredentialsError(#[cause] rusoto_core::credential::CredentialsError),
+    /// Errors while assuming a role
+    #[fail(display = "Error assuming role: {}", _0)]
+    AssumeRoleError(#[cause] Box<rusoto_core::RusotoError<rusoto_sts::AssumeRoleError>>),
+    /// Errors while dispatching a request to AWS
+    #[fail(display = "Error dispatching request to AWS: {}", _0)]
+    HttpDispatchError(#[cause] rusoto_core::request::HttpDispatchError),
+    /// The TLS client for requests to AWS could not be created
+    #[fail(display = "Error creating TLS client: {}", _0)]
+    TlsError(#[cause] rusoto_core::request::TlsError),
+    /// AWS STS rejected a request
+    #[fail(display = "AWS STS returned {} {}: {}", status, code, message)]
+    StsError {
+        /// HTTP status code of the response
+        status: u16,
+        /// Error code reported by STS, such as `SignatureDoesNotMatch`
+        code: crate::sts::StsErrorCode,
+        /// Error message reported by STS
+        message: String,
+    },
+    /// A response could not be parsed as XML
+    #[fail(display = "Invalid XML response: {}", _0)]
+    XmlError(#[cause] xml::reader::Error),
+    /// Vault rejected a request
+    #[fail(display = "Vault returned {}: {:?}", status, errors)]
+    VaultError {
+        /// HTTP status code of the response
+        status: u16,
+        /// Errors reported by Vault
+        errors: Vec<String>,
+    },
+    /// The Vault server has not been initialized
+    #[fail(display = "Vault is not initialized")]
+    VaultNotInitialized,
+    /// The Vault server is sealed and must be unsealed before it can serve requests
+    #[fail(display = "Vault is sealed")]
+    VaultSealed,
+    /// The Vault server is a disaster recovery secondary, which does not serve logins
+    #[fail(display = "Vault is a disaster recovery secondary")]
+    VaultDrSecondary,
+    /// No auth method is mounted at the path a login was attempted with
+    #[fail(display = "No auth method is mounted at auth/{}", mount)]
+    VaultMountNotFound {
+        /// The path the auth method was expected at
+        mount: String,
+    },
+    /// Errors reported by the `vaultrs` client
+    #[cfg(feature = "vaultrs")]
+    #[fail(display = "vaultrs error: {}", _0)]
+    VaultrsError(#[cause] ::vaultrs::error::ClientError),
+    /// A payload does not match the client configuration of a Vault AWS auth method
+    #[fail(
+        display = "Payload does not match the Vault AWS auth configuration: {}",
+        _0
+    )]
+    VaultConfigMismatch(String),
+    /// A Vault token can no longer be renewed
+    #[fail(display = "Vault token is not renewable")]
+    VaultTokenNotRenewable,
+    /// A Vault token has reached the end of its lifetime
+    #[fail(display = "Vault token has expired")]
+    VaultTokenExpired,
+    /// Consul rejected a request
+    #[fail(display = "Consul returned {}: {}", status, message)]
+    ConsulError {
+        /// HTTP status code of the response
+        status: u16,
+        /// Error message reported by Consul
+        message: String,
+    },
+    /// Errors while fetching an ECR authorization token
+    #[cfg(feature = "ecr")]
+    #[fail(display = "Error fetching ECR authorization token: {}", _0)]
+    EcrError(#[cause] Box<rusoto_core::RusotoError<rusoto_ecr::GetAuthorizationTokenError>>),
+    /// Errors while describing an EKS cluster
+    #[cfg(feature = "eks")]
+    #[fail(display = "Error describing EKS cluster: {}", _0)]
+    EksError(#[cause] Box<rusoto_core::RusotoError<rusoto_eks::DescribeClusterError>>),
+    /// Errors while getting credentials for a Redshift cluster
+    #[cfg(feature = "redshift")]
+    #[fail(display = "Error getting Redshift credentials: {}", _0)]
+    RedshiftError(
+        #[cause] Box<rusoto_core::RusotoError<rusoto_redshift::GetClusterCredentialsError>>,
+    ),
+    /// Redshift Serverless rejected a request
+    #[cfg(feature = "redshift")]
+    #[fail(
+        display = "Redshift Serverless returned {} {}: {}",
+        status, code, message
+    )]
+    RedshiftServerlessError {
+        /// HTTP status code of the response
+        status: u16,
+        /// Error code reported by Redshift Serverless, such as `ResourceNotFoundException`
+        code: String,
+        /// Error message reported by Redshift Serverless
+        message: String,
+    },
+    /// Errors reported by the `kube` client
+    #[cfg(feature = "kube")]
+    #[fail(display = "kube error: {}", _0)]
+    KubeError(#[cause] ::kube::Error),
+    /// A bearer token for Kubernetes AWS IAM Authenticator is malformed
+    #[fail(display = "Invalid EKS token: {}", _0)]
+    InvalidEksToken(String),
+    /// A bearer token for Kubernetes AWS IAM Authenticator was signed too long ago
+    #[fail(display = "EKS token has expired")]
+    EksTokenExpired,
+    /// A payload for AWS IAM authentication is malformed or could reach other things than STS
+    /// `GetCallerIdentity`
+    #[fail(display = "Invalid AWS auth payload: {}", _0)]
+    InvalidAuthPayload(String),
+    /// A signed `GetCallerIdentity` request was signed too long ago
+    #[fail(display = "Signed GetCallerIdentity request has expired")]
+    AuthPayloadExpired,
+    /// A signed `GetCallerIdentity` request was already verified
+    #[fail(display = "Signed GetCallerIdentity request was replayed")]
+    AuthPayloadReplayed,
+    /// A signed `GetCallerIdentity` request has the same query or body parameter more than once
+    #[fail(
+        display = "Signed GetCallerIdentity request repeats the parameter {}",
+        _0
+    )]
+    AuthPayloadDuplicateParameter(String),
+    /// The host of a signed `GetCallerIdentity` request is not in lower case
+    #[fail(
+        display = "Signed GetCallerIdentity request is for the mixed-case host {}",
+        _0
+    )]
+    AuthPayloadMixedCaseHost(String),
+    /// The URL of a signed `GetCallerIdentity` request has dot segments, empty segments or
+    /// backslashes in its path
+    #[fail(display = "Signed GetCallerIdentity request has the path {}", _0)]
+    AuthPayloadPathTraversal(String),
+    /// The URL of a signed `GetCallerIdentity` request has a port other than 443
+    #[fail(display = "Signed GetCallerIdentity request is for the port {}", _0)]
+    AuthPayloadPort(u16),
+    /// A header that STS relies on is part of a signed `GetCallerIdentity` request but not of its
+    /// signature
+    #[fail(display = "Signed GetCallerIdentity request does not sign {}", _0)]
+    AuthPayloadUnsignedHeader(String),
+    /// AWS STS throttled too many verifications in a row, and the verifier stopped sending it
+    /// requests for a while
+    #[fail(display = "AWS STS is throttling verifications")]
+    StsCircuitOpen,
+    /// A verification was shed because the rate limit of the requests to AWS STS was reached
+    #[fail(display = "Too many verifications for the AWS STS rate limit")]
+    StsRateLimited,
+    /// A JSON document could not be (de)serialized
+    #[fail(display = "Invalid JSON: {}", _0)]
+    JsonError(#[cause] serde_json::Error),
+    /// Errors while reading or writing files
+    #[fail(display = "I/O error: {}", _0)]
+    IoError(#[cause] std::io::Error),
+    /// Errors while performing an HTTP request
+    #[fail(display = "HTTP request failed: {}", _0)]
+    HttpError(#[cause] hyper::Error),
+    /// An HTTP request could not be built
+    #[fail(display = "Invalid HTTP request: {}", _0)]
+    HttpRequestError(#[cause] hyper::http::Error),
+    /// An HTTP request did not complete in time
+    #[fail(display = "HTTP request timed out")]
+    HttpTimeout,
+    /// A region name is malformed or unknown
+    #[fail(display = "Invalid region {}: {}", region, reason)]
+    InvalidRegion {
+        /// The offending region name
+        region: String,
+        /// Why the region was rejected
+        reason: String,
+    },
+    /// An ARN is malformed
+    #[fail(display = "Invalid ARN {}: {}", arn, reason)]
+    InvalidArn {
+        /// The offending ARN
+        arn: String,
+        /// Why the ARN was rejected
+        reason: String,
+    },
+    /// No region could be determined from any of the sources consulted
+    #[fail(display = "Unable to detect the AWS region")]
+    RegionNotFound,
     /// Generic Error
     #[fail(display = "{}", _0)]
     GenericError(String),
@@ -16,3 +200,86 @@ impl From<rusoto_core::credential::CredentialsError> for Error {
         Error::CredentialsError(error)
     }
 }
+
+impl From<rusoto_core::RusotoError<rusoto_sts::AssumeRoleError>> for Error {
+    fn from(error: rusoto_core::RusotoError<rusoto_sts::AssumeRoleError>) -> Self {
+        Error::AssumeRoleError(Box::new(error))
+    }
+}
+
+#[cfg(feature = "ecr")]
+impl From<rusoto_core::RusotoError<rusoto_ecr::GetAuthorizationTokenError>> for Error {
+    fn from(error: rusoto_core::RusotoError<rusoto_ecr::GetAuthorizationTokenError>) -> Self {
+        Error::EcrError(Box::new(error))
+    }
+}
+
+#[cfg(feature = "eks")]
+impl From<rusoto_core::RusotoError<rusoto_eks::DescribeClusterError>> for Error {
+    fn from(error: rusoto_core::RusotoError<rusoto_eks::DescribeClusterError>) -> Self {
+        Error::EksError(Box::new(error))
+    }
+}
+
+#[cfg(feature = "redshift")]
+impl From<rusoto_core::RusotoError<rusoto_redshift::GetClusterCredentialsError>> for Error {
+    fn from(error: rusoto_core::RusotoError<rusoto_redshift::GetClusterCredentialsError>) -> Self {
+        Error::RedshiftError(Box::new(error))
+    }
+}
+
+#[cfg(feature = "kube")]
+impl From<::kube::Error> for Error {
+    fn from(error: ::kube::Error) -> Self {
+        Error::KubeError(error)
+    }
+}
+
+impl From<rusoto_core::request::HttpDispatchError> for Error {
+    fn from(error: rusoto_core::request::HttpDispatchError) -> Self {
+        Error::HttpDispatchError(error)
+    }
+}
+
+impl From<rusoto_core::request::TlsError> for Error {
+    fn from(error: rusoto_core::request::TlsError) -> Self {
+        Error::TlsError(error)
+    }
+}
+
+impl From<xml::reader::Error> for Error {
+    fn from(error: xml::reader::Error) -> Self {
+        Error::XmlError(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::JsonError(error)
+    }
+}
+
+#[cfg(feature = "vaultrs")]
+impl From<::vaultrs::error::ClientError> for Error {
+    fn from(error: ::vaultrs::error::ClientError) -> Self {
+        Error::VaultrsError(error)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::IoError(error)
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(error: hyper::Error) -> Self {
+        Error::HttpError(error)
+    }
+}
+
+impl From<hyper::http::Error> for Error {
+    fn from(error: hyper::http::Error) -> Self {
+        Error::HttpRequestError(error)
+    }
+}
@@ -0,0 +1,119 @@
+//! Minimal HTTP helpers for the endpoints this library talks to directly
+use std::time::Duration;
+
+use hyper::body::{self, Bytes};
+use hyper::client::connect::Connect;
+use hyper::{Body, Client, HeaderMap, Request, StatusCode};
+
+use crate::Error;
+
+/// A fully buffered HTTP response
+#[derive(Debug)]
+pub(crate) struct Response {
+    pub(crate) status: StatusCode,
+    #[cfg_attr(not(feature = "vault-client"), allow(dead_code))]
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: Bytes,
+}
+
+/// Send `request` using `client` and buffer the entire response, giving up after `timeout`
+pub(crate) async fn send<C>(
+    client: &Client<C>,
+    request: Request<Body>,
+    timeout: Duration,
+) -> Result<Response, Error>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    let response = async {
+        let response = client.request(request).await?;
+        let (parts, body) = response.into_parts();
+        let body = body::to_bytes(body).await?;
+        Ok(Response {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        })
+    };
+
+    tokio::time::timeout(timeout, response)
+        .await
+        .map_err(|_| Error::HttpTimeout)?
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Method, Server};
+
+    use super::*;
+
+    /// A recorded request received by a test server
+    pub(crate) struct Received {
+        pub(crate) method: Method,
+        pub(crate) path: String,
+        pub(crate) headers: HeaderMap,
+        pub(crate) body: Bytes,
+    }
+
+    #[cfg_attr(
+        not(any(feature = "consul-client", feature = "vault-client")),
+        allow(dead_code)
+    )]
+    impl Received {
+        /// The body parsed as JSON, or `null` if it is not JSON
+        pub(crate) fn json(&self) -> serde_json::Value {
+            serde_json::from_slice(&self.body).unwrap_or(serde_json::Value::Null)
+        }
+    }
+
+    /// Serve `handler` on a random local port and return the address of the server
+    pub(crate) fn serve<F>(handler: F) -> String
+    where
+        F: Fn(Received) -> (StatusCode, String) + Send + Sync + 'static,
+    {
+        serve_responses(move |request| {
+            let (status, body) = handler(request);
+            hyper::Response::builder()
+                .status(status)
+                .body(Body::from(body))
+                .unwrap()
+        })
+    }
+
+    /// Serve `handler`, which builds entire responses, on a random local port
+    #[cfg_attr(not(feature = "vault-client"), allow(dead_code))]
+    pub(crate) fn serve_responses<F>(handler: F) -> String
+    where
+        F: Fn(Received) -> hyper::Response<Body> + Send + Sync + 'static,
+    {
+        let handler = Arc::new(handler);
+        let make_service = make_service_fn(move |_| {
+            let handler = Arc::clone(&handler);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let handler = Arc::clone(&handler);
+                    async move {
+                        let (parts, body) = request.into_parts();
+                        let received = Received {
+                            method: parts.method,
+                            path: parts.uri.path().to_string(),
+                            headers: parts.headers,
+                            body: body::to_bytes(body).await?,
+                        };
+                        Ok::<_, hyper::Error>(handler(received))
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let address = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        address
+    }
+}
@@ -0,0 +1,75 @@
+//! Access to the EC2 instance metadata service (IMDS)
+use std::env;
+use std::time::Duration;
+
+use hyper::{Body, Client, Method, Request};
+use log::debug;
+
+use crate::http;
+use crate::Error;
+
+const DEFAULT_ENDPOINT: &str = "http://169.254.169.254";
+const TOKEN_TTL_HEADER: &str = "x-aws-ec2-metadata-token-ttl-seconds";
+const TOKEN_HEADER: &str = "x-aws-ec2-metadata-token";
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Whether the instance metadata service was disabled with `AWS_EC2_METADATA_DISABLED`
+pub(crate) fn disabled() -> bool {
+    env::var("AWS_EC2_METADATA_DISABLED")
+        .map(|value| value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+fn endpoint() -> String {
+    env::var("AWS_EC2_METADATA_SERVICE_ENDPOINT")
+        .map(|endpoint| endpoint.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| DEFAULT_ENDPOINT.to_string())
+}
+
+/// Retrieve the metadata at `path`, for example `/latest/meta-data/placement/region`
+///
+/// An IMDSv2 session token is requested first. If the instance does not support IMDSv2, the
+/// request is retried without one.
+pub(crate) async fn get(path: &str) -> Result<String, Error> {
+    if disabled() {
+        return Err(Error::GenericError(
+            "The EC2 instance metadata service is disabled".to_string(),
+        ));
+    }
+
+    let endpoint = endpoint();
+    let client = Client::new();
+
+    let token_request = Request::builder()
+        .method(Method::PUT)
+        .uri(format!("{}/latest/api/token", endpoint))
+        .header(TOKEN_TTL_HEADER, "60")
+        .body(Body::empty())?;
+    let token = match http::send(&client, token_request, TIMEOUT).await {
+        Ok(response) if response.status.is_success() => {
+            Some(String::from_utf8_lossy(&response.body).into_owned())
+        }
+        Ok(response) => {
+            debug!("IMDSv2 token request returned {}", response.status);
+            None
+        }
+        Err(e) => {
+            debug!("IMDSv2 token request failed: {}", e);
+            None
+        }
+    };
+
+    let mut request = Request::builder().uri(format!("{}{}", endpoint, path));
+    if let Some(token) = token {
+        request = request.header(TOKEN_HEADER, token);
+    }
+    let response = http::send(&client, request.body(Body::empty())?, TIMEOUT).await?;
+    if !response.status.is_success() {
+        return Err(Error::GenericError(format!(
+            "Instance metadata request for {} returned {}",
+            path, response.status
+        )));
+    }
+
+    String::from_utf8(response.body.to_vec()).map_err(|e| Error::GenericError(e.to_string()))
+}
@@ -0,0 +1,155 @@
+//! The SigV4 authenticator of [Amazon Keyspaces](https://docs.aws.amazon.com/keyspaces/latest/devguide/programmatic.credentials.SigV4_KEYSPACES.html)
+//!
+//! Keyspaces authenticates CQL connections with a SASL exchange: the client sends
+//! [`INITIAL_RESPONSE`], the server answers with a challenge holding a nonce, and the client
+//! responds with a signature over the nonce. This is what the `SigV4AuthProvider` plugins of the
+//! official Cassandra drivers do.
+use chrono::{DateTime, Utc};
+use log::info;
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::Region;
+
+use crate::sigv4::{self, hex_sha256};
+use crate::Error;
+
+/// Initial SASL response selecting the SigV4 mechanism
+pub const INITIAL_RESPONSE: &[u8] = b"SigV4\0\0";
+
+/// Signing name of Amazon Keyspaces
+const SERVICE: &str = "cassandra";
+
+/// Format of the signing time, which unlike other SigV4 signatures includes milliseconds
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3fZ";
+
+/// Answer the `challenge` of a Keyspaces endpoint in `region`
+///
+/// The challenge is of the form `nonce=<nonce>`. The response holds the signature, the access
+/// key ID, the signing time and, for temporary credentials, the session token.
+pub fn challenge_response(
+    credentials: &AwsCredentials,
+    region: &Region,
+    challenge: &[u8],
+) -> Result<Vec<u8>, Error> {
+    challenge_response_at(credentials, region, challenge, Utc::now())
+}
+
+pub(crate) fn challenge_response_at(
+    credentials: &AwsCredentials,
+    region: &Region,
+    challenge: &[u8],
+    now: DateTime<Utc>,
+) -> Result<Vec<u8>, Error> {
+    info!("Answering Keyspaces SigV4 challenge");
+    let nonce = nonce(challenge)?;
+    let timestamp = now.format(TIMESTAMP_FORMAT).to_string();
+    let signature = signature(credentials, region.name(), nonce, now);
+
+    let mut response = format!(
+        "signature={},access_key={},amzdate={}",
+        signature,
+        credentials.aws_access_key_id(),
+        timestamp
+    );
+    if let Some(token) = credentials.token() {
+        response.push_str(",session_token=");
+        response.push_str(token);
+    }
+    Ok(response.into_bytes())
+}
+
+/// The nonce of a `nonce=<nonce>` challenge
+fn nonce(challenge: &[u8]) -> Result<&[u8], Error> {
+    let invalid = || Error::GenericError("Malformed Keyspaces challenge".to_string());
+    let start = challenge
+        .windows(b"nonce=".len())
+        .position(|window| window == b"nonce=")
+        .ok_or_else(invalid)?
+        + b"nonce=".len();
+    let nonce = &challenge[start..];
+    let end = nonce
+        .iter()
+        .position(|&byte| byte == b',')
+        .unwrap_or(nonce.len());
+    match &nonce[..end] {
+        [] => Err(invalid()),
+        nonce => Ok(nonce),
+    }
+}
+
+/// Sign `nonce` like a pre-signed `PUT /authenticate` to the host `cassandra`
+fn signature(
+    credentials: &AwsCredentials,
+    region: &str,
+    nonce: &[u8],
+    now: DateTime<Utc>,
+) -> String {
+    let timestamp = now.format(TIMESTAMP_FORMAT).to_string();
+    let scope = sigv4::scope(now, region, SERVICE);
+
+    let credential = format!("{}/{}", credentials.aws_access_key_id(), scope);
+    let mut query = [
+        ("X-Amz-Algorithm", sigv4::ALGORITHM),
+        ("X-Amz-Credential", credential.as_str()),
+        ("X-Amz-Date", timestamp.as_str()),
+        ("X-Amz-Expires", "900"),
+    ]
+    .iter()
+    .map(|param| serde_urlencoded::to_string([param]).expect("parameters to be encodable"))
+    .collect::<Vec<_>>();
+    query.sort();
+
+    let canonical_request = format!(
+        "PUT\n/authenticate\n{}\nhost:{}\n\nhost\n{}",
+        query.join("&"),
+        SERVICE,
+        hex_sha256(nonce)
+    );
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        sigv4::ALGORITHM,
+        timestamp,
+        scope,
+        hex_sha256(canonical_request.as_bytes())
+    );
+
+    let key = sigv4::signing_key(credentials.aws_secret_access_key(), now, region, SERVICE);
+    hex::encode(sigv4::hmac(&key, string_to_sign.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn challenges_are_answered_like_the_driver_plugins() -> Result<(), crate::Error> {
+        // The test vector of the SigV4 plugin of the DataStax Java driver
+        let credentials = AwsCredentials::new("UserID-1", "UserSecretKey-1", None, None);
+        let now = Utc.timestamp_millis_opt(1_591_742_511_000).unwrap();
+        let response = challenge_response_at(
+            &credentials,
+            &Region::UsWest2,
+            b"nonce=91703fdc2ef562e19fbdab0f58e42fe5",
+            now,
+        )?;
+        assert_eq!(
+            String::from_utf8(response).unwrap(),
+            "signature=7f3691c18a81b8ce7457699effbfae5b09b4e0714ab38c1292dbdf082c9ddd87,\
+             access_key=UserID-1,amzdate=2020-06-09T22:41:51.000Z"
+        );
+
+        let temporary = AwsCredentials::new(
+            "UserID-1",
+            "UserSecretKey-1",
+            Some("my-token".to_string()),
+            None,
+        );
+        let response = challenge_response_at(&temporary, &Region::UsWest2, b"nonce=abc", now)?;
+        assert!(String::from_utf8(response)
+            .unwrap()
+            .ends_with(",session_token=my-token"));
+        assert!(challenge_response(&credentials, &Region::UsWest2, b"nonce=").is_err());
+        Ok(())
+    }
+}
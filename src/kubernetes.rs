@@ -0,0 +1,726 @@
+//! Credentials for Kubernetes clusters that authenticate AWS IAM identities, such as EKS
+//!
+//! kubectl and other clients based on client-go obtain tokens by running a
+//! [credential plugin](https://kubernetes.io/docs/reference/access-authn-authz/authentication/#client-go-credential-plugins)
+//! which prints an [`ExecCredential`] to its standard output. Information about the cluster
+//! the credential is for is passed to the plugin as an [`ExecInfo`].
+pub mod cache;
+pub mod kubeconfig;
+pub mod secret;
+#[cfg(feature = "kube")]
+pub mod token_source;
+pub mod verify;
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use futures::future::join_all;
+use log::info;
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::request::{DispatchSignedRequest, HttpClient};
+use rusoto_core::Region;
+use serde::{Deserialize, Serialize};
+
+use crate::arn::Arn;
+use crate::client::{eks_token, EKS_TOKEN_PREFIX};
+use crate::region::{parse_region, Partition};
+use crate::Error;
+
+#[doc(inline)]
+pub use self::cache::TokenCache;
+#[doc(inline)]
+pub use self::verify::{TokenVerifier, VerifiedToken};
+
+/// API version of `ExecCredential` understood by all supported releases of kubectl
+pub const EXEC_CREDENTIAL_V1BETA1: &str = "client.authentication.k8s.io/v1beta1";
+
+/// API version of `ExecCredential` understood by kubectl 1.22 and later
+pub const EXEC_CREDENTIAL_V1: &str = "client.authentication.k8s.io/v1";
+
+/// API versions of `ExecCredential` this library can respond with, newest first
+pub const SUPPORTED_API_VERSIONS: [&str; 2] = [EXEC_CREDENTIAL_V1, EXEC_CREDENTIAL_V1BETA1];
+
+/// Environment variable kubectl passes the [`ExecInfo`] of a request to plugins in
+pub const EXEC_INFO_ENV: &str = "KUBERNETES_EXEC_INFO";
+
+/// Keys of the cluster extension data that can hold the name of an EKS cluster
+const CLUSTER_NAME_KEYS: [&str; 5] = [
+    "clusterName",
+    "cluster_name",
+    "cluster-name",
+    "clusterID",
+    "cluster-id",
+];
+
+/// Keys of the cluster extension data that can hold the ARN of a role to assume
+const ROLE_ARN_KEYS: [&str; 4] = ["roleARN", "roleArn", "role_arn", "role-arn"];
+
+/// How long Kubernetes AWS IAM Authenticator accepts a token after it has been signed
+///
+/// This does not depend on the expiry of the pre-signed URL inside the token.
+pub const EKS_TOKEN_VALIDITY_MINUTES: i64 = 15;
+
+/// How long before the authenticator rejects a token clients are asked to replace it, unless
+/// configured otherwise
+pub const DEFAULT_EXPIRY_MARGIN: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Format of the `X-Amz-Date` query parameter of pre-signed URLs
+const AMZ_DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// An EKS cluster to generate tokens for, identified by its name or its ARN
+///
+/// Parsing an ARN such as `arn:aws:eks:us-west-2:123456789012:cluster/my-cluster` also sets the
+/// region of the cluster, so tokens are signed for the STS endpoint of that region. A plain name
+/// leaves the region unset.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EksCluster {
+    /// Name of the cluster, which tokens are bound to with the
+    /// [`EKS_CLUSTER_ID_HEADER`](crate::client::EKS_CLUSTER_ID_HEADER)
+    pub name: String,
+    /// Region of the cluster, if known
+    pub region: Option<Region>,
+}
+
+impl EksCluster {
+    /// The cluster named `name` in `region`
+    pub fn new(name: &str, region: Option<Region>) -> Self {
+        Self {
+            name: name.to_string(),
+            region,
+        }
+    }
+
+    /// Partition of the cluster, going by its region or the default region if it is not known
+    pub fn partition(&self) -> Partition {
+        let region = self.region.clone().unwrap_or_default();
+        Partition::of_region(region.name())
+    }
+
+    /// Generate a token for the cluster
+    pub fn token(&self, credentials: &AwsCredentials) -> EksToken {
+        eks_token(credentials, &self.name, self.region.as_ref())
+    }
+}
+
+impl FromStr for EksCluster {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.starts_with("arn:") {
+            if s.is_empty() || s.contains(|c: char| c == '/' || c == ':' || c.is_whitespace()) {
+                return Err(Error::GenericError(format!(
+                    "Invalid EKS cluster name {:?}",
+                    s
+                )));
+            }
+            return Ok(Self::new(s, None));
+        }
+
+        let arn: Arn = s.parse()?;
+        let invalid = |reason: &str| Error::InvalidArn {
+            arn: s.to_string(),
+            reason: reason.to_string(),
+        };
+        if arn.service != "eks" {
+            return Err(invalid("not an EKS ARN"));
+        }
+        let name = arn
+            .resource
+            .strip_prefix("cluster/")
+            .filter(|name| !name.is_empty() && !name.contains('/'))
+            .ok_or_else(|| invalid("not the ARN of a cluster"))?;
+        if Partition::of_region(&arn.region).name() != arn.partition {
+            return Err(invalid("region is not in the partition of the ARN"));
+        }
+        Ok(Self::new(name, Some(parse_region(&arn.region)?)))
+    }
+}
+
+impl fmt::Display for EksCluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// A cluster to generate a credential for with [`ExecCredential::for_clusters`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClusterRequest {
+    /// The cluster
+    pub cluster: EksCluster,
+    /// ARN of a role to assume and sign the token as, instead of signing it with the credentials
+    /// that were provided
+    pub role_arn: Option<String>,
+}
+
+impl ClusterRequest {
+    /// Request a credential for `cluster`
+    pub fn new(cluster: EksCluster) -> Self {
+        Self {
+            cluster,
+            role_arn: None,
+        }
+    }
+
+    /// Sign the token as `role_arn`
+    pub fn with_role(mut self, role_arn: &str) -> Self {
+        self.role_arn = Some(role_arn.to_string());
+        self
+    }
+}
+
+/// A bearer token for an EKS cluster, as generated by [`eks_token`]
+///
+/// Tokens parse from and display as their string representation. The time they were signed is
+/// read from the pre-signed URL inside the token, so tokens read back from a cache report the
+/// same validity as when they were generated.
+#[derive(Clone, Eq, PartialEq)]
+pub struct EksToken {
+    token: String,
+    signed_at: DateTime<Utc>,
+}
+
+impl EksToken {
+    /// The token to send as the bearer token
+    pub fn as_str(&self) -> &str {
+        &self.token
+    }
+
+    /// When the pre-signed URL inside the token was signed
+    pub fn signed_at(&self) -> DateTime<Utc> {
+        self.signed_at
+    }
+
+    /// When the authenticator stops accepting the token, [`EKS_TOKEN_VALIDITY_MINUTES`] after it
+    /// was signed
+    pub fn expiration_timestamp(&self) -> DateTime<Utc> {
+        self.signed_at + Duration::minutes(EKS_TOKEN_VALIDITY_MINUTES)
+    }
+
+    /// How long the authenticator keeps accepting the token, zero if it has expired
+    pub fn remaining_ttl(&self) -> std::time::Duration {
+        (self.expiration_timestamp() - Utc::now())
+            .to_std()
+            .unwrap_or_default()
+    }
+
+    /// Whether less than `margin` is left before the token expires
+    pub fn is_stale(&self, margin: std::time::Duration) -> bool {
+        self.remaining_ttl() <= margin
+    }
+}
+
+impl FromStr for EksToken {
+    type Err = Error;
+
+    fn from_str(token: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| Error::InvalidEksToken(reason.to_string());
+
+        let url = decode_eks_token(token)?;
+        let query = url
+            .split_once('?')
+            .map(|(_, query)| query)
+            .ok_or_else(|| invalid("URL has no query"))?;
+        let params: HashMap<String, String> =
+            serde_urlencoded::from_str(query).map_err(|_| invalid("malformed query"))?;
+        let date = params
+            .get("X-Amz-Date")
+            .ok_or_else(|| invalid("missing X-Amz-Date"))?;
+        Ok(Self {
+            token: token.to_string(),
+            signed_at: parse_amz_date(date)?,
+        })
+    }
+}
+
+impl fmt::Display for EksToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.token)
+    }
+}
+
+impl fmt::Debug for EksToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EksToken")
+            .field("token", &"<redacted>")
+            .field("signed_at", &self.signed_at)
+            .finish()
+    }
+}
+
+impl From<EksToken> for String {
+    fn from(token: EksToken) -> Self {
+        token.token
+    }
+}
+
+/// Credentials returned by a client-go credential plugin
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecCredential {
+    /// API version of the document, such as [`EXEC_CREDENTIAL_V1BETA1`]
+    pub api_version: String,
+    /// Always `ExecCredential`
+    pub kind: String,
+    /// The credentials
+    pub status: ExecCredentialStatus,
+}
+
+/// The `status` of an [`ExecCredential`]
+#[derive(Clone, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecCredentialStatus {
+    /// Bearer token to authenticate with
+    pub token: String,
+    /// When clients should run the plugin again to get a new token. Clients cache the token
+    /// for as long as their process lives if this is not set.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "rfc3339_seconds"
+    )]
+    pub expiration_timestamp: Option<DateTime<Utc>>,
+}
+
+impl fmt::Debug for ExecCredentialStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExecCredentialStatus")
+            .field("token", &"<redacted>")
+            .field("expiration_timestamp", &self.expiration_timestamp)
+            .finish()
+    }
+}
+
+impl ExecCredential {
+    /// Wrap `token` in a `v1beta1` credential that expires at `expiration_timestamp`
+    pub fn new(token: String, expiration_timestamp: Option<DateTime<Utc>>) -> Self {
+        Self {
+            api_version: EXEC_CREDENTIAL_V1BETA1.to_string(),
+            kind: "ExecCredential".to_string(),
+            status: ExecCredentialStatus {
+                token,
+                expiration_timestamp,
+            },
+        }
+    }
+
+    /// Wrap a token from [`eks_token`]
+    ///
+    /// The expiry is derived from the time the token was signed rather than the current time,
+    /// so it stays correct for tokens that were cached. Like `aws eks get-token`, clients are
+    /// asked for a new token one minute before the authenticator would reject it.
+    pub fn for_eks_token(token: &EksToken) -> Self {
+        Self::for_eks_token_with_margin(token, DEFAULT_EXPIRY_MARGIN)
+    }
+
+    /// Wrap a token from [`eks_token`], asking clients for a new token `margin` before the
+    /// authenticator would reject it
+    ///
+    /// The authenticator accepts tokens for [`EKS_TOKEN_VALIDITY_MINUTES`] after they were
+    /// signed, regardless of the expiry of their pre-signed URL. A larger margin gives clients
+    /// with skewed clocks or slow requests more leeway, at the cost of signing tokens more often.
+    pub fn for_eks_token_with_margin(token: &EksToken, margin: std::time::Duration) -> Self {
+        let validity = Duration::minutes(EKS_TOKEN_VALIDITY_MINUTES);
+        let margin = Duration::from_std(margin).unwrap_or(validity).min(validity);
+        let expiration = token.expiration_timestamp() - margin;
+        Self::new(token.to_string(), Some(expiration))
+    }
+
+    /// Generate credentials for many clusters concurrently, in the same order as `clusters`
+    ///
+    /// Roles are assumed in the region of their cluster, and separately for every request.
+    /// A failure only affects the cluster it happened for.
+    pub async fn for_clusters(
+        credentials: &AwsCredentials,
+        clusters: &[ClusterRequest],
+    ) -> Vec<Result<Self, Error>> {
+        match HttpClient::new() {
+            Ok(dispatcher) => {
+                Self::for_clusters_with_dispatcher(Arc::new(dispatcher), credentials, clusters)
+                    .await
+            }
+            Err(e) => {
+                let e = e.to_string();
+                clusters
+                    .iter()
+                    .map(|_| {
+                        Err(Error::GenericError(format!(
+                            "Error creating TLS client: {}",
+                            e
+                        )))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    pub(crate) async fn for_clusters_with_dispatcher<D>(
+        dispatcher: Arc<D>,
+        credentials: &AwsCredentials,
+        clusters: &[ClusterRequest],
+    ) -> Vec<Result<Self, Error>>
+    where
+        D: DispatchSignedRequest + Send + Sync + 'static,
+    {
+        info!("Generating EKS tokens for {} clusters", clusters.len());
+        let credentials = clusters.iter().map(|request| {
+            let dispatcher = Arc::clone(&dispatcher);
+            async move {
+                let token = match &request.role_arn {
+                    Some(role_arn) => {
+                        let region = request.cluster.region.clone().unwrap_or_default();
+                        let credentials =
+                            crate::assume_role(dispatcher, credentials, role_arn, region).await?;
+                        request.cluster.token(&credentials)
+                    }
+                    None => request.cluster.token(credentials),
+                };
+                Ok(Self::for_eks_token(&token))
+            }
+        });
+        join_all(credentials).await
+    }
+
+    /// Use the API version `api_version`, such as [`EXEC_CREDENTIAL_V1`]
+    pub fn with_api_version(mut self, api_version: &str) -> Self {
+        self.api_version = api_version.to_string();
+        self
+    }
+
+    /// Serialize the credential as the JSON clients expect on the standard output of a plugin
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("an ExecCredential to be serializable")
+    }
+}
+
+/// The request of a client-go credential plugin, passed in [`EXEC_INFO_ENV`]
+///
+/// kubectl 1.20 and later set the variable for every plugin, but only include the cluster if
+/// `provideClusterInfo` is enabled in the `exec` stanza of the user.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecInfo {
+    /// API version of the `ExecCredential` the plugin must respond with
+    pub api_version: String,
+    /// Always `ExecCredential`
+    pub kind: String,
+    /// Details of the request
+    #[serde(default)]
+    pub spec: ExecInfoSpec,
+}
+
+/// The `spec` of an [`ExecInfo`]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecInfoSpec {
+    /// Whether the plugin may prompt the user through its standard input
+    #[serde(default)]
+    pub interactive: bool,
+    /// The cluster kubectl is connecting to, if `provideClusterInfo` is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<ExecCluster>,
+}
+
+/// A cluster that credentials are requested for
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExecCluster {
+    /// Address of the API server
+    pub server: String,
+    /// Name the certificate of the API server is verified against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_server_name: Option<String>,
+    /// Whether the certificate of the API server is verified
+    #[serde(default)]
+    pub insecure_skip_tls_verify: bool,
+    /// Base64 encoded PEM certificate authorities of the API server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate_authority_data: Option<String>,
+    /// Proxy to connect to the API server through
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_url: Option<String>,
+    /// The `client.authentication.k8s.io/exec` extension of the cluster in the kubeconfig
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<serde_json::Value>,
+}
+
+impl ExecInfo {
+    /// Read the request from [`EXEC_INFO_ENV`], returning `None` if it is not set
+    pub fn from_env() -> Result<Option<Self>, Error> {
+        match env::var(EXEC_INFO_ENV) {
+            Ok(info) => info.parse().map(Some),
+            Err(env::VarError::NotPresent) => Ok(None),
+            Err(e) => Err(Error::GenericError(format!("{}: {}", EXEC_INFO_ENV, e))),
+        }
+    }
+
+    /// Name of the EKS cluster from the extension data of the cluster
+    ///
+    /// The name is taken from the first of `clusterName`, `cluster_name`, `cluster-name`,
+    /// `clusterID` and `cluster-id` that is set. It can be a name or an ARN, either of which
+    /// parses as an [`EksCluster`].
+    pub fn cluster_name(&self) -> Option<&str> {
+        self.config_value(&CLUSTER_NAME_KEYS)
+    }
+
+    /// ARN of the role to assume from the extension data of the cluster
+    ///
+    /// The ARN is taken from the first of `roleARN`, `roleArn`, `role_arn` and `role-arn` that
+    /// is set.
+    pub fn role_arn(&self) -> Option<&str> {
+        self.config_value(&ROLE_ARN_KEYS)
+    }
+
+    /// Answer the request with `credential`, using the API version that was asked for
+    ///
+    /// Fails if the requested version is not supported. See [`negotiate_api_version`].
+    pub fn respond(&self, credential: ExecCredential) -> Result<ExecCredential, Error> {
+        let version = negotiate_api_version(Some(self))?;
+        Ok(credential.with_api_version(version))
+    }
+
+    fn config_value(&self, keys: &[&str]) -> Option<&str> {
+        let config = self.spec.cluster.as_ref()?.config.as_ref()?;
+        keys.iter()
+            .filter_map(|key| config.get(key)?.as_str())
+            .find(|value| !value.is_empty())
+    }
+}
+
+impl std::str::FromStr for ExecInfo {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+/// The API version of `ExecCredential` to respond to `info` with
+///
+/// Clients reject credentials of another version than they asked for, so the requested version
+/// is used if it is one of the [`SUPPORTED_API_VERSIONS`] and anything else is an error. Without
+/// a request, which is how kubectl before 1.20 runs plugins, [`EXEC_CREDENTIAL_V1BETA1`] is used
+/// because every client that does not send its version understands it.
+pub fn negotiate_api_version(info: Option<&ExecInfo>) -> Result<&'static str, Error> {
+    let info = match info {
+        Some(info) => info,
+        None => return Ok(EXEC_CREDENTIAL_V1BETA1),
+    };
+    SUPPORTED_API_VERSIONS
+        .iter()
+        .copied()
+        .find(|version| *version == info.api_version)
+        .ok_or_else(|| {
+            Error::GenericError(format!(
+                "Unsupported ExecCredential API version {}, expected one of {}",
+                info.api_version,
+                SUPPORTED_API_VERSIONS.join(", ")
+            ))
+        })
+}
+
+/// The pre-signed URL inside an EKS `token`
+pub(crate) fn decode_eks_token(token: &str) -> Result<String, Error> {
+    let invalid = |reason: &str| Error::InvalidEksToken(reason.to_string());
+
+    let encoded = token
+        .strip_prefix(EKS_TOKEN_PREFIX)
+        .ok_or_else(|| invalid("missing k8s-aws-v1 prefix"))?;
+    let url = base64::decode_config(encoded.trim_end_matches('='), base64::URL_SAFE_NO_PAD)
+        .map_err(|_| invalid("not URL safe base64"))?;
+    String::from_utf8(url).map_err(|_| invalid("not a URL"))
+}
+
+/// Parse the `X-Amz-Date` of a pre-signed URL
+pub(crate) fn parse_amz_date(date: &str) -> Result<DateTime<Utc>, Error> {
+    let date = NaiveDateTime::parse_from_str(date, AMZ_DATE_FORMAT)
+        .map_err(|_| Error::InvalidEksToken("malformed X-Amz-Date".to_string()))?;
+    Ok(Utc.from_utc_datetime(&date))
+}
+
+/// Timestamps as RFC 3339 with whole seconds, which is what Kubernetes produces
+mod rfc3339_seconds {
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S>(
+        value: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match value {
+            Some(value) => {
+                serializer.serialize_str(&value.to_rfc3339_opts(SecondsFormat::Secs, true))
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<DateTime<Utc>>::deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{SecondsFormat, Timelike};
+    use rusoto_core::Region;
+
+    use crate::client::eks_token;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn eks_tokens_expire_after_signing() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let before = Utc::now().with_nanosecond(0).unwrap();
+        let token = eks_token(&credentials, "my-cluster", Some(Region::UsWest2));
+        assert!(token.signed_at() >= before && token.signed_at() <= Utc::now());
+        assert_eq!(
+            token.expiration_timestamp(),
+            token.signed_at() + Duration::minutes(15)
+        );
+        assert!(token.remaining_ttl() > std::time::Duration::from_secs(14 * 60));
+        assert!(!token.is_stale(std::time::Duration::from_secs(60)));
+        assert!(token.is_stale(std::time::Duration::from_secs(15 * 60)));
+        assert_eq!(token.to_string().parse::<EksToken>()?, token);
+        assert!("k8s-aws-v1.bm9wZQ".parse::<EksToken>().is_err());
+
+        let credential = ExecCredential::for_eks_token(&token);
+        let expiration = credential.status.expiration_timestamp.unwrap();
+        assert_eq!(expiration, token.signed_at() + Duration::minutes(14));
+
+        let json: serde_json::Value = serde_json::from_str(&credential.to_json())?;
+        assert_eq!(json["apiVersion"], EXEC_CREDENTIAL_V1BETA1);
+        assert_eq!(json["kind"], "ExecCredential");
+        assert_eq!(json["status"]["token"], token.as_str());
+        assert_eq!(
+            json["status"]["expirationTimestamp"],
+            expiration.to_rfc3339_opts(SecondsFormat::Secs, true)
+        );
+        assert!(!format!("{:?}", credential).contains(token.as_str()));
+        assert!(!format!("{:?}", token).contains(token.as_str()));
+
+        let margin = std::time::Duration::from_secs(300);
+        let credential = ExecCredential::for_eks_token_with_margin(&token, margin);
+        assert_eq!(
+            credential.status.expiration_timestamp,
+            Some(token.signed_at() + Duration::minutes(10))
+        );
+        let credential =
+            ExecCredential::for_eks_token_with_margin(&token, std::time::Duration::from_secs(3600));
+        assert_eq!(
+            credential.status.expiration_timestamp,
+            Some(token.signed_at())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn exec_info_is_parsed() -> Result<(), crate::Error> {
+        let info: ExecInfo = r#"{
+            "kind": "ExecCredential",
+            "apiVersion": "client.authentication.k8s.io/v1beta1",
+            "spec": {
+                "interactive": false,
+                "cluster": {
+                    "server": "https://example.eks.amazonaws.com",
+                    "certificate-authority-data": "Q0E=",
+                    "config": {
+                        "clusterName": "my-cluster",
+                        "role_arn": "arn:aws:iam::123456789012:role/admin"
+                    }
+                }
+            }
+        }"#
+        .parse()?;
+
+        assert_eq!(info.cluster_name(), Some("my-cluster"));
+        assert_eq!(
+            info.role_arn(),
+            Some("arn:aws:iam::123456789012:role/admin")
+        );
+        let cluster = info.spec.cluster.as_ref().unwrap();
+        assert_eq!(cluster.certificate_authority_data.as_deref(), Some("Q0E="));
+
+        let credential = info.respond(ExecCredential::new("token".to_string(), None))?;
+        assert_eq!(credential.api_version, EXEC_CREDENTIAL_V1BETA1);
+
+        let v1 = ExecInfo {
+            api_version: EXEC_CREDENTIAL_V1.to_string(),
+            ..info.clone()
+        };
+        let credential = v1.respond(ExecCredential::new("token".to_string(), None))?;
+        assert_eq!(credential.api_version, EXEC_CREDENTIAL_V1);
+        assert_eq!(negotiate_api_version(None)?, EXEC_CREDENTIAL_V1BETA1);
+
+        let unsupported = ExecInfo {
+            api_version: "client.authentication.k8s.io/v1alpha1".to_string(),
+            ..info
+        };
+        assert!(unsupported
+            .respond(ExecCredential::new("token".to_string(), None))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn eks_clusters_are_parsed_from_arns() -> Result<(), crate::Error> {
+        let cluster: EksCluster =
+            "arn:aws-cn:eks:cn-north-1:123456789012:cluster/my-cluster".parse()?;
+        assert_eq!(cluster.name, "my-cluster");
+        assert_eq!(cluster.region, Some(Region::CnNorth1));
+        assert_eq!(cluster.partition(), Partition::AwsCn);
+
+        let cluster: EksCluster = "my-cluster".parse()?;
+        assert_eq!(cluster, EksCluster::new("my-cluster", None));
+
+        assert!("arn:aws:eks:cn-north-1:123456789012:cluster/my-cluster"
+            .parse::<EksCluster>()
+            .is_err());
+        assert!("arn:aws:iam::123456789012:role/my-cluster"
+            .parse::<EksCluster>()
+            .is_err());
+        assert!(
+            "arn:aws:eks:us-west-2:123456789012:nodegroup/my-cluster/ng/1"
+                .parse::<EksCluster>()
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn credentials_are_generated_for_many_clusters() -> Result<(), crate::Error> {
+        use rusoto_mock::MockRequestDispatcher;
+
+        let credentials = crate::client::tests::credentials().await?;
+        let dispatcher = MockRequestDispatcher::with_status(403)
+            .with_body("<ErrorResponse><Error><Code>AccessDenied</Code></Error></ErrorResponse>");
+        let clusters = vec![
+            ClusterRequest::new(EksCluster::new("first", Some(Region::UsWest2))),
+            ClusterRequest::new(EksCluster::new("second", Some(Region::EuWest1)))
+                .with_role("arn:aws:iam::123456789012:role/denied"),
+        ];
+
+        let results = ExecCredential::for_clusters_with_dispatcher(
+            Arc::new(dispatcher),
+            &credentials,
+            &clusters,
+        )
+        .await;
+        assert_eq!(results.len(), 2);
+        let first = results[0].as_ref().unwrap();
+        let token: EksToken = first.status.token.parse()?;
+        assert!(!token.is_stale(DEFAULT_EXPIRY_MARGIN));
+        assert!(results[1].is_err());
+        Ok(())
+    }
+}
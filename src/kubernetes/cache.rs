@@ -0,0 +1,276 @@
+//! Reusing EKS tokens for as long as they remain valid
+//!
+//! Signing a token is cheap, but resolving the credentials to sign it with often is not.
+//! Running a credential plugin for every kubectl command, like scripts and controllers tend to
+//! do, can also hit rate limits of STS when roles are assumed. kubectl only caches tokens for
+//! the lifetime of its process, so a cache needs to live outside of it.
+use std::collections::HashMap;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::Utc;
+use log::debug;
+use rusoto_core::credential::AwsCredentials;
+use sha2::{Digest, Sha256};
+
+use super::{EksCluster, ExecCredential};
+use crate::sink::FileSink;
+use crate::Error;
+
+/// How long a cached credential must remain valid at least for it to be reused
+pub const DEFAULT_MARGIN: Duration = Duration::from_secs(60);
+
+/// Credentials cached in memory and optionally in files, keyed by cluster, the AWS credentials
+/// they were signed with and role
+///
+/// Credentials are reused until less than the margin is left before their
+/// `expirationTimestamp`. Credentials without an expiry are never cached.
+#[derive(Debug)]
+pub struct TokenCache {
+    directory: Option<PathBuf>,
+    margin: Duration,
+    entries: Mutex<HashMap<String, ExecCredential>>,
+}
+
+impl TokenCache {
+    /// A cache that only lives as long as the process
+    pub fn in_memory() -> Self {
+        Self {
+            directory: None,
+            margin: DEFAULT_MARGIN,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// A cache that also stores credentials as files in `directory`, which is created when the
+    /// first credential is stored. On Unix, only the user can access the directory.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        Self {
+            directory: Some(directory.into()),
+            ..Self::in_memory()
+        }
+    }
+
+    /// Only reuse credentials that remain valid for at least `margin`
+    pub fn with_margin(mut self, margin: Duration) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Directory the credentials are stored in, if any
+    pub fn directory(&self) -> Option<&Path> {
+        self.directory.as_deref()
+    }
+
+    /// The cached credential for `cluster`, signed with `credentials` or the role `role_arn`
+    /// assumed with them, if it is still valid for long enough
+    pub fn get(
+        &self,
+        cluster: &EksCluster,
+        credentials: &AwsCredentials,
+        role_arn: Option<&str>,
+    ) -> Result<Option<ExecCredential>, Error> {
+        let key = key(cluster, credentials, role_arn);
+        if let Some(credential) = self.entries().get(&key).filter(|c| self.is_fresh(c)) {
+            return Ok(Some(credential.clone()));
+        }
+
+        let path = match self.path(&key) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match serde_json::from_str::<ExecCredential>(&json) {
+            Ok(credential) if self.is_fresh(&credential) => {
+                self.entries().insert(key, credential.clone());
+                Ok(Some(credential))
+            }
+            Ok(_) => Ok(None),
+            Err(e) => {
+                debug!("Ignoring unreadable cached token {}: {}", path.display(), e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Cache `credential` for `cluster`, `credentials` and `role_arn`
+    pub fn insert(
+        &self,
+        cluster: &EksCluster,
+        credentials: &AwsCredentials,
+        role_arn: Option<&str>,
+        credential: &ExecCredential,
+    ) -> Result<(), Error> {
+        if credential.status.expiration_timestamp.is_none() {
+            return Ok(());
+        }
+        let key = key(cluster, credentials, role_arn);
+        if let (Some(directory), Some(path)) = (&self.directory, self.path(&key)) {
+            create_directory(directory)?;
+            FileSink::new(path).write(credential.to_json().as_bytes())?;
+        }
+        self.entries().insert(key, credential.clone());
+        Ok(())
+    }
+
+    /// The cached credential for `cluster`, `credentials` and `role_arn`, or the one `generate`
+    /// produces if there is none that is still valid for long enough
+    pub async fn get_or_insert_with<F, Fut>(
+        &self,
+        cluster: &EksCluster,
+        credentials: &AwsCredentials,
+        role_arn: Option<&str>,
+        generate: F,
+    ) -> Result<ExecCredential, Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<ExecCredential, Error>>,
+    {
+        if let Some(credential) = self.get(cluster, credentials, role_arn)? {
+            debug!("Reusing cached token for {}", cluster.name);
+            return Ok(credential);
+        }
+        let credential = generate().await?;
+        self.insert(cluster, credentials, role_arn, &credential)?;
+        Ok(credential)
+    }
+
+    fn is_fresh(&self, credential: &ExecCredential) -> bool {
+        let refresh_at = chrono::Duration::from_std(self.margin)
+            .ok()
+            .zip(credential.status.expiration_timestamp)
+            .and_then(|(margin, expiration)| expiration.checked_sub_signed(margin));
+        matches!(refresh_at, Some(refresh_at) if Utc::now() < refresh_at)
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<String, ExecCredential>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        let name = format!("{}.json", hex::encode(Sha256::digest(key.as_bytes())));
+        self.directory
+            .as_ref()
+            .map(|directory| directory.join(name))
+    }
+}
+
+/// Tokens differ by the name and region of the cluster and by who signed them
+fn key(cluster: &EksCluster, credentials: &AwsCredentials, role_arn: Option<&str>) -> String {
+    format!(
+        "{}\n{:?}\n{}\n{}",
+        cluster.name,
+        cluster.region,
+        credentials.aws_access_key_id(),
+        role_arn.unwrap_or_default()
+    )
+}
+
+/// Create the cache directory, only accessible to the user on Unix as it holds tokens
+fn create_directory(directory: &Path) -> Result<(), Error> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    Ok(builder.create(directory)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    use rusoto_core::Region;
+
+    fn credential(token: &str, valid_for: i64) -> ExecCredential {
+        let expiration = Utc::now() + chrono::Duration::seconds(valid_for);
+        ExecCredential::new(token.to_string(), Some(expiration))
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn tokens_are_reused_until_they_are_about_to_expire() -> Result<(), crate::Error> {
+        let directory = env::temp_dir().join(format!(
+            "aws-auth-payload-token-cache-{}",
+            std::process::id()
+        ));
+        let cache = TokenCache::new(&directory);
+        let cluster = EksCluster::new("my-cluster", None);
+        let other = EksCluster::new("other", None);
+        let credentials = crate::client::tests::credentials().await?;
+
+        let first = cache
+            .get_or_insert_with(&cluster, &credentials, None, || async {
+                Ok(credential("a", 600))
+            })
+            .await?;
+        let second = cache
+            .get_or_insert_with(&cluster, &credentials, None, || async {
+                Ok(credential("b", 600))
+            })
+            .await?;
+        assert_eq!(second, first);
+
+        let from_disk = TokenCache::new(&directory);
+        let cached = from_disk.get(&cluster, &credentials, None)?.unwrap();
+        assert_eq!(cached.status.token, first.status.token);
+        assert_eq!(
+            from_disk.get(&cluster, &credentials, Some("arn:aws:iam::1:role/x"))?,
+            None
+        );
+
+        // Tokens of the same cluster in other regions or signed by others are not reused
+        let regional = EksCluster::new("my-cluster", Some(Region::EuWest1));
+        assert_eq!(from_disk.get(&regional, &credentials, None)?, None);
+        let someone_else = AwsCredentials::new("AKIDOTHER", "secret", None, None);
+        assert_eq!(from_disk.get(&cluster, &someone_else, None)?, None);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&directory)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        cache.insert(&cluster, &credentials, None, &credential("c", 30))?;
+        let renewed = cache
+            .get_or_insert_with(&cluster, &credentials, None, || async {
+                Ok(credential("d", 600))
+            })
+            .await?;
+        assert_eq!(renewed.status.token, "d");
+
+        let memory = TokenCache::in_memory().with_margin(Duration::from_secs(0));
+        memory.insert(&cluster, &credentials, None, &credential("e", 30))?;
+        memory.insert(
+            &other,
+            &credentials,
+            None,
+            &ExecCredential::new("f".to_string(), None),
+        )?;
+        assert_eq!(
+            memory
+                .get(&cluster, &credentials, None)?
+                .unwrap()
+                .status
+                .token,
+            "e"
+        );
+        assert_eq!(memory.get(&other, &credentials, None)?, None);
+
+        fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,369 @@
+//! Generating kubeconfig entries that obtain EKS tokens through a credential plugin
+//!
+//! The documents are serialized as JSON, which kubectl reads like any YAML kubeconfig.
+//!
+//! With the `eks` feature, [`Kubeconfig::describe`] looks up the endpoint and certificate
+//! authority of a cluster, like `aws eks update-kubeconfig` does.
+use serde::{Deserialize, Serialize};
+
+use super::{EksCluster, EXEC_CREDENTIAL_V1BETA1};
+#[cfg(feature = "eks")]
+use crate::Error;
+
+/// Command the `exec` stanzas run unless another one is configured
+pub const DEFAULT_COMMAND: &str = "aws-auth-payload";
+
+/// A complete kubeconfig
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Kubeconfig {
+    /// Always `v1`
+    pub api_version: String,
+    /// Always `Config`
+    pub kind: String,
+    /// Clusters to connect to
+    pub clusters: Vec<NamedCluster>,
+    /// Combinations of clusters and users
+    pub contexts: Vec<NamedContext>,
+    /// Context used when none is selected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_context: Option<String>,
+    /// Users to authenticate as
+    pub users: Vec<NamedUser>,
+}
+
+/// A cluster in a [`Kubeconfig`]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct NamedCluster {
+    /// Name the cluster is referred to by in contexts
+    pub name: String,
+    /// How to connect to the cluster
+    pub cluster: Cluster,
+}
+
+/// How to connect to a cluster
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Cluster {
+    /// Address of the API server
+    pub server: String,
+    /// Base64 encoded PEM certificate authorities of the API server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate_authority_data: Option<String>,
+}
+
+/// A context in a [`Kubeconfig`]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct NamedContext {
+    /// Name of the context
+    pub name: String,
+    /// The cluster and user of the context
+    pub context: Context,
+}
+
+/// A combination of a cluster and a user
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Context {
+    /// Name of the cluster
+    pub cluster: String,
+    /// Name of the user
+    pub user: String,
+}
+
+/// An entry of the `users` of a [`Kubeconfig`]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct NamedUser {
+    /// Name the user is referred to by in contexts
+    pub name: String,
+    /// How the user authenticates
+    pub user: User,
+}
+
+/// How a user authenticates
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct User {
+    /// The credential plugin to run
+    pub exec: ExecConfig,
+}
+
+/// The `exec` stanza of a user, which runs a credential plugin to obtain tokens
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecConfig {
+    /// API version of the `ExecCredential` the plugin must print
+    pub api_version: String,
+    /// Command to run, looked up in `PATH` if it is not a path
+    pub command: String,
+    /// Arguments of the command
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables to set for the command
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<ExecEnvVar>,
+    /// Whether the plugin may use standard input: `Never`, `IfAvailable` or `Always`
+    pub interactive_mode: String,
+    /// Whether to pass the cluster to the plugin in `KUBERNETES_EXEC_INFO`
+    #[serde(default)]
+    pub provide_cluster_info: bool,
+}
+
+/// An environment variable of an [`ExecConfig`]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ExecEnvVar {
+    /// Name of the variable
+    pub name: String,
+    /// Value of the variable
+    pub value: String,
+}
+
+impl ExecConfig {
+    /// Run `aws-auth-payload eks token` for `cluster`, assuming `role_arn` if it is set
+    ///
+    /// The region of the cluster is passed with `--region` if it is known.
+    pub fn for_cluster(cluster: &EksCluster, role_arn: Option<&str>) -> Self {
+        let mut args = vec![
+            "eks".to_string(),
+            "token".to_string(),
+            "--cluster-name".to_string(),
+            cluster.name.clone(),
+        ];
+        if let Some(region) = &cluster.region {
+            args.extend(vec!["--region".to_string(), region.name().to_string()]);
+        }
+        if let Some(role_arn) = role_arn {
+            args.extend(vec!["--role-arn".to_string(), role_arn.to_string()]);
+        }
+        Self {
+            api_version: EXEC_CREDENTIAL_V1BETA1.to_string(),
+            command: DEFAULT_COMMAND.to_string(),
+            args,
+            env: Vec::new(),
+            interactive_mode: "IfAvailable".to_string(),
+            provide_cluster_info: false,
+        }
+    }
+
+    /// Ask the plugin for credentials of `api_version`, such as
+    /// [`EXEC_CREDENTIAL_V1`](super::EXEC_CREDENTIAL_V1)
+    pub fn with_api_version(mut self, api_version: &str) -> Self {
+        self.api_version = api_version.to_string();
+        self
+    }
+
+    /// Run `command` instead of [`DEFAULT_COMMAND`]
+    pub fn with_command(mut self, command: &str) -> Self {
+        self.command = command.to_string();
+        self
+    }
+
+    /// Set the environment variable `name` to `value` for the command, such as `AWS_PROFILE`
+    pub fn with_env(mut self, name: &str, value: &str) -> Self {
+        self.env.retain(|var| var.name != name);
+        self.env.push(ExecEnvVar {
+            name: name.to_string(),
+            value: value.to_string(),
+        });
+        self
+    }
+
+    /// The `users` entry named `name` that runs this command
+    pub fn user(&self, name: &str) -> NamedUser {
+        NamedUser {
+            name: name.to_string(),
+            user: User { exec: self.clone() },
+        }
+    }
+}
+
+impl NamedUser {
+    /// Serialize the entry as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a kubeconfig user to be serializable")
+    }
+}
+
+impl Kubeconfig {
+    /// A kubeconfig with a single cluster, user and context, which are all called `name`
+    ///
+    /// `certificate_authority_data` is the base64 encoded PEM certificate authority of the
+    /// cluster, as returned by `eks:DescribeCluster`.
+    pub fn for_cluster(
+        name: &str,
+        server: &str,
+        certificate_authority_data: Option<&str>,
+        exec: ExecConfig,
+    ) -> Self {
+        Self {
+            api_version: "v1".to_string(),
+            kind: "Config".to_string(),
+            clusters: vec![NamedCluster {
+                name: name.to_string(),
+                cluster: Cluster {
+                    server: server.to_string(),
+                    certificate_authority_data: certificate_authority_data.map(str::to_string),
+                },
+            }],
+            contexts: vec![NamedContext {
+                name: name.to_string(),
+                context: Context {
+                    cluster: name.to_string(),
+                    user: name.to_string(),
+                },
+            }],
+            current_context: Some(name.to_string()),
+            users: vec![exec.user(name)],
+        }
+    }
+
+    /// Serialize the kubeconfig as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a kubeconfig to be serializable")
+    }
+}
+
+#[cfg(feature = "eks")]
+impl Kubeconfig {
+    /// Describe `cluster` with `eks:DescribeCluster` and build a kubeconfig for it
+    ///
+    /// The cluster, user and context are named after the ARN of the cluster. The cluster is
+    /// described in the default region if its region is not known, and the user passes that
+    /// region to the token command.
+    pub async fn describe(
+        credentials: &rusoto_core::credential::AwsCredentials,
+        cluster: &EksCluster,
+        role_arn: Option<&str>,
+    ) -> Result<Self, Error> {
+        let dispatcher = rusoto_core::request::HttpClient::new()?;
+        Self::describe_with_dispatcher(dispatcher, credentials, cluster, role_arn).await
+    }
+
+    pub(crate) async fn describe_with_dispatcher<D>(
+        dispatcher: D,
+        credentials: &rusoto_core::credential::AwsCredentials,
+        cluster: &EksCluster,
+        role_arn: Option<&str>,
+    ) -> Result<Self, Error>
+    where
+        D: rusoto_core::request::DispatchSignedRequest + Send + Sync + 'static,
+    {
+        use rusoto_core::credential::StaticProvider;
+        use rusoto_eks::{DescribeClusterRequest, Eks, EksClient};
+
+        let cluster = EksCluster {
+            region: Some(cluster.region.clone().unwrap_or_default()),
+            ..cluster.clone()
+        };
+        let client = EksClient::new_with(
+            dispatcher,
+            StaticProvider::from(credentials.clone()),
+            crate::region::service_region(&cluster.region.clone().unwrap_or_default(), "eks"),
+        );
+        let described = client
+            .describe_cluster(DescribeClusterRequest {
+                name: cluster.name.clone(),
+            })
+            .await?
+            .cluster
+            .ok_or_else(|| {
+                Error::GenericError("DescribeCluster returned no cluster".to_string())
+            })?;
+
+        let endpoint = described.endpoint.ok_or_else(|| {
+            Error::GenericError(format!("EKS cluster {} has no endpoint yet", cluster))
+        })?;
+        let name = described.arn.unwrap_or_else(|| cluster.name.clone());
+        let certificate_authority = described
+            .certificate_authority
+            .and_then(|certificate| certificate.data);
+        Ok(Self::for_cluster(
+            &name,
+            &endpoint,
+            certificate_authority.as_deref(),
+            ExecConfig::for_cluster(&cluster, role_arn),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kubeconfigs_run_the_token_command() -> Result<(), crate::Error> {
+        let arn = "arn:aws:eks:us-west-2:123456789012:cluster/my-cluster";
+        let cluster: EksCluster = arn.parse()?;
+        let exec = ExecConfig::for_cluster(&cluster, Some("arn:aws:iam::123456789012:role/x"))
+            .with_env("AWS_PROFILE", "prod");
+
+        let config = Kubeconfig::for_cluster(arn, "https://example.com", Some("Q0E="), exec);
+        let json: serde_json::Value = serde_json::from_str(&config.to_json())?;
+        assert_eq!(json["current-context"], arn);
+        assert_eq!(
+            json["clusters"][0]["cluster"]["certificate-authority-data"],
+            "Q0E="
+        );
+        assert_eq!(json["contexts"][0]["context"]["user"], arn);
+
+        let exec = &json["users"][0]["user"]["exec"];
+        assert_eq!(exec["apiVersion"], EXEC_CREDENTIAL_V1BETA1);
+        assert_eq!(exec["command"], DEFAULT_COMMAND);
+        assert_eq!(
+            exec["args"],
+            serde_json::json!([
+                "eks",
+                "token",
+                "--cluster-name",
+                "my-cluster",
+                "--region",
+                "us-west-2",
+                "--role-arn",
+                "arn:aws:iam::123456789012:role/x"
+            ])
+        );
+        assert_eq!(exec["env"][0]["name"], "AWS_PROFILE");
+        Ok(())
+    }
+
+    #[cfg(feature = "eks")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn kubeconfigs_are_built_from_described_clusters() -> Result<(), crate::Error> {
+        use rusoto_core::Region;
+        use rusoto_mock::MockRequestDispatcher;
+
+        let arn = "arn:aws:eks:eu-west-1:123456789012:cluster/my-cluster";
+        let dispatcher = MockRequestDispatcher::with_status(200)
+            .with_body(
+                &serde_json::json!({
+                    "cluster": {
+                        "arn": arn,
+                        "name": "my-cluster",
+                        "endpoint": "https://ABCDEF.gr7.eu-west-1.eks.amazonaws.com",
+                        "certificateAuthority": { "data": "Q0E=" }
+                    }
+                })
+                .to_string(),
+            )
+            .with_request_checker(|request| {
+                assert_eq!(request.method, "GET");
+                assert_eq!(request.path, "/clusters/my-cluster");
+            });
+        let credentials = crate::client::tests::credentials().await?;
+        let cluster = EksCluster::new("my-cluster", Some(Region::EuWest1));
+
+        let config =
+            Kubeconfig::describe_with_dispatcher(dispatcher, &credentials, &cluster, None).await?;
+        assert_eq!(config.current_context.as_deref(), Some(arn));
+        assert_eq!(
+            config.clusters[0].cluster,
+            Cluster {
+                server: "https://ABCDEF.gr7.eu-west-1.eks.amazonaws.com".to_string(),
+                certificate_authority_data: Some("Q0E=".to_string()),
+            }
+        );
+        assert_eq!(
+            config.users[0].user.exec,
+            ExecConfig::for_cluster(&cluster, None)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! Kubernetes `Secret` manifests holding tokens, ready for `kubectl apply`
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// Key of the token in the data of a secret, unless configured otherwise
+pub const DEFAULT_KEY: &str = "token";
+
+/// A `v1` `Secret`
+#[derive(Clone, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretManifest {
+    /// Always `v1`
+    pub api_version: String,
+    /// Always `Secret`
+    pub kind: String,
+    /// Name, namespace and labels of the secret
+    pub metadata: SecretMetadata,
+    /// Type of the secret, such as `Opaque`
+    #[serde(rename = "type")]
+    pub secret_type: String,
+    /// Base64 encoded values of the secret
+    pub data: BTreeMap<String, String>,
+}
+
+/// The `metadata` of a [`SecretManifest`]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SecretMetadata {
+    /// Name of the secret
+    pub name: String,
+    /// Namespace of the secret. kubectl uses the namespace of the current context if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Labels of the secret
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl fmt::Debug for SecretManifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<_> = self.data.keys().collect();
+        f.debug_struct("SecretManifest")
+            .field("metadata", &self.metadata)
+            .field("secret_type", &self.secret_type)
+            .field("data", &keys)
+            .finish()
+    }
+}
+
+impl SecretManifest {
+    /// An `Opaque` secret named `name` holding `token`, such as an EKS or Vault token, under
+    /// [`DEFAULT_KEY`]
+    ///
+    /// The name must be a valid DNS subdomain, as Kubernetes requires.
+    pub fn new(name: &str, token: &str) -> Result<Self, Error> {
+        validate_name(name)?;
+        let mut data = BTreeMap::new();
+        data.insert(DEFAULT_KEY.to_string(), base64::encode(token));
+        Ok(Self {
+            api_version: "v1".to_string(),
+            kind: "Secret".to_string(),
+            metadata: SecretMetadata {
+                name: name.to_string(),
+                namespace: None,
+                labels: BTreeMap::new(),
+            },
+            secret_type: "Opaque".to_string(),
+            data,
+        })
+    }
+
+    /// Put the secret in `namespace`
+    pub fn with_namespace(mut self, namespace: &str) -> Result<Self, Error> {
+        validate_label_value("namespace", namespace, false)?;
+        self.metadata.namespace = Some(namespace.to_string());
+        Ok(self)
+    }
+
+    /// Set the label `key` to `value`
+    pub fn with_label(mut self, key: &str, value: &str) -> Result<Self, Error> {
+        let name = key.rsplit('/').next().unwrap_or_default();
+        validate_label_value("label", name, false)?;
+        validate_label_value("label value", value, true)?;
+        self.metadata
+            .labels
+            .insert(key.to_string(), value.to_string());
+        Ok(self)
+    }
+
+    /// Store the token under `key` instead of [`DEFAULT_KEY`]
+    pub fn with_key(mut self, key: &str) -> Result<Self, Error> {
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        {
+            return Err(Error::GenericError(format!("Invalid secret key {:?}", key)));
+        }
+        let data = std::mem::take(&mut self.data);
+        self.data = data
+            .into_values()
+            .map(|value| (key.to_string(), value))
+            .collect();
+        Ok(self)
+    }
+
+    /// Use the secret type `secret_type`, such as `kubernetes.io/service-account-token`
+    pub fn with_type(mut self, secret_type: &str) -> Self {
+        self.secret_type = secret_type.to_string();
+        self
+    }
+
+    /// Serialize the manifest as JSON, which `kubectl apply -f -` accepts
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a secret to be serializable")
+    }
+}
+
+/// Check that `name` is a DNS subdomain as defined by RFC 1123
+fn validate_name(name: &str) -> Result<(), Error> {
+    let valid = !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::GenericError(format!(
+            "Invalid secret name {:?}",
+            name
+        )))
+    }
+}
+
+/// Check that `value` is at most 63 alphanumeric characters, `-`, `_` or `.`, beginning and
+/// ending with an alphanumeric character
+fn validate_label_value(kind: &str, value: &str, allow_empty: bool) -> Result<(), Error> {
+    let alphanumeric = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric());
+    let valid = (allow_empty && value.is_empty())
+        || (value.len() <= 63
+            && alphanumeric(value.chars().next())
+            && alphanumeric(value.chars().last())
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)));
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::GenericError(format!("Invalid {} {:?}", kind, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_hold_tokens() -> Result<(), crate::Error> {
+        let secret = SecretManifest::new("eks-token", "k8s-aws-v1.abc")?
+            .with_namespace("ci")?
+            .with_label("app.kubernetes.io/managed-by", "aws-auth-payload")?
+            .with_key("bearer")?;
+
+        let json: serde_json::Value = serde_json::from_str(&secret.to_json())?;
+        assert_eq!(json["apiVersion"], "v1");
+        assert_eq!(json["kind"], "Secret");
+        assert_eq!(json["type"], "Opaque");
+        assert_eq!(json["metadata"]["namespace"], "ci");
+        assert_eq!(
+            json["metadata"]["labels"]["app.kubernetes.io/managed-by"],
+            "aws-auth-payload"
+        );
+        assert_eq!(json["data"]["bearer"], base64::encode("k8s-aws-v1.abc"));
+        assert!(!format!("{:?}", secret).contains(&base64::encode("k8s-aws-v1.abc")));
+
+        assert!(SecretManifest::new("Not_Valid", "token").is_err());
+        assert!(SecretManifest::new("ok", "token")?
+            .with_label("app", "-bad")
+            .is_err());
+        Ok(())
+    }
+}
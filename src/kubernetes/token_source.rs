@@ -0,0 +1,212 @@
+//! Authenticating [`kube`](https://docs.rs/kube) clients to EKS with tokens from this library
+//!
+//! This module requires the `kube` feature. Instead of running a credential plugin, the client
+//! signs tokens in process and replaces them shortly before they expire:
+//!
+//! ```rust,no_run
+//! use aws_auth_payload::kubernetes::token_source::{self, EksTokenSource};
+//! use rusoto_core::credential::DefaultCredentialsProvider;
+//!
+//! # async fn run() -> Result<(), aws_auth_payload::Error> {
+//! let cluster = "arn:aws:eks:us-west-2:123456789012:cluster/my-cluster".parse()?;
+//! let source = EksTokenSource::new(DefaultCredentialsProvider::new()?, cluster);
+//! let config = kube::Config::new("https://ABCDEF.gr7.us-west-2.eks.amazonaws.com".parse().unwrap());
+//! let client = token_source::client(config, source)?;
+//! # Ok(())
+//! # }
+//! ```
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use hyper::header::{HeaderValue, AUTHORIZATION};
+use hyper::{Body, Request};
+use kube::client::ConfigExt;
+use rusoto_core::credential::ProvideAwsCredentials;
+use tower::{Layer, Service, ServiceBuilder};
+
+use super::{EksCluster, ExecCredential, TokenCache};
+use crate::Error;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Signs tokens for an EKS cluster with credentials from a provider, reusing each token until
+/// it is about to expire
+#[derive(Debug)]
+pub struct EksTokenSource<P> {
+    credentials: P,
+    cluster: EksCluster,
+    cache: TokenCache,
+}
+
+impl<P> EksTokenSource<P>
+where
+    P: ProvideAwsCredentials + Send + Sync + 'static,
+{
+    /// Sign tokens for `cluster` with credentials from `credentials`
+    pub fn new(credentials: P, cluster: EksCluster) -> Self {
+        Self {
+            credentials,
+            cluster,
+            cache: TokenCache::in_memory(),
+        }
+    }
+
+    /// Replace tokens once less than `margin` is left before they expire
+    pub fn with_margin(mut self, margin: Duration) -> Self {
+        self.cache = self.cache.with_margin(margin);
+        self
+    }
+
+    /// The cluster tokens are signed for
+    pub fn cluster(&self) -> &EksCluster {
+        &self.cluster
+    }
+
+    /// A token that remains valid for at least the margin
+    pub async fn token(&self) -> Result<String, Error> {
+        let credentials = self.credentials.credentials().await?;
+        let credential = self
+            .cache
+            .get_or_insert_with(&self.cluster, &credentials, None, || async {
+                Ok(ExecCredential::for_eks_token(
+                    &self.cluster.token(&credentials),
+                ))
+            })
+            .await?;
+        Ok(credential.status.token)
+    }
+}
+
+/// Build a `kube::Client` for `config` that authenticates with tokens from `source`
+///
+/// Any authentication configured in `config` is ignored. This must be called from within a
+/// Tokio runtime.
+pub fn client<P>(config: kube::Config, source: EksTokenSource<P>) -> Result<kube::Client, Error>
+where
+    P: ProvideAwsCredentials + Send + Sync + 'static,
+{
+    let https = config.rustls_https_connector()?;
+    let service = ServiceBuilder::new()
+        .layer(config.base_uri_layer())
+        .layer(config.extra_headers_layer()?)
+        .layer(EksAuthLayer::new(source))
+        .service(hyper::Client::builder().build(https));
+    Ok(kube::Client::new(service, config.default_namespace))
+}
+
+/// A `tower` layer adding tokens from an [`EksTokenSource`] to requests
+#[derive(Debug)]
+pub struct EksAuthLayer<P> {
+    source: Arc<EksTokenSource<P>>,
+}
+
+impl<P> EksAuthLayer<P> {
+    /// Add tokens from `source` to requests
+    pub fn new(source: EksTokenSource<P>) -> Self {
+        Self {
+            source: Arc::new(source),
+        }
+    }
+}
+
+impl<P> Clone for EksAuthLayer<P> {
+    fn clone(&self) -> Self {
+        Self {
+            source: Arc::clone(&self.source),
+        }
+    }
+}
+
+impl<S, P> Layer<S> for EksAuthLayer<P> {
+    type Service = EksAuth<S, P>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        EksAuth {
+            inner,
+            source: Arc::clone(&self.source),
+        }
+    }
+}
+
+/// A `tower` service setting the `Authorization` header of requests to a token from an
+/// [`EksTokenSource`]
+#[derive(Debug)]
+pub struct EksAuth<S, P> {
+    inner: S,
+    source: Arc<EksTokenSource<P>>,
+}
+
+impl<S: Clone, P> Clone for EksAuth<S, P> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            source: Arc::clone(&self.source),
+        }
+    }
+}
+
+impl<S, P> Service<Request<Body>> for EksAuth<S, P>
+where
+    S: Service<Request<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<BoxError>,
+    P: ProvideAwsCredentials + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        // The clone may not be ready, so use the service that was polled and leave the clone
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let source = Arc::clone(&self.source);
+
+        Box::pin(async move {
+            let token = source.token().await.map_err(|e| e.to_string())?;
+            let mut value = HeaderValue::from_str(&format!("Bearer {}", token))?;
+            value.set_sensitive(true);
+            request.headers_mut().insert(AUTHORIZATION, value);
+            inner.call(request).await.map_err(Into::into)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::StatusCode;
+    use rusoto_core::credential::StaticProvider;
+    use rusoto_core::Region;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn kube_clients_send_eks_tokens() -> Result<(), crate::Error> {
+        let address = crate::http::tests::serve(|request| {
+            let authorization = request.headers[AUTHORIZATION].to_str().unwrap();
+            assert!(authorization.starts_with("Bearer k8s-aws-v1."));
+            (StatusCode::OK, r#"{"gitVersion":"v1.28.0"}"#.to_string())
+        });
+        let credentials =
+            StaticProvider::new_minimal("AKIDEXAMPLE".to_string(), "secret".to_string());
+        let source = EksTokenSource::new(
+            credentials,
+            EksCluster::new("my-cluster", Some(Region::UsWest2)),
+        );
+        let first = source.token().await?;
+        assert_eq!(source.token().await?, first);
+
+        let config = kube::Config::new(address.parse().unwrap());
+        let client = client(config, source)?;
+        let version = client.apiserver_version().await?;
+        assert_eq!(version.git_version, "v1.28.0");
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+//! Verifying EKS tokens like the server side of Kubernetes AWS IAM Authenticator
+//!
+//! A token is a pre-signed `GetCallerIdentity` URL. The verifier checks that the URL is one
+//! that can only reach STS and that it is bound to the expected cluster, then sends it to STS to
+//! learn who signed it. Without these checks, a token could make the verifier send requests to
+//! arbitrary hosts, or be replayed against another cluster.
+use std::collections::HashSet;
+
+use chrono::{Duration, Utc};
+use hyper::Uri;
+use log::{debug, info};
+use rusoto_core::request::{DispatchSignedRequest, HttpClient};
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::Region;
+
+use super::{decode_eks_token, parse_amz_date, EKS_TOKEN_VALIDITY_MINUTES};
+use crate::arn::Arn;
+use crate::client::EKS_CLUSTER_ID_HEADER;
+use crate::sts::{self, CallerIdentity};
+use crate::verify::{non_https_port, ALLOWED_PARAMS, REQUIRED_PARAMS};
+use crate::Error;
+
+/// The identity behind a verified token
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VerifiedToken {
+    /// Identity reported by STS
+    pub identity: CallerIdentity,
+    /// ARN to map to Kubernetes users: the ARN of the role for assumed role sessions, and the
+    /// ARN of the caller otherwise
+    pub canonical_arn: Arn,
+    /// ID of the access key the token was signed with
+    pub access_key_id: String,
+}
+
+/// Verifies EKS tokens bound to a cluster
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TokenVerifier {
+    cluster_id: String,
+    allowed_hosts: HashSet<String>,
+}
+
+impl TokenVerifier {
+    /// Verify tokens for the cluster `cluster_id`, sent to any of the
+    /// [`sts::endpoint_hosts`]
+    pub fn new(cluster_id: &str) -> Self {
+        Self {
+            cluster_id: cluster_id.to_string(),
+            allowed_hosts: sts::endpoint_hosts(),
+        }
+    }
+
+    /// Only accept tokens for the given STS hosts, such as `sts.us-west-2.amazonaws.com`
+    pub fn with_allowed_hosts<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.allowed_hosts = hosts
+            .into_iter()
+            .map(|host| host.as_ref().to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// ID of the cluster tokens must be bound to
+    pub fn cluster_id(&self) -> &str {
+        &self.cluster_id
+    }
+
+    /// Check `token` and ask STS who signed it
+    pub async fn verify(&self, token: &str) -> Result<VerifiedToken, Error> {
+        let dispatcher = HttpClient::new()?;
+        self.verify_with_dispatcher(&dispatcher, token).await
+    }
+
+    pub(crate) async fn verify_with_dispatcher<D>(
+        &self,
+        dispatcher: &D,
+        token: &str,
+    ) -> Result<VerifiedToken, Error>
+    where
+        D: DispatchSignedRequest,
+    {
+        info!("Verifying EKS token");
+        let checked = self.check(token)?;
+
+        let mut request = SignedRequest::new(
+            "GET",
+            "sts",
+            &Region::Custom {
+                name: "us-east-1".to_string(),
+                endpoint: format!("https://{}", checked.host),
+            },
+            "/",
+        );
+        request.set_hostname(Some(checked.host.clone()));
+        request.canonical_query_string = checked.query;
+        request.add_header(EKS_CLUSTER_ID_HEADER, &self.cluster_id);
+
+        let response = sts::dispatch(dispatcher, request).await?;
+        let identity = sts::parse_caller_identity(&response.body)?;
+        debug!("Verified EKS token of {}", identity.arn);
+        Ok(VerifiedToken {
+            canonical_arn: identity
+                .arn
+                .role_arn()
+                .unwrap_or_else(|| identity.arn.clone()),
+            identity,
+            access_key_id: checked.access_key_id,
+        })
+    }
+
+    /// Validate everything about `token` that can be validated without calling STS
+    fn check(&self, token: &str) -> Result<CheckedToken, Error> {
+        let invalid = |reason: String| Err(Error::InvalidEksToken(reason));
+
+        let url = decode_eks_token(token)?;
+        let uri: Uri = match url.parse() {
+            Ok(uri) => uri,
+            Err(_) => return invalid("not a URL".to_string()),
+        };
+        if uri.scheme_str() != Some("https") {
+            return invalid("URL is not HTTPS".to_string());
+        }
+        let host = match uri.host() {
+            Some(host) => host.to_string(),
+            None => return invalid("URL has no host".to_string()),
+        };
+        if let Some(port) = non_https_port(&uri) {
+            return invalid(format!("URL has the port {}", port));
+        }
+        if !self.allowed_hosts.contains(&host) {
+            return invalid(format!("{} is not an allowed STS host", host));
+        }
+        if uri.path() != "/" {
+            return invalid("URL path is not /".to_string());
+        }
+
+        let query = uri.query().unwrap_or_default();
+        let params: Vec<(String, String)> = match serde_urlencoded::from_str(query) {
+            Ok(params) => params,
+            Err(_) => return invalid("malformed query".to_string()),
+        };
+        let mut seen = HashSet::new();
+        for (name, _) in &params {
+            if !ALLOWED_PARAMS.contains(&name.as_str()) {
+                return invalid(format!("unexpected query parameter {}", name));
+            }
+            if !seen.insert(name.as_str()) {
+                return invalid(format!("duplicate query parameter {}", name));
+            }
+        }
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        if let Some(missing) = REQUIRED_PARAMS.iter().find(|name| param(name).is_none()) {
+            return invalid(format!("missing query parameter {}", missing));
+        }
+
+        if param("Action") != Some("GetCallerIdentity") {
+            return invalid("action is not GetCallerIdentity".to_string());
+        }
+        if param("X-Amz-Expires")
+            .and_then(|e| e.parse::<u64>().ok())
+            .is_none()
+        {
+            return invalid("malformed X-Amz-Expires".to_string());
+        }
+        let signed_headers = param("X-Amz-SignedHeaders").unwrap_or_default();
+        if !signed_headers
+            .split(';')
+            .any(|h| h == EKS_CLUSTER_ID_HEADER)
+        {
+            return invalid(format!("{} is not signed", EKS_CLUSTER_ID_HEADER));
+        }
+        let signed_at = parse_amz_date(param("X-Amz-Date").unwrap_or_default())?;
+        if Utc::now() > signed_at + Duration::minutes(EKS_TOKEN_VALIDITY_MINUTES) {
+            return Err(Error::EksTokenExpired);
+        }
+        let access_key_id = param("X-Amz-Credential")
+            .unwrap_or_default()
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        Ok(CheckedToken {
+            host,
+            query: query.to_string(),
+            access_key_id,
+        })
+    }
+}
+
+/// The parts of a token that passed [`TokenVerifier::check`]
+struct CheckedToken {
+    host: String,
+    query: String,
+    access_key_id: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusoto_mock::MockRequestDispatcher;
+
+    use crate::client::{eks_token, presigned_url, EKS_TOKEN_PREFIX};
+    use crate::sts::tests::CALLER_IDENTITY_RESPONSE;
+
+    fn encode(url: &str) -> String {
+        format!(
+            "{}{}",
+            EKS_TOKEN_PREFIX,
+            base64::encode_config(url, base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn tokens_are_verified_with_sts() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let token = eks_token(&credentials, "my-cluster", Some(Region::UsWest2));
+        let dispatcher = MockRequestDispatcher::with_status(200)
+            .with_body(CALLER_IDENTITY_RESPONSE)
+            .with_request_checker(|request| {
+                assert_eq!(request.method, "GET");
+                assert_eq!(request.hostname(), "sts.us-west-2.amazonaws.com");
+                assert!(request
+                    .canonical_query_string()
+                    .contains("Action=GetCallerIdentity"));
+                assert_eq!(
+                    request.headers["x-k8s-aws-id"],
+                    vec![b"my-cluster".to_vec()]
+                );
+            });
+
+        let verified = TokenVerifier::new("my-cluster")
+            .verify_with_dispatcher(&dispatcher, token.as_str())
+            .await?;
+        assert_eq!(
+            verified.canonical_arn.to_string(),
+            "arn:aws:iam::123456789012:role/my-role"
+        );
+        assert_eq!(verified.identity.account, "123456789012");
+        assert_eq!(verified.access_key_id, credentials.aws_access_key_id());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn malicious_tokens_are_rejected() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let verifier = TokenVerifier::new("my-cluster");
+        let url = url::Url::parse(&presigned_url(
+            &credentials,
+            Some(Region::UsWest2),
+            [(EKS_CLUSTER_ID_HEADER, "my-cluster")]
+                .iter()
+                .cloned()
+                .collect(),
+            None,
+        ))
+        .unwrap();
+        assert!(verifier.check(&encode(url.as_str())).is_ok());
+        let default_port = url.as_str().replacen(".com/", ".com:443/", 1);
+        assert!(verifier.check(&encode(&default_port)).is_ok());
+
+        let mut elsewhere = url.clone();
+        elsewhere.set_host(Some("attacker.example.com")).unwrap();
+        let mut port = url.clone();
+        port.set_port(Some(8443)).unwrap();
+        let mut path = url.clone();
+        path.set_path("/other");
+        let duplicate = format!("{}&Action=AssumeRole", url);
+        let unbound = presigned_url(
+            &credentials,
+            Some(Region::UsWest2),
+            Default::default(),
+            None,
+        );
+
+        for url in [
+            elsewhere.to_string(),
+            port.to_string(),
+            path.to_string(),
+            url.as_str().replacen("https", "http", 1),
+            duplicate,
+            unbound,
+        ]
+        .iter()
+        {
+            match verifier.check(&encode(url)) {
+                Err(Error::InvalidEksToken(_)) => {}
+                _ => panic!("{} was not rejected", url),
+            }
+        }
+
+        let mut expired = url.clone();
+        let params: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(k, v)| match k.as_ref() {
+                "X-Amz-Date" => (k.into_owned(), "20200101T000000Z".to_string()),
+                _ => (k.into_owned(), v.into_owned()),
+            })
+            .collect();
+        expired.query_pairs_mut().clear().extend_pairs(params);
+        assert!(matches!(
+            verifier.check(&encode(expired.as_str())),
+            Err(Error::EksTokenExpired)
+        ));
+        Ok(())
+    }
+}
@@ -1,10 +1,50 @@
+pub mod appsync;
+pub mod arn;
 pub mod client;
+#[cfg(feature = "cloudfront")]
+pub mod cloudfront;
+pub mod consul;
+pub mod ec2;
+#[cfg(feature = "ecr")]
+pub mod ecr;
 mod error;
+mod http;
+mod imds;
+pub mod keyspaces;
+pub mod kubernetes;
+pub mod msk;
+pub mod profile;
+#[cfg(feature = "redshift")]
+pub mod redshift;
+pub mod region;
+pub mod s3;
+mod sigv4;
+pub mod sink;
+pub mod sts;
+pub mod teleport;
+#[cfg(any(feature = "consul-client", feature = "vault-client"))]
+pub mod tls;
+#[cfg(feature = "vault-client")]
+pub mod vault;
+#[cfg(feature = "vaultrs")]
+pub mod vaultrs;
+pub mod verify;
 
 #[doc(inline)]
 pub use crate::error::Error;
 
-use rusoto_core::credential::{AwsCredentials, DefaultCredentialsProvider, ProvideAwsCredentials};
+use std::path::Path;
+
+use rusoto_core::credential::{
+    AwsCredentials, ContainerProvider, CredentialsError, DefaultCredentialsProvider,
+    ProvideAwsCredentials, StaticProvider, Variable,
+};
+use rusoto_core::request::DispatchSignedRequest;
+use rusoto_core::Region;
+use rusoto_sts::{StsAssumeRoleSessionCredentialsProvider, StsClient, WebIdentityProvider};
+
+/// Session name used when assuming roles
+const ROLE_SESSION_NAME: &str = "aws-auth-payload";
 
 /// Use the priority documented
 /// [here](https://rusoto.github.io/rusoto/rusoto_credential/struct.ChainProvider.html)
@@ -12,13 +52,187 @@ use rusoto_core::credential::{AwsCredentials, DefaultCredentialsProvider, Provid
 ///
 /// # Limitations
 ///
-/// Assuming roles through profiles is not supported at the moment. See this
-/// [Github issue](https://github.com/rusoto/rusoto/issues/1120)
+/// Assuming roles through profiles is not supported by this chain. See this
+/// [Github issue](https://github.com/rusoto/rusoto/issues/1120). Use
+/// [`profile::get_profile_credentials`] for those profiles.
 pub async fn get_aws_credentials() -> Result<AwsCredentials, Error> {
     let provider = DefaultCredentialsProvider::new()?;
     Ok(provider.credentials().await?)
 }
 
+/// Obtain the credentials of the ECS or Fargate task role
+///
+/// Only the container credentials endpoint advertised through
+/// `AWS_CONTAINER_CREDENTIALS_RELATIVE_URI` or `AWS_CONTAINER_CREDENTIALS_FULL_URI` is
+/// queried. The rest of the default chain is never consulted, so the result does not depend on
+/// whatever else happens to be configured in the environment of the task.
+///
+/// Task role credentials are always temporary: their expiry is available from
+/// [`AwsCredentials::expires_at`] and is guaranteed to be set.
+pub async fn get_task_credentials() -> Result<AwsCredentials, Error> {
+    let provider = ContainerProvider::new();
+    let credentials = provider.credentials().await?;
+    if credentials.expires_at().is_none() {
+        return Err(Error::GenericError(
+            "Container credentials endpoint did not return an expiry".to_string(),
+        ));
+    }
+    Ok(credentials)
+}
+
+/// Exchange the web identity token stored in `token_file` for the credentials of `role_arn`
+///
+/// This calls STS `AssumeRoleWithWebIdentity` explicitly and ignores `AWS_WEB_IDENTITY_TOKEN_FILE`
+/// and `AWS_ROLE_ARN`, so several identities (for example multiple
+/// [IRSA](https://docs.aws.amazon.com/eks/latest/userguide/iam-roles-for-service-accounts.html)
+/// service account tokens) can be used side by side in the same process.
+///
+/// The token file is read on every call, which allows projected tokens to be rotated on disk.
+pub async fn get_web_identity_credentials<P>(
+    role_arn: &str,
+    token_file: P,
+) -> Result<AwsCredentials, Error>
+where
+    P: AsRef<Path>,
+{
+    let provider = WebIdentityProvider::new(
+        Variable::from_text_file(token_file),
+        role_arn,
+        None::<Variable<Option<String>, CredentialsError>>,
+    );
+    Ok(provider.credentials().await?)
+}
+
+/// A role to assume with [`get_role_credentials`]
+#[derive(Clone, Eq, PartialEq)]
+pub struct AssumeRoleOptions {
+    /// ARN of the role to assume
+    pub role_arn: String,
+    /// Name of the role session, which appears in the ARN of the assumed role. Defaults to
+    /// `aws-auth-payload`.
+    pub session_name: Option<String>,
+    /// External ID the trust policy of the role requires, if any
+    pub external_id: Option<String>,
+    /// How long the credentials remain valid. STS defaults to one hour.
+    pub duration: Option<std::time::Duration>,
+    /// Serial number or ARN of the MFA device the trust policy of the role requires, if any
+    pub mfa_serial: Option<String>,
+    /// Current code of the MFA device
+    pub mfa_code: Option<String>,
+}
+
+impl AssumeRoleOptions {
+    /// Assume `role_arn` in a session named `aws-auth-payload`
+    pub fn new(role_arn: &str) -> Self {
+        Self {
+            role_arn: role_arn.to_string(),
+            session_name: None,
+            external_id: None,
+            duration: None,
+            mfa_serial: None,
+            mfa_code: None,
+        }
+    }
+
+    /// Name the role session `session_name`
+    pub fn with_session_name(mut self, session_name: &str) -> Self {
+        self.session_name = Some(session_name.to_string());
+        self
+    }
+
+    /// Pass `external_id` to STS
+    pub fn with_external_id(mut self, external_id: &str) -> Self {
+        self.external_id = Some(external_id.to_string());
+        self
+    }
+
+    /// Ask for credentials that remain valid for `duration`
+    pub fn with_duration(mut self, duration: std::time::Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Authenticate with the `code` of the MFA device `serial`
+    pub fn with_mfa(mut self, serial: &str, code: &str) -> Self {
+        self.mfa_serial = Some(serial.to_string());
+        self.mfa_code = Some(code.to_string());
+        self
+    }
+}
+
+impl std::fmt::Debug for AssumeRoleOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AssumeRoleOptions")
+            .field("role_arn", &self.role_arn)
+            .field("session_name", &self.session_name)
+            .field("external_id", &self.external_id)
+            .field("duration", &self.duration)
+            .field("mfa_serial", &self.mfa_serial)
+            .field("mfa_code", &self.mfa_code.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// Assume a role with `credentials`, calling STS in `region`
+pub async fn get_role_credentials(
+    credentials: &AwsCredentials,
+    options: &AssumeRoleOptions,
+    region: Region,
+) -> Result<AwsCredentials, Error> {
+    let dispatcher = rusoto_core::request::HttpClient::new()?;
+    assume_role_with(dispatcher, credentials, options, region).await
+}
+
+/// Assume `role_arn` with `credentials`, calling STS in `region` through `dispatcher`
+pub(crate) async fn assume_role<D>(
+    dispatcher: D,
+    credentials: &AwsCredentials,
+    role_arn: &str,
+    region: Region,
+) -> Result<AwsCredentials, Error>
+where
+    D: DispatchSignedRequest + Send + Sync + 'static,
+{
+    let options = AssumeRoleOptions::new(role_arn);
+    assume_role_with(dispatcher, credentials, &options, region).await
+}
+
+/// Assume the role of `options` with `credentials`, calling STS in `region` through `dispatcher`
+pub(crate) async fn assume_role_with<D>(
+    dispatcher: D,
+    credentials: &AwsCredentials,
+    options: &AssumeRoleOptions,
+    region: Region,
+) -> Result<AwsCredentials, Error>
+where
+    D: DispatchSignedRequest + Send + Sync + 'static,
+{
+    let sts = StsClient::new_with(
+        dispatcher,
+        StaticProvider::from(credentials.clone()),
+        region::service_region(&region, "sts"),
+    );
+    let duration = options
+        .duration
+        .map(|duration| chrono::Duration::seconds(duration.as_secs() as i64));
+    let mut provider = StsAssumeRoleSessionCredentialsProvider::new(
+        sts,
+        options.role_arn.clone(),
+        options
+            .session_name
+            .clone()
+            .unwrap_or_else(|| ROLE_SESSION_NAME.to_string()),
+        options.external_id.clone(),
+        duration,
+        None,
+        options.mfa_serial.clone(),
+    );
+    if let Some(ref code) = options.mfa_code {
+        provider.set_mfa_code(code.as_str());
+    }
+    Ok(provider.assume_role().await?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -40,4 +254,83 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn task_credentials_require_container_endpoint() {
+        let relative_uri = env::var_os("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI");
+        let full_uri = env::var_os("AWS_CONTAINER_CREDENTIALS_FULL_URI");
+        env::remove_var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI");
+        env::remove_var("AWS_CONTAINER_CREDENTIALS_FULL_URI");
+
+        let result = get_task_credentials().await;
+
+        if let Some(uri) = relative_uri {
+            env::set_var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI", uri);
+        }
+        if let Some(uri) = full_uri {
+            env::set_var("AWS_CONTAINER_CREDENTIALS_FULL_URI", uri);
+        }
+        assert!(matches!(result, Err(crate::Error::CredentialsError(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn web_identity_credentials_require_token_file() {
+        let result = get_web_identity_credentials(
+            "arn:aws:iam::123456789012:role/example",
+            "/nonexistent/web-identity-token",
+        )
+        .await;
+        assert!(matches!(result, Err(crate::Error::CredentialsError(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn roles_are_assumed_with_mfa() -> Result<(), crate::Error> {
+        let dispatcher = rusoto_mock::MockRequestDispatcher::with_status(200)
+            .with_body(
+                r#"<AssumeRoleResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <AssumeRoleResult>
+    <Credentials>
+      <AccessKeyId>ASIAEXAMPLE</AccessKeyId>
+      <SecretAccessKey>assumed_secret</SecretAccessKey>
+      <SessionToken>assumed_token</SessionToken>
+      <Expiration>2099-01-01T00:00:00Z</Expiration>
+    </Credentials>
+    <AssumedRoleUser>
+      <Arn>arn:aws:sts::123456789012:assumed-role/admin/operator</Arn>
+      <AssumedRoleId>AROAEXAMPLE:operator</AssumedRoleId>
+    </AssumedRoleUser>
+  </AssumeRoleResult>
+  <ResponseMetadata>
+    <RequestId>01234567-89ab-cdef-0123-456789abcdef</RequestId>
+  </ResponseMetadata>
+</AssumeRoleResponse>"#,
+            )
+            .with_request_checker(|request| {
+                let body = match request.payload {
+                    Some(rusoto_core::signature::SignedRequestPayload::Buffer(ref body)) => {
+                        String::from_utf8_lossy(body).to_string()
+                    }
+                    _ => panic!("AssumeRole without a body"),
+                };
+                for param in [
+                    "RoleSessionName=operator",
+                    "ExternalId=partner",
+                    "DurationSeconds=900",
+                    "TokenCode=123456",
+                ] {
+                    assert!(body.contains(param), "{} not in {}", param, body);
+                }
+            });
+        let options = AssumeRoleOptions::new("arn:aws:iam::123456789012:role/admin")
+            .with_session_name("operator")
+            .with_external_id("partner")
+            .with_duration(std::time::Duration::from_secs(900))
+            .with_mfa("arn:aws:iam::123456789012:mfa/operator", "123456");
+        assert!(!format!("{:?}", options).contains("123456\""));
+
+        let credentials = AwsCredentials::new("AKIDEXAMPLE", "secret", None, None);
+        let assumed = assume_role_with(dispatcher, &credentials, &options, Region::UsEast1).await?;
+        assert_eq!(assumed.aws_access_key_id(), "ASIAEXAMPLE");
+        Ok(())
+    }
 }
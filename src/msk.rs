@@ -0,0 +1,177 @@
+//! Payloads for the `AWS_MSK_IAM` SASL mechanism of
+//! [Amazon MSK](https://docs.aws.amazon.com/msk/latest/developerguide/iam-access-control.html)
+//!
+//! A client authenticates to a broker by sending a JSON object holding the query parameters of
+//! a pre-signed `kafka-cluster:Connect` request to the broker. The broker validates the
+//! signature and authorizes the connection with the IAM policies of the signer.
+//!
+//! With `rdkafka`, set `sasl.mechanism` to `AWS_MSK_IAM` in a custom SASL callback and send
+//! [`MskAuthPayload::to_bytes`] as the initial client response.
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use log::info;
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::param::{Params, ServiceParams};
+use rusoto_core::Region;
+use serde::{Deserialize, Serialize};
+
+use crate::client::presign;
+
+/// Version of the payload format understood by MSK brokers
+pub const PAYLOAD_VERSION: &str = "2020_10_22";
+
+/// How long payloads are valid for
+pub const PAYLOAD_EXPIRES: Duration = Duration::from_secs(900);
+
+/// Action brokers authorize connecting clients for
+const CONNECT_ACTION: &str = "kafka-cluster:Connect";
+
+/// Payload of the `AWS_MSK_IAM` SASL mechanism
+///
+/// The `Debug` output of the payload redacts the signature and the session token.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct MskAuthPayload {
+    /// Version of the payload format, [`PAYLOAD_VERSION`]
+    pub version: String,
+    /// Host name of the broker the payload is signed for
+    pub host: String,
+    /// User agent of the client
+    #[serde(rename = "user-agent")]
+    pub user_agent: String,
+    /// Action the payload was signed for, `kafka-cluster:Connect`
+    pub action: String,
+    /// Signing algorithm, `AWS4-HMAC-SHA256`
+    #[serde(rename = "x-amz-algorithm")]
+    pub algorithm: String,
+    /// Access key ID and credential scope the payload was signed with
+    #[serde(rename = "x-amz-credential")]
+    pub credential: String,
+    /// Time the payload was signed, as `%Y%m%dT%H%M%SZ`
+    #[serde(rename = "x-amz-date")]
+    pub date: String,
+    /// Session token of temporary credentials
+    #[serde(
+        rename = "x-amz-security-token",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub security_token: Option<String>,
+    /// Headers included in the signature
+    #[serde(rename = "x-amz-signedheaders")]
+    pub signed_headers: String,
+    /// Number of seconds the payload is valid for after it was signed
+    #[serde(rename = "x-amz-expires")]
+    pub expires: String,
+    /// The signature
+    #[serde(rename = "x-amz-signature")]
+    pub signature: String,
+}
+
+impl MskAuthPayload {
+    /// Sign a payload to authenticate to the broker at `broker_host` in `region`
+    ///
+    /// `broker_host` is the host name of the broker, without the port, as found in the bootstrap
+    /// broker string of the cluster. Each broker requires a payload signed for its own host.
+    pub fn new(credentials: &AwsCredentials, region: &Region, broker_host: &str) -> Self {
+        info!("Building MSK IAM payload for {}", broker_host);
+        let mut params = Params::new();
+        params.put("Action", CONNECT_ACTION);
+        let url = presign(
+            credentials,
+            "kafka-cluster",
+            region,
+            broker_host,
+            "/",
+            params,
+            &PAYLOAD_EXPIRES,
+        );
+
+        let query = url
+            .split_once('?')
+            .map(|(_, query)| query)
+            .unwrap_or_default();
+        let mut params: HashMap<String, String> =
+            serde_urlencoded::from_str(query).expect("a pre-signed URL to have a valid query");
+        let mut param = |name: &str| params.remove(name).unwrap_or_default();
+
+        Self {
+            version: PAYLOAD_VERSION.to_string(),
+            host: broker_host.to_string(),
+            user_agent: format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            action: param("Action"),
+            algorithm: param("X-Amz-Algorithm"),
+            credential: param("X-Amz-Credential"),
+            date: param("X-Amz-Date"),
+            security_token: credentials.token().clone(),
+            signed_headers: param("X-Amz-SignedHeaders"),
+            expires: param("X-Amz-Expires"),
+            signature: param("X-Amz-Signature"),
+        }
+    }
+
+    /// Serialize the payload to the JSON sent to the broker
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("payloads to serialize")
+    }
+
+    /// Serialize the payload to the bytes of the SASL client response
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_json().into_bytes()
+    }
+}
+
+impl fmt::Debug for MskAuthPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MskAuthPayload")
+            .field("version", &self.version)
+            .field("host", &self.host)
+            .field("user_agent", &self.user_agent)
+            .field("action", &self.action)
+            .field("algorithm", &self.algorithm)
+            .field("credential", &self.credential)
+            .field("date", &self.date)
+            .field(
+                "security_token",
+                &self.security_token.as_ref().map(|_| "<redacted>"),
+            )
+            .field("signed_headers", &self.signed_headers)
+            .field("expires", &self.expires)
+            .field("signature", &"<redacted>")
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn payloads_hold_the_presigned_connect_request() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let host = "b-1.my-cluster.abc123.c2.kafka.us-west-2.amazonaws.com";
+        let payload = MskAuthPayload::new(&credentials, &Region::UsWest2, host);
+
+        let json: serde_json::Value = serde_json::from_slice(&payload.to_bytes())?;
+        assert_eq!(json["version"], PAYLOAD_VERSION);
+        assert_eq!(json["host"], host);
+        assert_eq!(json["action"], "kafka-cluster:Connect");
+        assert_eq!(json["x-amz-algorithm"], "AWS4-HMAC-SHA256");
+        assert_eq!(json["x-amz-signedheaders"], "host");
+        assert_eq!(json["x-amz-expires"], "900");
+        assert!(json["x-amz-credential"]
+            .as_str()
+            .unwrap()
+            .starts_with("mock_key/"));
+        assert!(json["x-amz-credential"]
+            .as_str()
+            .unwrap()
+            .ends_with("/us-west-2/kafka-cluster/aws4_request"));
+        assert_eq!(json["x-amz-signature"].as_str().unwrap().len(), 64);
+        assert!(json.get("x-amz-security-token").is_none());
+
+        assert!(!format!("{:?}", payload).contains(&payload.signature));
+        Ok(())
+    }
+}
@@ -0,0 +1,675 @@
+//! Credentials of the named profiles of the AWS CLI configuration files
+//!
+//! Profiles are read from `~/.aws/config` and `~/.aws/credentials`, or the files named by
+//! `AWS_CONFIG_FILE` and `AWS_SHARED_CREDENTIALS_FILE`, like the AWS CLI does. Besides static
+//! keys, profiles can get their credentials from a `credential_process`, from AWS IAM Identity
+//! Center (SSO) or by assuming a `role_arn`, with credentials from a `source_profile` or a
+//! `credential_source`. Roles whose profile sets an `mfa_serial` are assumed with the code an
+//! [`MfaPrompt`] returns.
+//!
+//! SSO profiles use the token the AWS CLI caches in `~/.aws/sso/cache` with `aws sso login`.
+//! Tokens are not refreshed: once the cached token has expired, log in again.
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use log::{debug, info};
+use rusoto_core::credential::{
+    AwsCredentials, ContainerProvider, EnvironmentProvider, InstanceMetadataProvider,
+    ProvideAwsCredentials,
+};
+use rusoto_core::request::{DispatchSignedRequest, HttpClient};
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::Region;
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+use crate::region::{parse_region, Partition};
+use crate::{AssumeRoleOptions, Error};
+
+/// Header carrying the SSO access token in requests to the SSO portal
+const SSO_BEARER_TOKEN_HEADER: &str = "x-amz-sso_bearer_token";
+
+type Section = HashMap<String, String>;
+
+/// Returns the current code of the MFA device with the given serial, for example by asking the
+/// user
+pub type MfaPrompt = dyn Fn(&str) -> Result<String, Error> + Send + Sync;
+
+/// The profiles of the AWS CLI configuration and shared credentials files
+#[derive(Clone, Default)]
+pub struct AwsConfig {
+    profiles: HashMap<String, Section>,
+    sso_sessions: HashMap<String, Section>,
+    sso_cache: PathBuf,
+    mfa_prompt: Option<Arc<MfaPrompt>>,
+}
+
+impl fmt::Debug for AwsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwsConfig")
+            .field("profiles", &self.profiles.keys().collect::<Vec<_>>())
+            .field(
+                "sso_sessions",
+                &self.sso_sessions.keys().collect::<Vec<_>>(),
+            )
+            .field("sso_cache", &self.sso_cache)
+            .field("mfa_prompt", &self.mfa_prompt.is_some())
+            .finish()
+    }
+}
+
+impl AwsConfig {
+    /// Read the files the AWS CLI reads
+    ///
+    /// Files that do not exist are treated as empty.
+    pub fn load() -> Result<Self, Error> {
+        let aws = home_dir().unwrap_or_default().join(".aws");
+        let config = env::var_os("AWS_CONFIG_FILE")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| aws.join("config"));
+        let credentials = env::var_os("AWS_SHARED_CREDENTIALS_FILE")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| aws.join("credentials"));
+        Ok(Self::from_files(config, credentials)?.with_sso_cache(aws.join("sso").join("cache")))
+    }
+
+    /// Read the configuration file `config` and the shared credentials file `credentials`
+    ///
+    /// Files that do not exist are treated as empty.
+    pub fn from_files<P, Q>(config: P, credentials: Q) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let read = |path: &Path| match fs::read_to_string(path) {
+            Ok(contents) => Ok(contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(Error::from(e)),
+        };
+        Ok(Self::parse(
+            &read(config.as_ref())?,
+            &read(credentials.as_ref())?,
+        ))
+    }
+
+    /// Parse the contents of a configuration file and a shared credentials file
+    ///
+    /// Settings of the credentials file win over those of the configuration file.
+    pub fn parse(config: &str, credentials: &str) -> Self {
+        let mut profiles: HashMap<String, Section> = HashMap::new();
+        let mut sso_sessions = HashMap::new();
+        for (name, section) in parse_ini(config) {
+            if let Some(session) = name.strip_prefix("sso-session ") {
+                sso_sessions.insert(session.trim().to_string(), section);
+            } else if let Some(profile) = name.strip_prefix("profile ") {
+                profiles.insert(profile.trim().to_string(), section);
+            } else if name == "default" {
+                profiles.insert(name, section);
+            }
+        }
+        for (name, section) in parse_ini(credentials) {
+            profiles.entry(name).or_default().extend(section);
+        }
+        Self {
+            profiles,
+            sso_sessions,
+            sso_cache: PathBuf::new(),
+            mfa_prompt: None,
+        }
+    }
+
+    /// Look for cached SSO tokens in `directory` instead of `~/.aws/sso/cache`
+    pub fn with_sso_cache<P: Into<PathBuf>>(mut self, directory: P) -> Self {
+        self.sso_cache = directory.into();
+        self
+    }
+
+    /// Ask `prompt` for the code of the MFA device whose serial it is passed, whenever a role
+    /// profile with an `mfa_serial` is assumed
+    pub fn with_mfa_prompt(mut self, prompt: Arc<MfaPrompt>) -> Self {
+        self.mfa_prompt = Some(prompt);
+        self
+    }
+
+    /// Whether a profile named `profile` is configured
+    pub fn has_profile(&self, profile: &str) -> bool {
+        self.profiles.contains_key(profile)
+    }
+
+    /// The `region` setting of `profile`, if any
+    pub fn region(&self, profile: &str) -> Option<&str> {
+        self.profiles
+            .get(profile)?
+            .get("region")
+            .map(String::as_str)
+    }
+
+    /// Resolve the credentials of `profile`
+    pub async fn credentials(&self, profile: &str) -> Result<AwsCredentials, Error> {
+        let dispatcher = Arc::new(HttpClient::new()?);
+        self.credentials_with_dispatcher(dispatcher, profile).await
+    }
+
+    pub(crate) async fn credentials_with_dispatcher<D>(
+        &self,
+        dispatcher: Arc<D>,
+        profile: &str,
+    ) -> Result<AwsCredentials, Error>
+    where
+        D: DispatchSignedRequest + Send + Sync + 'static,
+    {
+        info!("Resolving the credentials of the AWS profile {}", profile);
+        // Follow `source_profile` down to the profile the first role is assumed with
+        let mut roles = vec![];
+        let mut visited = HashSet::new();
+        let mut name = profile;
+        let mut credentials = loop {
+            if !visited.insert(name) {
+                return Err(profile_error(name, "source_profile forms a cycle"));
+            }
+            let section = self.section(name)?;
+            if !section.contains_key("role_arn") {
+                break self.base_credentials(&dispatcher, name, section).await?;
+            }
+            roles.push((name, section));
+            match (
+                section.get("source_profile"),
+                section.get("credential_source"),
+            ) {
+                (Some(source), _) if source == name => break static_credentials(name, section)?,
+                (Some(source), _) => name = source,
+                (None, Some(source)) => break credential_source(name, source).await?,
+                (None, None) => {
+                    return Err(profile_error(
+                        name,
+                        "role_arn requires a source_profile or a credential_source",
+                    ))
+                }
+            }
+        };
+
+        let region = match self.region(profile) {
+            Some(region) => parse_region(region)?,
+            None => Region::default(),
+        };
+        for (name, section) in roles.into_iter().rev() {
+            let options = self.role_options(name, section)?;
+            credentials = crate::assume_role_with(
+                Arc::clone(&dispatcher),
+                &credentials,
+                &options,
+                region.clone(),
+            )
+            .await?;
+        }
+        Ok(credentials)
+    }
+
+    /// How to assume the role of a profile, prompting for an MFA code if it requires one
+    fn role_options(&self, name: &str, section: &Section) -> Result<AssumeRoleOptions, Error> {
+        let mut options = AssumeRoleOptions::new(&section["role_arn"]);
+        options.session_name = section.get("role_session_name").cloned();
+        options.external_id = section.get("external_id").cloned();
+        if let Some(seconds) = section.get("duration_seconds") {
+            let seconds = seconds.parse().map_err(|_| {
+                profile_error(name, &format!("invalid duration_seconds {}", seconds))
+            })?;
+            options.duration = Some(Duration::from_secs(seconds));
+        }
+        if let Some(serial) = section.get("mfa_serial") {
+            let prompt = self.mfa_prompt.as_ref().ok_or_else(|| {
+                profile_error(name, "mfa_serial is set but there is no MFA prompt")
+            })?;
+            let code = prompt(serial)?;
+            options = options.with_mfa(serial, code.trim());
+        }
+        Ok(options)
+    }
+
+    fn section(&self, profile: &str) -> Result<&Section, Error> {
+        self.profiles
+            .get(profile)
+            .ok_or_else(|| profile_error(profile, "not configured"))
+    }
+
+    /// Credentials of a profile that does not assume a role
+    async fn base_credentials<D>(
+        &self,
+        dispatcher: &Arc<D>,
+        name: &str,
+        section: &Section,
+    ) -> Result<AwsCredentials, Error>
+    where
+        D: DispatchSignedRequest + Send + Sync + 'static,
+    {
+        if section.contains_key("sso_session") || section.contains_key("sso_start_url") {
+            self.sso_credentials(dispatcher, name, section).await
+        } else if let Some(command) = section.get("credential_process") {
+            process_credentials(name, command).await
+        } else {
+            static_credentials(name, section)
+        }
+    }
+
+    async fn sso_credentials<D>(
+        &self,
+        dispatcher: &Arc<D>,
+        name: &str,
+        section: &Section,
+    ) -> Result<AwsCredentials, Error>
+    where
+        D: DispatchSignedRequest + Send + Sync + 'static,
+    {
+        let setting = |key: &str| {
+            section
+                .get(key)
+                .ok_or_else(|| profile_error(name, &format!("{} is not set", key)))
+        };
+        // The token is cached under the name of the session, or the start URL for legacy
+        // profiles configuring SSO directly
+        let (cache_key, sso_region) = match section.get("sso_session") {
+            Some(session) => {
+                let sso_region = self
+                    .sso_sessions
+                    .get(session)
+                    .and_then(|session| session.get("sso_region"))
+                    .ok_or_else(|| {
+                        profile_error(name, &format!("sso-session {} has no sso_region", session))
+                    })?;
+                (session, sso_region)
+            }
+            None => (setting("sso_start_url")?, setting("sso_region")?),
+        };
+        let account_id = setting("sso_account_id")?;
+        let role_name = setting("sso_role_name")?;
+        let token = self.sso_token(name, cache_key)?;
+
+        let endpoint = format!(
+            "https://portal.sso.{}.{}",
+            sso_region,
+            Partition::of_region(sso_region).dns_suffix()
+        );
+        let region = Region::Custom {
+            name: sso_region.to_string(),
+            endpoint,
+        };
+        let mut request =
+            SignedRequest::new("GET", "awsssoportal", &region, "/federation/credentials");
+        request.add_param("account_id", account_id);
+        request.add_param("role_name", role_name);
+        request.add_header(SSO_BEARER_TOKEN_HEADER, &token);
+
+        debug!("Getting the credentials of {} in {}", role_name, account_id);
+        let response = crate::sts::dispatch_buffered(&**dispatcher, request).await?;
+        if !response.status.is_success() {
+            return Err(Error::GenericError(format!(
+                "AWS SSO returned {} for the profile {}: {}",
+                response.status,
+                name,
+                String::from_utf8_lossy(&response.body)
+            )));
+        }
+        let response: SsoCredentialsResponse = serde_json::from_slice(&response.body)?;
+        let credentials = response.role_credentials;
+        Ok(AwsCredentials::new(
+            credentials.access_key_id,
+            credentials.secret_access_key,
+            Some(credentials.session_token),
+            Utc.timestamp_millis_opt(credentials.expiration).single(),
+        ))
+    }
+
+    /// The access token `aws sso login` cached for `key`
+    fn sso_token(&self, name: &str, key: &str) -> Result<String, Error> {
+        let file = self.sso_cache.join(format!(
+            "{}.json",
+            hex::encode(Sha1::digest(key.as_bytes()))
+        ));
+        let login = || {
+            profile_error(
+                name,
+                &format!("no valid SSO token, run aws sso login --profile {}", name),
+            )
+        };
+        let cached = match fs::read(&file) {
+            Ok(cached) => cached,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(login()),
+            Err(e) => return Err(e.into()),
+        };
+        let cached: CachedSsoToken = serde_json::from_slice(&cached)?;
+        match parse_sso_expiry(&cached.expires_at) {
+            Some(expires_at) if expires_at > Utc::now() => Ok(cached.access_token),
+            _ => Err(login()),
+        }
+    }
+}
+
+/// Resolve the credentials of `profile` from the files the AWS CLI reads
+///
+/// This is a shorthand for [`AwsConfig::load`] and [`AwsConfig::credentials`].
+pub async fn get_profile_credentials(profile: &str) -> Result<AwsCredentials, Error> {
+    AwsConfig::load()?.credentials(profile).await
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedSsoToken {
+    access_token: String,
+    expires_at: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SsoCredentialsResponse {
+    role_credentials: SsoRoleCredentials,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SsoRoleCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: String,
+    /// Milliseconds since the epoch
+    expiration: i64,
+}
+
+/// The output of a `credential_process`
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProcessCredentials {
+    version: u32,
+    access_key_id: String,
+    secret_access_key: String,
+    #[serde(default)]
+    session_token: Option<String>,
+    #[serde(default)]
+    expiration: Option<DateTime<Utc>>,
+}
+
+fn profile_error(profile: &str, reason: &str) -> Error {
+    Error::GenericError(format!("AWS profile {}: {}", profile, reason))
+}
+
+fn static_credentials(name: &str, section: &Section) -> Result<AwsCredentials, Error> {
+    match (
+        section.get("aws_access_key_id"),
+        section.get("aws_secret_access_key"),
+    ) {
+        (Some(key), Some(secret)) => Ok(AwsCredentials::new(
+            key,
+            secret,
+            section.get("aws_session_token").cloned(),
+            None,
+        )),
+        _ => Err(profile_error(name, "no credentials are configured")),
+    }
+}
+
+async fn credential_source(name: &str, source: &str) -> Result<AwsCredentials, Error> {
+    let credentials = match source {
+        "Environment" => EnvironmentProvider::default().credentials().await?,
+        "Ec2InstanceMetadata" => InstanceMetadataProvider::new().credentials().await?,
+        "EcsContainer" => ContainerProvider::new().credentials().await?,
+        source => {
+            return Err(profile_error(
+                name,
+                &format!("unknown credential_source {}", source),
+            ))
+        }
+    };
+    Ok(credentials)
+}
+
+async fn process_credentials(name: &str, command: &str) -> Result<AwsCredentials, Error> {
+    debug!("Running the credential_process of the profile {}", name);
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut process = Command::new(shell);
+    process.arg(flag).arg(command).stderr(Stdio::inherit());
+    let output = tokio::task::spawn_blocking(move || process.output())
+        .await
+        .map_err(|e| Error::GenericError(e.to_string()))??;
+    if !output.status.success() {
+        return Err(profile_error(
+            name,
+            &format!("credential_process exited with {}", output.status),
+        ));
+    }
+
+    let credentials: ProcessCredentials = serde_json::from_slice(&output.stdout)?;
+    if credentials.version != 1 {
+        return Err(profile_error(
+            name,
+            &format!(
+                "credential_process returned version {}",
+                credentials.version
+            ),
+        ));
+    }
+    Ok(AwsCredentials::new(
+        credentials.access_key_id,
+        credentials.secret_access_key,
+        credentials.session_token,
+        credentials.expiration,
+    ))
+}
+
+/// Parse the sections of an INI file, lowercasing keys
+///
+/// Comments and the nested settings of services, such as `s3 =` followed by indented lines, are
+/// skipped.
+fn parse_ini(contents: &str) -> Vec<(String, Section)> {
+    let mut sections: Vec<(String, Section)> = vec![];
+    for line in contents.lines() {
+        let nested = line.starts_with(|c: char| c.is_whitespace());
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') || nested {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((name.trim().to_string(), Section::new()));
+        } else if let (Some((key, value)), Some((_, section))) =
+            (line.split_once('='), sections.last_mut())
+        {
+            let value = value.trim();
+            if !value.is_empty() {
+                section.insert(key.trim().to_lowercase(), value.to_string());
+            }
+        }
+    }
+    sections
+}
+
+/// Parse an `expiresAt` of the SSO cache, which older versions of the AWS CLI wrote as
+/// `2024-01-01T00:00:00UTC`
+fn parse_sso_expiry(expires_at: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(expires_at)
+        .map(|date| date.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(expires_at, "%Y-%m-%dT%H:%M:%SUTC")
+                .ok()
+                .map(|date| Utc.from_utc_datetime(&date))
+        })
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusoto_mock::MockRequestDispatcher;
+
+    const ASSUMED: &str = r#"<AssumeRoleResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <AssumeRoleResult>
+    <Credentials>
+      <AccessKeyId>ASIAEXAMPLE</AccessKeyId>
+      <SecretAccessKey>assumed_secret</SecretAccessKey>
+      <SessionToken>assumed_token</SessionToken>
+      <Expiration>2099-01-01T00:00:00Z</Expiration>
+    </Credentials>
+    <AssumedRoleUser>
+      <Arn>arn:aws:sts::123456789012:assumed-role/admin/aws-auth-payload</Arn>
+      <AssumedRoleId>AROAEXAMPLE:aws-auth-payload</AssumedRoleId>
+    </AssumedRoleUser>
+  </AssumeRoleResult>
+  <ResponseMetadata>
+    <RequestId>01234567-89ab-cdef-0123-456789abcdef</RequestId>
+  </ResponseMetadata>
+</AssumeRoleResponse>"#;
+
+    fn config() -> AwsConfig {
+        AwsConfig::parse(
+            r#"
+[default]
+region = eu-west-1
+s3 =
+  max_concurrent_requests = 20
+
+[profile admin]
+role_arn = arn:aws:iam::123456789012:role/admin
+source_profile = base
+external_id = secret-id
+
+[profile mfa]
+role_arn = arn:aws:iam::123456789012:role/admin
+source_profile = base
+mfa_serial = arn:aws:iam::123456789012:mfa/operator
+
+[profile loop]
+role_arn = arn:aws:iam::123456789012:role/loop
+source_profile = other
+
+[profile other]
+role_arn = arn:aws:iam::123456789012:role/other
+source_profile = loop
+
+[profile sso]
+sso_session = corp
+sso_account_id = 123456789012
+sso_role_name = ReadOnly
+
+[sso-session corp]
+sso_region = us-east-1
+sso_start_url = https://corp.awsapps.com/start
+"#,
+            r#"
+# Static keys
+[base]
+aws_access_key_id = AKIDEXAMPLE
+aws_secret_access_key = base_secret
+"#,
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn roles_are_assumed_with_their_source_profile() -> Result<(), Error> {
+        let config = config();
+        assert_eq!(config.region("default"), Some("eu-west-1"));
+        assert!(config.has_profile("base"));
+
+        let dispatcher = MockRequestDispatcher::with_status(200)
+            .with_body(ASSUMED)
+            .with_request_checker(|request| {
+                let body = String::from_utf8_lossy(match request.payload {
+                    Some(rusoto_core::signature::SignedRequestPayload::Buffer(ref body)) => body,
+                    _ => panic!("AssumeRole without a body"),
+                })
+                .to_string();
+                assert!(body.contains("ExternalId=secret-id"));
+                assert!(request.headers["authorization"][0]
+                    .starts_with(b"AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+            });
+        let credentials = config
+            .credentials_with_dispatcher(Arc::new(dispatcher), "admin")
+            .await?;
+        assert_eq!(credentials.aws_access_key_id(), "ASIAEXAMPLE");
+
+        let dispatcher = Arc::new(MockRequestDispatcher::with_status(200).with_body(ASSUMED));
+        assert!(config
+            .credentials_with_dispatcher(Arc::clone(&dispatcher), "mfa")
+            .await
+            .is_err());
+        let prompt = |serial: &str| {
+            assert_eq!(serial, "arn:aws:iam::123456789012:mfa/operator");
+            Ok("123456\n".to_string())
+        };
+        let options = config
+            .clone()
+            .with_mfa_prompt(Arc::new(prompt))
+            .role_options("mfa", config.section("mfa")?)?;
+        assert_eq!(options.mfa_code.as_deref(), Some("123456"));
+
+        let error = config
+            .credentials_with_dispatcher(Arc::clone(&dispatcher), "loop")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("cycle"));
+        assert!(config
+            .credentials_with_dispatcher(dispatcher, "missing")
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn sso_profiles_use_the_cached_token() -> Result<(), Error> {
+        let cache = env::temp_dir().join(format!("aws-auth-payload-sso-{}", std::process::id()));
+        fs::create_dir_all(&cache)?;
+        let token = cache.join(format!("{}.json", hex::encode(Sha1::digest(b"corp"))));
+        fs::write(
+            &token,
+            r#"{"accessToken": "sso-token", "expiresAt": "2099-01-01T00:00:00Z"}"#,
+        )?;
+        let config = config().with_sso_cache(&cache);
+
+        let dispatcher = MockRequestDispatcher::with_status(200)
+            .with_body(
+                r#"{"roleCredentials": {"accessKeyId": "ASIASSO", "secretAccessKey": "sso_secret",
+                    "sessionToken": "sso_session", "expiration": 4070908800000}}"#,
+            )
+            .with_request_checker(|request| {
+                assert_eq!(request.hostname(), "portal.sso.us-east-1.amazonaws.com");
+                assert_eq!(request.path, "/federation/credentials");
+                assert_eq!(
+                    request.headers[SSO_BEARER_TOKEN_HEADER],
+                    vec![b"sso-token".to_vec()]
+                );
+            });
+        let credentials = config
+            .credentials_with_dispatcher(Arc::new(dispatcher), "sso")
+            .await?;
+        assert_eq!(credentials.aws_access_key_id(), "ASIASSO");
+        assert_eq!(credentials.token().as_deref(), Some("sso_session"));
+
+        fs::write(
+            &token,
+            r#"{"accessToken": "sso-token", "expiresAt": "2015-01-01T00:00:00UTC"}"#,
+        )?;
+        let dispatcher = Arc::new(MockRequestDispatcher::with_status(200));
+        let error = config
+            .credentials_with_dispatcher(dispatcher, "sso")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("aws sso login"));
+        fs::remove_dir_all(&cache)?;
+        Ok(())
+    }
+}
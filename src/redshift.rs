@@ -0,0 +1,298 @@
+//! Temporary database credentials for [Amazon Redshift](https://docs.aws.amazon.com/redshift/latest/mgmt/generating-iam-credentials-overview.html)
+//!
+//! This module requires the `redshift` feature. Unlike RDS, Redshift does not accept pre-signed
+//! requests as passwords: the credentials are issued by `redshift:GetClusterCredentials` for
+//! provisioned clusters, and by `redshift-serverless:GetCredentials` for serverless workgroups.
+use std::fmt;
+
+use chrono::{DateTime, TimeZone, Utc};
+use log::{debug, info};
+use rusoto_core::credential::{AwsCredentials, StaticProvider};
+use rusoto_core::request::{DispatchSignedRequest, HttpClient};
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::Region;
+use rusoto_redshift::{GetClusterCredentialsMessage, Redshift, RedshiftClient};
+use serde::Deserialize;
+
+use crate::region::service_region;
+use crate::Error;
+
+/// Temporary credentials of a Redshift database user
+///
+/// The `Debug` output redacts the password.
+#[derive(Clone, Eq, PartialEq)]
+pub struct RedshiftCredentials {
+    /// User name to connect as
+    pub db_user: String,
+    /// Password to connect with
+    pub db_password: String,
+    /// When the password expires
+    pub expiration: Option<DateTime<Utc>>,
+}
+
+impl RedshiftCredentials {
+    /// Get credentials for `db_user` of the provisioned cluster `cluster_identifier` in
+    /// `region` with `redshift:GetClusterCredentials`
+    ///
+    /// If `db_name` is set, the user can only connect to that database.
+    pub async fn for_cluster(
+        credentials: &AwsCredentials,
+        region: &Region,
+        cluster_identifier: &str,
+        db_user: &str,
+        db_name: Option<&str>,
+    ) -> Result<Self, Error> {
+        let dispatcher = HttpClient::new()?;
+        Self::for_cluster_with_dispatcher(
+            dispatcher,
+            credentials,
+            region,
+            cluster_identifier,
+            db_user,
+            db_name,
+        )
+        .await
+    }
+
+    pub(crate) async fn for_cluster_with_dispatcher<D>(
+        dispatcher: D,
+        credentials: &AwsCredentials,
+        region: &Region,
+        cluster_identifier: &str,
+        db_user: &str,
+        db_name: Option<&str>,
+    ) -> Result<Self, Error>
+    where
+        D: DispatchSignedRequest + Send + Sync + 'static,
+    {
+        info!("Getting Redshift credentials for {}", cluster_identifier);
+        let client = RedshiftClient::new_with(
+            dispatcher,
+            StaticProvider::from(credentials.clone()),
+            service_region(region, "redshift"),
+        );
+        let issued = client
+            .get_cluster_credentials(GetClusterCredentialsMessage {
+                cluster_identifier: cluster_identifier.to_string(),
+                db_user: db_user.to_string(),
+                db_name: db_name.map(str::to_string),
+                ..Default::default()
+            })
+            .await?;
+
+        let missing =
+            |name: &str| Error::GenericError(format!("GetClusterCredentials returned no {}", name));
+        let expiration = issued
+            .expiration
+            .and_then(|at| DateTime::parse_from_rfc3339(&at).ok())
+            .map(|at| at.with_timezone(&Utc));
+        debug!("Redshift credentials expire at {:?}", expiration);
+        Ok(Self {
+            db_user: issued.db_user.ok_or_else(|| missing("DbUser"))?,
+            db_password: issued.db_password.ok_or_else(|| missing("DbPassword"))?,
+            expiration,
+        })
+    }
+
+    /// Get credentials for the serverless workgroup `workgroup` in `region` with
+    /// `redshift-serverless:GetCredentials`
+    ///
+    /// The user is derived from the identity of `credentials`. If `db_name` is set, the user can
+    /// only connect to that database.
+    pub async fn for_workgroup(
+        credentials: &AwsCredentials,
+        region: &Region,
+        workgroup: &str,
+        db_name: Option<&str>,
+    ) -> Result<Self, Error> {
+        let dispatcher = HttpClient::new()?;
+        Self::for_workgroup_with_dispatcher(&dispatcher, credentials, region, workgroup, db_name)
+            .await
+    }
+
+    pub(crate) async fn for_workgroup_with_dispatcher<D>(
+        dispatcher: &D,
+        credentials: &AwsCredentials,
+        region: &Region,
+        workgroup: &str,
+        db_name: Option<&str>,
+    ) -> Result<Self, Error>
+    where
+        D: DispatchSignedRequest,
+    {
+        info!("Getting Redshift Serverless credentials for {}", workgroup);
+        let mut body = serde_json::json!({ "workgroupName": workgroup });
+        if let Some(db_name) = db_name {
+            body["dbName"] = db_name.into();
+        }
+
+        // Redshift Serverless is newer than rusoto, so the request is built by hand
+        let region = service_region(region, "redshift-serverless");
+        let mut request = SignedRequest::new("POST", "redshift-serverless", &region, "/");
+        request.add_header("x-amz-target", "RedshiftServerless.GetCredentials");
+        request.set_content_type("application/x-amz-json-1.1".to_string());
+        request.set_payload(Some(body.to_string().into_bytes()));
+        request.sign(credentials);
+
+        let mut response = dispatcher.dispatch(request, None).await?;
+        let response = response.buffer().await?;
+        if !response.status.is_success() {
+            let error: ServerlessError = serde_json::from_slice(&response.body).unwrap_or_default();
+            return Err(Error::RedshiftServerlessError {
+                status: response.status.as_u16(),
+                code: error
+                    .kind
+                    .rsplit('#')
+                    .next()
+                    .filter(|code| !code.is_empty())
+                    .unwrap_or("Unknown")
+                    .to_string(),
+                message: error.message,
+            });
+        }
+
+        let issued: ServerlessCredentials = serde_json::from_slice(&response.body)?;
+        let expiration = issued
+            .expiration
+            .and_then(|at| Utc.timestamp_millis_opt((at * 1000.0) as i64).single());
+        debug!("Redshift Serverless credentials expire at {:?}", expiration);
+        Ok(Self {
+            db_user: issued.db_user,
+            db_password: issued.db_password,
+            expiration,
+        })
+    }
+
+    /// Whether the password has expired
+    pub fn is_expired(&self) -> bool {
+        self.expiration.is_some_and(|at| at <= Utc::now())
+    }
+}
+
+impl fmt::Debug for RedshiftCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedshiftCredentials")
+            .field("db_user", &self.db_user)
+            .field("db_password", &"<redacted>")
+            .field("expiration", &self.expiration)
+            .finish()
+    }
+}
+
+/// Response of `redshift-serverless:GetCredentials`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerlessCredentials {
+    db_user: String,
+    db_password: String,
+    expiration: Option<f64>,
+}
+
+/// Error response of Redshift Serverless
+#[derive(Default, Deserialize)]
+struct ServerlessError {
+    #[serde(rename = "__type", default)]
+    kind: String,
+    #[serde(default)]
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusoto_mock::MockRequestDispatcher;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn cluster_credentials_are_parsed() -> Result<(), crate::Error> {
+        let dispatcher = MockRequestDispatcher::with_status(200)
+            .with_body(
+                r#"<GetClusterCredentialsResponse xmlns="http://redshift.amazonaws.com/doc/2012-12-01/">
+  <GetClusterCredentialsResult>
+    <DbUser>IAM:my_user</DbUser>
+    <Expiration>2024-01-01T00:15:00Z</Expiration>
+    <DbPassword>my-password</DbPassword>
+  </GetClusterCredentialsResult>
+  <ResponseMetadata>
+    <RequestId>c0a2b1a4-1111-2222-3333-444455556666</RequestId>
+  </ResponseMetadata>
+</GetClusterCredentialsResponse>"#,
+            )
+            .with_request_checker(|request| {
+                assert_eq!(request.hostname(), "redshift.us-west-2.amazonaws.com");
+            });
+        let credentials = crate::client::tests::credentials().await?;
+
+        let redshift = RedshiftCredentials::for_cluster_with_dispatcher(
+            dispatcher,
+            &credentials,
+            &Region::UsWest2,
+            "my-cluster",
+            "my_user",
+            Some("dev"),
+        )
+        .await?;
+        assert_eq!(redshift.db_user, "IAM:my_user");
+        assert_eq!(redshift.db_password, "my-password");
+        assert_eq!(
+            redshift.expiration,
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 15, 0).unwrap())
+        );
+        assert!(redshift.is_expired());
+        assert!(!format!("{:?}", redshift).contains("my-password"));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn workgroup_credentials_are_parsed() -> Result<(), crate::Error> {
+        let dispatcher = MockRequestDispatcher::with_status(200)
+            .with_body(
+                r#"{"dbUser":"IAMR:my-role","dbPassword":"my-password","expiration":1700000000}"#,
+            )
+            .with_request_checker(|request| {
+                assert_eq!(
+                    request.hostname(),
+                    "redshift-serverless.us-west-2.amazonaws.com"
+                );
+                assert_eq!(
+                    request.headers["x-amz-target"],
+                    vec![b"RedshiftServerless.GetCredentials".to_vec()]
+                );
+            });
+        let credentials = crate::client::tests::credentials().await?;
+
+        let redshift = RedshiftCredentials::for_workgroup_with_dispatcher(
+            &dispatcher,
+            &credentials,
+            &Region::UsWest2,
+            "my-workgroup",
+            None,
+        )
+        .await?;
+        assert_eq!(redshift.db_user, "IAMR:my-role");
+        assert_eq!(
+            redshift.expiration,
+            Some(Utc.timestamp_opt(1_700_000_000, 0).unwrap())
+        );
+
+        let dispatcher = MockRequestDispatcher::with_status(400).with_body(
+            r#"{"__type":"com.amazonaws.redshiftserverless#ResourceNotFoundException","message":"Workgroup not found"}"#,
+        );
+        let result = RedshiftCredentials::for_workgroup_with_dispatcher(
+            &dispatcher,
+            &credentials,
+            &Region::UsWest2,
+            "missing",
+            None,
+        )
+        .await;
+        match result {
+            Err(Error::RedshiftServerlessError { status, code, .. }) => {
+                assert_eq!(status, 400);
+                assert_eq!(code, "ResourceNotFoundException");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,453 @@
+//! Resolving and validating the AWS region to generate payloads for
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use hyper::{Body, Client, Request, Uri};
+use log::{debug, info};
+use rusoto_core::credential::ProfileProvider;
+use rusoto_core::Region;
+use serde::Deserialize;
+
+use crate::{http, imds, Error};
+
+const ECS_METADATA_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// An AWS partition: a group of regions sharing a DNS suffix and an isolated set of identities
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Partition {
+    /// The standard `aws` partition
+    Aws,
+    /// The `aws-cn` partition for the China regions
+    AwsCn,
+    /// The `aws-us-gov` partition for the GovCloud (US) regions
+    AwsUsGov,
+    /// The `aws-iso` partition
+    AwsIso,
+    /// The `aws-iso-b` partition
+    AwsIsoB,
+    /// The `aws-iso-e` partition
+    AwsIsoE,
+    /// The `aws-iso-f` partition
+    AwsIsoF,
+    /// The `aws-eusc` partition for the European Sovereign Cloud
+    AwsEusc,
+}
+
+impl Partition {
+    /// All partitions known to this library
+    pub const ALL: [Partition; 8] = [
+        Partition::Aws,
+        Partition::AwsCn,
+        Partition::AwsUsGov,
+        Partition::AwsIso,
+        Partition::AwsIsoB,
+        Partition::AwsIsoE,
+        Partition::AwsIsoF,
+        Partition::AwsEusc,
+    ];
+
+    /// Name of the partition as it appears in ARNs
+    pub fn name(self) -> &'static str {
+        match self {
+            Partition::Aws => "aws",
+            Partition::AwsCn => "aws-cn",
+            Partition::AwsUsGov => "aws-us-gov",
+            Partition::AwsIso => "aws-iso",
+            Partition::AwsIsoB => "aws-iso-b",
+            Partition::AwsIsoE => "aws-iso-e",
+            Partition::AwsIsoF => "aws-iso-f",
+            Partition::AwsEusc => "aws-eusc",
+        }
+    }
+
+    /// DNS suffix of the service endpoints in the partition
+    pub fn dns_suffix(self) -> &'static str {
+        match self {
+            Partition::Aws | Partition::AwsUsGov => "amazonaws.com",
+            Partition::AwsCn => "amazonaws.com.cn",
+            Partition::AwsIso => "c2s.ic.gov",
+            Partition::AwsIsoB => "sc2s.sgov.gov",
+            Partition::AwsIsoE => "cloud.adc-e.uk",
+            Partition::AwsIsoF => "csp.hci.ic.gov",
+            Partition::AwsEusc => "amazonaws.eu",
+        }
+    }
+
+    /// Region that requests to IAM, a global service, are signed for in the partition
+    pub fn iam_region(self) -> &'static str {
+        match self {
+            Partition::Aws => "us-east-1",
+            Partition::AwsCn => "cn-north-1",
+            Partition::AwsUsGov => "us-gov-west-1",
+            Partition::AwsIso => "us-iso-east-1",
+            Partition::AwsIsoB => "us-isob-east-1",
+            Partition::AwsIsoE => "eu-isoe-west-1",
+            Partition::AwsIsoF => "us-isof-south-1",
+            Partition::AwsEusc => "eusc-de-east-1",
+        }
+    }
+
+    /// Host name of the IAM endpoint of the partition
+    pub fn iam_host(self) -> String {
+        match self {
+            Partition::Aws => "iam.amazonaws.com".to_string(),
+            Partition::AwsUsGov => "iam.us-gov.amazonaws.com".to_string(),
+            partition => format!("iam.{}.{}", partition.iam_region(), partition.dns_suffix()),
+        }
+    }
+
+    /// Regions currently available in the partition
+    pub fn regions(self) -> &'static [&'static str] {
+        match self {
+            Partition::Aws => &[
+                "af-south-1",
+                "ap-east-1",
+                "ap-east-2",
+                "ap-northeast-1",
+                "ap-northeast-2",
+                "ap-northeast-3",
+                "ap-south-1",
+                "ap-south-2",
+                "ap-southeast-1",
+                "ap-southeast-2",
+                "ap-southeast-3",
+                "ap-southeast-4",
+                "ap-southeast-5",
+                "ap-southeast-6",
+                "ap-southeast-7",
+                "ca-central-1",
+                "ca-west-1",
+                "eu-central-1",
+                "eu-central-2",
+                "eu-north-1",
+                "eu-south-1",
+                "eu-south-2",
+                "eu-west-1",
+                "eu-west-2",
+                "eu-west-3",
+                "il-central-1",
+                "me-central-1",
+                "me-south-1",
+                "mx-central-1",
+                "sa-east-1",
+                "us-east-1",
+                "us-east-2",
+                "us-west-1",
+                "us-west-2",
+            ],
+            Partition::AwsCn => &["cn-north-1", "cn-northwest-1"],
+            Partition::AwsUsGov => &["us-gov-east-1", "us-gov-west-1"],
+            Partition::AwsIso => &["us-iso-east-1", "us-iso-west-1"],
+            Partition::AwsIsoB => &["us-isob-east-1"],
+            Partition::AwsIsoE => &["eu-isoe-west-1"],
+            Partition::AwsIsoF => &["us-isof-east-1", "us-isof-south-1"],
+            Partition::AwsEusc => &["eusc-de-east-1"],
+        }
+    }
+
+    /// The partition a region belongs to, going by the naming scheme of its partition
+    pub fn of_region(region: &str) -> Partition {
+        let prefixes = [
+            ("cn-", Partition::AwsCn),
+            ("us-gov-", Partition::AwsUsGov),
+            ("us-iso-", Partition::AwsIso),
+            ("us-isob-", Partition::AwsIsoB),
+            ("eu-isoe-", Partition::AwsIsoE),
+            ("us-isof-", Partition::AwsIsoF),
+            ("eusc-", Partition::AwsEusc),
+        ];
+        prefixes
+            .iter()
+            .find(|(prefix, _)| region.starts_with(prefix))
+            .map(|(_, partition)| *partition)
+            .unwrap_or(Partition::Aws)
+    }
+}
+
+impl fmt::Display for Partition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parse and validate a region name such as `eu-west-1`
+///
+/// The name must be well-formed and one of the [regions](Partition::regions) of a known partition.
+/// Regions that are newer than `rusoto` or are in a partition whose endpoints it cannot build
+/// are returned as a [`Region::Custom`] without an endpoint, which [`service_region`] fills in
+/// with the regional endpoint of the service a request is sent to.
+///
+/// Use [`custom_region`] for regions with explicit endpoints, such as VPC endpoints or local
+/// emulators.
+pub fn parse_region(name: &str) -> Result<Region, Error> {
+    validate_region_name(name)?;
+
+    let partition = Partition::of_region(name);
+    if !partition.regions().contains(&name) {
+        return Err(Error::InvalidRegion {
+            region: name.to_string(),
+            reason: format!("not a known region of the {} partition", partition),
+        });
+    }
+
+    match (partition, Region::from_str(name)) {
+        (Partition::Aws, Ok(region))
+        | (Partition::AwsCn, Ok(region))
+        | (Partition::AwsUsGov, Ok(region)) => Ok(region),
+        _ => Ok(Region::Custom {
+            name: name.to_string(),
+            endpoint: String::new(),
+        }),
+    }
+}
+
+/// The region to send requests for `service`, such as `sts` or `api.ecr`, to
+///
+/// Regions [`parse_region`] returns without an endpoint are given the regional endpoint of the
+/// service in their partition, like `https://api.ecr.me-central-1.amazonaws.com`. Other regions
+/// are returned as they are.
+pub fn service_region(region: &Region, service: &str) -> Region {
+    match region {
+        Region::Custom { name, endpoint } if endpoint.is_empty() => Region::Custom {
+            name: name.clone(),
+            endpoint: format!(
+                "https://{}.{}.{}",
+                service,
+                name,
+                Partition::of_region(name).dns_suffix()
+            ),
+        },
+        region => region.clone(),
+    }
+}
+
+/// Create a region named `name` whose requests are sent to `endpoint`
+///
+/// The name is used in the credential scope of signatures and must be well-formed, but need not
+/// be a known region. The endpoint must be an absolute `http` or `https` URL.
+pub fn custom_region(name: &str, endpoint: &str) -> Result<Region, Error> {
+    validate_region_name(name)?;
+
+    let uri: Uri = endpoint.parse().map_err(|e| Error::InvalidRegion {
+        region: name.to_string(),
+        reason: format!("invalid endpoint {}: {}", endpoint, e),
+    })?;
+    match (uri.scheme_str(), uri.host()) {
+        (Some("http"), Some(_)) | (Some("https"), Some(_)) => Ok(Region::Custom {
+            name: name.to_string(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+        }),
+        _ => Err(Error::InvalidRegion {
+            region: name.to_string(),
+            reason: format!("endpoint {} is not an absolute HTTP(S) URL", endpoint),
+        }),
+    }
+}
+
+/// Region names are lowercase words separated by hyphens, ending in a number: `us-gov-west-1`
+fn validate_region_name(name: &str) -> Result<(), Error> {
+    let invalid = |reason: &str| Error::InvalidRegion {
+        region: name.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let parts: Vec<&str> = name.split('-').collect();
+    let (number, words) = parts
+        .split_last()
+        .ok_or_else(|| invalid("region is empty"))?;
+    if words.len() < 2 {
+        return Err(invalid(
+            "expected at least a geography, a direction and a number",
+        ));
+    }
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid("region must end with a number"));
+    }
+    if words
+        .iter()
+        .any(|word| word.is_empty() || !word.bytes().all(|b| b.is_ascii_lowercase()))
+    {
+        return Err(invalid("region must consist of lowercase words"));
+    }
+    Ok(())
+}
+
+/// Detect the region the current process is running in
+///
+/// Unlike `Region::default()`, which silently falls back to `us-east-1`, an error is returned when
+/// none of the sources below yield a region. They are consulted in order:
+///
+/// 1. The `AWS_REGION` and `AWS_DEFAULT_REGION` environment variables
+/// 1. The region of the active profile in the AWS config file, respecting `AWS_PROFILE` and
+///    `AWS_CONFIG_FILE`
+/// 1. The placement data of the EC2 instance metadata service, unless it is disabled with
+///    `AWS_EC2_METADATA_DISABLED=true`
+/// 1. The ECS task metadata endpoint in `ECS_CONTAINER_METADATA_URI_V4` or
+///    `ECS_CONTAINER_METADATA_URI`
+pub async fn detect_region() -> Result<Region, Error> {
+    let region = match region_from_env().or_else(region_from_profile) {
+        Some(region) => region,
+        None => match region_from_instance_metadata().await {
+            Some(region) => region,
+            None => region_from_task_metadata()
+                .await
+                .ok_or(Error::RegionNotFound)?,
+        },
+    };
+
+    info!("Detected AWS region {}", region);
+    parse_region(&region)
+}
+
+fn region_from_env() -> Option<String> {
+    ["AWS_REGION", "AWS_DEFAULT_REGION"]
+        .iter()
+        .filter_map(|key| env::var(key).ok())
+        .find(|value| !value.is_empty())
+}
+
+fn region_from_profile() -> Option<String> {
+    ProfileProvider::region().ok().flatten()
+}
+
+async fn region_from_instance_metadata() -> Option<String> {
+    if imds::disabled() {
+        return None;
+    }
+
+    match imds::get("/latest/meta-data/placement/region").await {
+        Ok(region) => Some(region),
+        Err(e) => {
+            debug!("Unable to read region from instance metadata: {}", e);
+            None
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TaskMetadata {
+    #[serde(rename = "TaskARN")]
+    task_arn: String,
+}
+
+async fn region_from_task_metadata() -> Option<String> {
+    let endpoint = env::var("ECS_CONTAINER_METADATA_URI_V4")
+        .or_else(|_| env::var("ECS_CONTAINER_METADATA_URI"))
+        .ok()?;
+
+    let metadata = async {
+        let request = Request::builder()
+            .uri(format!("{}/task", endpoint.trim_end_matches('/')))
+            .body(Body::empty())?;
+        let response = http::send(&Client::new(), request, ECS_METADATA_TIMEOUT).await?;
+        serde_json::from_slice::<TaskMetadata>(&response.body)
+            .map_err(|e| Error::GenericError(e.to_string()))
+    };
+
+    match metadata.await {
+        Ok(metadata) => region_from_task_arn(&metadata.task_arn),
+        Err(e) => {
+            debug!("Unable to read region from task metadata: {}", e);
+            None
+        }
+    }
+}
+
+/// Task ARNs look like `arn:aws:ecs:us-west-2:123456789012:task/cluster/0123456789abcdef`
+fn region_from_task_arn(task_arn: &str) -> Option<String> {
+    task_arn
+        .split(':')
+        .nth(3)
+        .filter(|region| !region.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn region_is_detected_from_environment() -> Result<(), crate::Error> {
+        let previous = env::var_os("AWS_REGION");
+        env::set_var("AWS_REGION", "eu-west-1");
+
+        let region = detect_region().await;
+        match previous {
+            Some(previous) => env::set_var("AWS_REGION", previous),
+            None => env::remove_var("AWS_REGION"),
+        }
+        assert_eq!(region?, Region::EuWest1);
+        Ok(())
+    }
+
+    #[test]
+    fn regions_are_validated() {
+        assert_eq!(parse_region("us-west-2").unwrap(), Region::UsWest2);
+        assert_eq!(parse_region("us-gov-east-1").unwrap(), Region::UsGovEast1);
+        assert!(matches!(
+            parse_region("US-WEST-2"),
+            Err(Error::InvalidRegion { .. })
+        ));
+        assert!(matches!(
+            parse_region("us-west"),
+            Err(Error::InvalidRegion { .. })
+        ));
+        assert!(matches!(
+            parse_region("us-middle-9"),
+            Err(Error::InvalidRegion { .. })
+        ));
+    }
+
+    #[test]
+    fn regions_unknown_to_rusoto_use_regional_endpoint_of_service() {
+        let region = parse_region("ap-southeast-5").unwrap();
+        assert_eq!(
+            service_region(&region, "sts"),
+            Region::Custom {
+                name: "ap-southeast-5".to_string(),
+                endpoint: "https://sts.ap-southeast-5.amazonaws.com".to_string(),
+            }
+        );
+        assert_eq!(
+            service_region(&region, "api.ecr"),
+            Region::Custom {
+                name: "ap-southeast-5".to_string(),
+                endpoint: "https://api.ecr.ap-southeast-5.amazonaws.com".to_string(),
+            }
+        );
+        assert_eq!(
+            service_region(&parse_region("us-isob-east-1").unwrap(), "sts"),
+            Region::Custom {
+                name: "us-isob-east-1".to_string(),
+                endpoint: "https://sts.us-isob-east-1.sc2s.sgov.gov".to_string(),
+            }
+        );
+        assert_eq!(service_region(&Region::UsWest2, "sts"), Region::UsWest2);
+        let local = custom_region("us-east-1", "http://localhost:4566").unwrap();
+        assert_eq!(service_region(&local, "api.ecr"), local);
+    }
+
+    #[test]
+    fn custom_regions_require_absolute_endpoint() {
+        assert_eq!(
+            custom_region("us-east-1", "http://localhost:4566/").unwrap(),
+            Region::Custom {
+                name: "us-east-1".to_string(),
+                endpoint: "http://localhost:4566".to_string(),
+            }
+        );
+        assert!(custom_region("us-east-1", "localhost:4566").is_err());
+        assert!(custom_region("local", "http://localhost:4566").is_err());
+    }
+
+    #[test]
+    fn region_is_extracted_from_task_arn() {
+        assert_eq!(
+            region_from_task_arn("arn:aws:ecs:ap-southeast-1:123456789012:task/default/abcdef"),
+            Some("ap-southeast-1".to_string())
+        );
+        assert_eq!(region_from_task_arn("not-an-arn"), None);
+    }
+}
@@ -0,0 +1,364 @@
+//! Signature Version 4 primitives for the signatures rusoto cannot produce
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use percent_encoding::utf8_percent_encode;
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::param::{Params, ServiceParams};
+use rusoto_core::signature::{
+    decode_uri, SignedRequest, SignedRequestPayload, STRICT_ENCODE_SET, STRICT_PATH_ENCODE_SET,
+};
+use sha2::{Digest, Sha256};
+
+/// Name of the signing algorithm
+pub(crate) const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// Format of the date of a credential scope
+pub(crate) const DATE_FORMAT: &str = "%Y%m%d";
+
+/// Format of `X-Amz-Date`
+pub(crate) const DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// The credential scope of signatures made at `now` for `service` in `region`
+pub(crate) fn scope(now: DateTime<Utc>, region: &str, service: &str) -> String {
+    format!(
+        "{}/{}/{}/aws4_request",
+        now.format(DATE_FORMAT),
+        region,
+        service
+    )
+}
+
+/// The key derived from `secret` that signs requests made at `now` for `service` in `region`
+pub(crate) fn signing_key(
+    secret: &str,
+    now: DateTime<Utc>,
+    region: &str,
+    service: &str,
+) -> Vec<u8> {
+    let date = now.format(DATE_FORMAT).to_string();
+    [date.as_str(), region, service, "aws4_request"]
+        .iter()
+        .fold(format!("AWS4{}", secret).into_bytes(), |key, part| {
+            hmac(&key, part.as_bytes())
+        })
+}
+
+/// The canonical form of a request, and the names of the headers it signs
+///
+/// `headers` maps lowercase names to values. S3 paths are encoded once, the paths of other
+/// services twice.
+pub(crate) fn canonical_request(
+    method: &str,
+    service: &str,
+    path: &str,
+    params: &Params,
+    headers: &BTreeMap<String, String>,
+    payload_hash: &str,
+) -> (String, String) {
+    let mut path = utf8_percent_encode(&decode_uri(path), &STRICT_PATH_ENCODE_SET).to_string();
+    if service != "s3" {
+        path = utf8_percent_encode(&path, &STRICT_PATH_ENCODE_SET).to_string();
+    }
+    if path.is_empty() {
+        path.push('/');
+    }
+    let query = canonical_query(params);
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = headers.keys().cloned().collect::<Vec<_>>().join(";");
+    let request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method, path, query, canonical_headers, signed_headers, payload_hash
+    );
+    (request, signed_headers)
+}
+
+/// `params` as a query string in canonical form, sorted and strictly encoded
+pub(crate) fn canonical_query(params: &Params) -> String {
+    params
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(key, &STRICT_ENCODE_SET),
+                utf8_percent_encode(value.as_deref().unwrap_or_default(), &STRICT_ENCODE_SET)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Signs `request` with headers like `SignedRequest::sign`, but as if it were `now`
+///
+/// rusoto always signs with the current time, so its signatures cannot be reproduced.
+pub(crate) fn sign_at(
+    request: &mut SignedRequest,
+    credentials: &AwsCredentials,
+    now: DateTime<Utc>,
+) {
+    request.complement();
+    sign_complemented(request, credentials, now);
+}
+
+/// Signs `request` like [`sign_at`], but without the `content-type: application/octet-stream`
+/// rusoto adds to requests without one, so that only headers of the request itself are signed
+pub(crate) fn sign_as_is_at(
+    request: &mut SignedRequest,
+    credentials: &AwsCredentials,
+    now: DateTime<Utc>,
+) {
+    let has_content_type = request.headers().contains_key("content-type");
+    request.complement();
+    if !has_content_type {
+        request.remove_header("content-type");
+    }
+    sign_complemented(request, credentials, now);
+}
+
+/// Signs `request`, which `SignedRequest::complement` has been called on
+fn sign_complemented(
+    request: &mut SignedRequest,
+    credentials: &AwsCredentials,
+    now: DateTime<Utc>,
+) {
+    let timestamp = now.format(DATE_TIME_FORMAT).to_string();
+    request.remove_header("x-amz-date");
+    request.add_header("x-amz-date", &timestamp);
+    if let Some(token) = credentials.token() {
+        request.remove_header("x-amz-security-token");
+        request.add_header("x-amz-security-token", token);
+    }
+    let payload_hash = match request.payload {
+        Some(SignedRequestPayload::Buffer(ref payload)) => hex_sha256(payload),
+        Some(SignedRequestPayload::Stream(_)) => "UNSIGNED-PAYLOAD".to_string(),
+        None => hex_sha256(b""),
+    };
+    request.remove_header("x-amz-content-sha256");
+    request.add_header("x-amz-content-sha256", &payload_hash);
+
+    let region = request.region_for_service();
+    let scope = scope(now, &region, &request.service);
+    let (canonical_request, signed_headers) = canonical_request(
+        &request.method,
+        &request.service,
+        &request.canonical_path(),
+        &request.params,
+        &signed_header_values(request),
+        &payload_hash,
+    );
+    let signature = signature(
+        credentials,
+        now,
+        &region,
+        &request.service,
+        &canonical_request,
+    );
+    request.remove_header("authorization");
+    request.add_header(
+        "authorization",
+        &format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            ALGORITHM,
+            credentials.aws_access_key_id(),
+            scope,
+            signed_headers,
+            signature
+        ),
+    );
+}
+
+/// Pre-signs `request` like `SignedRequest::generate_presigned_url`, but as if it were `now`
+pub(crate) fn presign_at(
+    request: &mut SignedRequest,
+    credentials: &AwsCredentials,
+    expires_in: &Duration,
+    now: DateTime<Utc>,
+) -> String {
+    let hostname = request.hostname();
+    request.remove_header("host");
+    request.add_header("host", &hostname);
+    let headers = signed_header_values(request);
+    let signed_headers = headers.keys().cloned().collect::<Vec<_>>().join(";");
+
+    let region = request.region.name().to_string();
+    let credential = format!(
+        "{}/{}",
+        credentials.aws_access_key_id(),
+        scope(now, &region, &request.service)
+    );
+    let params = &mut request.params;
+    params.put("X-Amz-Algorithm", ALGORITHM);
+    params.put("X-Amz-Credential", credential);
+    params.put("X-Amz-Date", now.format(DATE_TIME_FORMAT).to_string());
+    params.put("X-Amz-Expires", expires_in.as_secs().to_string());
+    if let Some(token) = credentials.token() {
+        params.put("X-Amz-Security-Token", token);
+    }
+    params.put("X-Amz-SignedHeaders", &signed_headers);
+
+    let (canonical_request, _) = canonical_request(
+        &request.method,
+        &request.service,
+        &request.canonical_path(),
+        &request.params,
+        &headers,
+        &hex_sha256(b""),
+    );
+    let signature = signature(
+        credentials,
+        now,
+        &region,
+        &request.service,
+        &canonical_request,
+    );
+    request.params.put("X-Amz-Signature", signature);
+    format!(
+        "{}://{}{}?{}",
+        request.scheme(),
+        hostname,
+        request.canonical_path(),
+        canonical_query(&request.params)
+    )
+}
+
+/// The headers of `request` that are signed, with their values in canonical form
+fn signed_header_values(request: &SignedRequest) -> BTreeMap<String, String> {
+    request
+        .headers()
+        .iter()
+        .filter(|(name, _)| {
+            !["authorization", "content-length", "user-agent"].contains(&name.as_str())
+        })
+        .map(|(name, values)| {
+            let values = values
+                .iter()
+                .map(|value| {
+                    let value = String::from_utf8_lossy(value);
+                    value.split_whitespace().collect::<Vec<_>>().join(" ")
+                })
+                .collect::<Vec<_>>();
+            (name.clone(), values.join(","))
+        })
+        .collect()
+}
+
+/// The signature of `canonical_request` made at `now` for `service` in `region`
+fn signature(
+    credentials: &AwsCredentials,
+    now: DateTime<Utc>,
+    region: &str,
+    service: &str,
+    canonical_request: &str,
+) -> String {
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        now.format(DATE_TIME_FORMAT),
+        scope(now, region, service),
+        hex_sha256(canonical_request.as_bytes())
+    );
+    let key = signing_key(credentials.aws_secret_access_key(), now, region, service);
+    hex::encode(hmac(&key, string_to_sign.as_bytes()))
+}
+
+/// HMAC-SHA256 of `message` with `key`
+pub(crate) fn hmac(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC to accept keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Hex encoded SHA-256 of `data`
+pub(crate) fn hex_sha256(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn signing_keys_are_derived() {
+        // The example of the SigV4 documentation
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            now,
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "c4afb1cc5771d871763a393e44b703571b55cc28424d1a5e86da6ed3c154a4b9"
+        );
+        assert_eq!(
+            scope(now, "us-east-1", "iam"),
+            "20150830/us-east-1/iam/aws4_request"
+        );
+    }
+
+    #[test]
+    fn requests_are_signed_like_rusoto_signs_them() {
+        let credentials =
+            AwsCredentials::new("AKIDEXAMPLE", "secret", Some("token".to_string()), None);
+        let mut params = Params::new();
+        params.put("Action", "GetCallerIdentity");
+        let mut request = SignedRequest::new("POST", "sts", &rusoto_core::Region::EuWest1, "/");
+        request.set_payload(Some(b"Action=GetCallerIdentity".to_vec()));
+        request.add_header("X-Custom", "a  b ");
+        let mut reproduced = SignedRequest::new("POST", "sts", &rusoto_core::Region::EuWest1, "/");
+        reproduced.set_payload(Some(b"Action=GetCallerIdentity".to_vec()));
+        reproduced.add_header("X-Custom", "a  b ");
+
+        request.sign(&credentials);
+        let date = String::from_utf8(request.headers()["x-amz-date"][0].clone()).unwrap();
+        let now = Utc.from_utc_datetime(
+            &chrono::NaiveDateTime::parse_from_str(&date, DATE_TIME_FORMAT).unwrap(),
+        );
+        sign_at(&mut reproduced, &credentials, now);
+        assert_eq!(reproduced.headers(), request.headers());
+
+        // Runs of spaces collapse to one, which rusoto only does for pairs of spaces
+        let mut spaced = SignedRequest::new("POST", "sts", &rusoto_core::Region::EuWest1, "/");
+        spaced.set_payload(Some(b"Action=GetCallerIdentity".to_vec()));
+        spaced.add_header("X-Custom", "a    b");
+        let mut collapsed = SignedRequest::new("POST", "sts", &rusoto_core::Region::EuWest1, "/");
+        collapsed.set_payload(Some(b"Action=GetCallerIdentity".to_vec()));
+        collapsed.add_header("X-Custom", "a b");
+        collapsed.sign(&credentials);
+        let date = String::from_utf8(collapsed.headers()["x-amz-date"][0].clone()).unwrap();
+        let now = Utc.from_utc_datetime(
+            &chrono::NaiveDateTime::parse_from_str(&date, DATE_TIME_FORMAT).unwrap(),
+        );
+        sign_at(&mut spaced, &credentials, now);
+        assert_eq!(
+            spaced.headers()["authorization"],
+            collapsed.headers()["authorization"]
+        );
+
+        let mut request = SignedRequest::new("GET", "sts", &rusoto_core::Region::EuWest1, "/");
+        request.set_params(params.clone());
+        request.add_header("x-k8s-aws-id", "prod");
+        let mut reproduced = SignedRequest::new("GET", "sts", &rusoto_core::Region::EuWest1, "/");
+        reproduced.set_params(params);
+        reproduced.add_header("x-k8s-aws-id", "prod");
+
+        let expires_in = Duration::from_secs(60);
+        let url = request.generate_presigned_url(&credentials, &expires_in, true);
+        let date = url.split("X-Amz-Date=").nth(1).unwrap()[..16].to_string();
+        let now = Utc.from_utc_datetime(
+            &chrono::NaiveDateTime::parse_from_str(&date, DATE_TIME_FORMAT).unwrap(),
+        );
+        assert_eq!(
+            presign_at(&mut reproduced, &credentials, &expires_in, now),
+            url
+        );
+    }
+}
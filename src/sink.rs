@@ -0,0 +1,305 @@
+//! Writing tokens and payloads to files for other processes, like Vault agent's file sinks
+use std::fs::{self, OpenOptions};
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use rand::Rng;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use crate::client::AwsAuthIamPayload;
+use crate::Error;
+
+const DEFAULT_MODE: u32 = 0o600;
+
+/// How long before a token expires it is replaced, unless configured otherwise
+pub const DEFAULT_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Delay before retrying to generate or write a token
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// A file that is atomically replaced every time something is written to it
+///
+/// Contents are written to a uniquely named temporary file next to the sink, which is then
+/// renamed over the sink. Readers therefore never see a partially written file. On Unix, the
+/// file is created with the permissions `0600` unless configured otherwise.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileSink {
+    path: PathBuf,
+    #[cfg_attr(not(unix), allow(dead_code))]
+    mode: u32,
+}
+
+impl FileSink {
+    /// A sink writing to the file at `path`
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            mode: DEFAULT_MODE,
+        }
+    }
+
+    /// Create the file with the Unix permissions `mode` instead. Ignored on other platforms.
+    pub fn with_mode(mut self, mode: u32) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write a token, such as a Vault token, without a trailing new line like Vault agent does
+    pub fn write_token(&self, token: &str) -> Result<(), Error> {
+        self.write(token.as_bytes())
+    }
+
+    /// Write the wrapping information of a wrapped response as JSON, like Vault agent does for
+    /// sinks with `wrap_ttl`
+    #[cfg(feature = "vault-client")]
+    pub fn write_wrapped(&self, wrap_info: &crate::vault::WrapInfo) -> Result<(), Error> {
+        self.write(&serde_json::to_vec(wrap_info)?)
+    }
+
+    /// Write `payload` as JSON
+    pub fn write_payload(&self, payload: &AwsAuthIamPayload) -> Result<(), Error> {
+        self.write(&serde_json::to_vec(payload)?)
+    }
+
+    /// Atomically replace the contents of the file with `contents`
+    pub fn write(&self, contents: &[u8]) -> Result<(), Error> {
+        let file_name = self.path.file_name().ok_or_else(|| {
+            Error::GenericError(format!("Sink {} is not a file", self.path.display()))
+        })?;
+        let mut temporary_name = std::ffi::OsString::from(".");
+        temporary_name.push(file_name);
+        // Processes in different containers can share a process ID, and a process may be writing
+        // the same sink from several tasks
+        temporary_name.push(format!(
+            ".{}.{:016x}.tmp",
+            std::process::id(),
+            rand::thread_rng().gen::<u64>()
+        ));
+        let temporary = self.path.with_file_name(temporary_name);
+
+        debug!("Writing sink {}", self.path.display());
+        let result = self.write_temporary(&temporary, contents).and_then(|()| {
+            fs::rename(&temporary, &self.path)?;
+            Ok(())
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        result
+    }
+
+    fn write_temporary(&self, path: &Path, contents: &[u8]) -> Result<(), Error> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(self.mode);
+        }
+
+        let mut file = options.open(path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+/// Keeps a token in a [`FileSink`] fresh in a background task, like a projected service account
+/// token
+///
+/// `generate` produces a token and how long it is valid for. The token is rewritten `margin`
+/// before it expires, and failures are retried every few seconds for as long as the writer
+/// runs. Tokens that do not expire are written once.
+///
+/// ```rust,no_run
+/// use aws_auth_payload::sink::{FileSink, TokenFileWriter, DEFAULT_REFRESH_MARGIN};
+///
+/// # async fn run() -> Result<(), aws_auth_payload::Error> {
+/// let sink = FileSink::new("/var/run/secrets/eks/token");
+/// let writer = TokenFileWriter::start(sink, DEFAULT_REFRESH_MARGIN, || async {
+///     let credentials = aws_auth_payload::get_aws_credentials().await?;
+///     let token = aws_auth_payload::client::eks_token(
+///         &credentials,
+///         "my-cluster",
+///         None::<rusoto_core::Region>,
+///     );
+///     Ok((token.to_string(), Some(token.remaining_ttl())))
+/// })
+/// .await?;
+/// // ...
+/// writer.shutdown().await;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Dropping the writer stops the background task.
+#[derive(Debug)]
+pub struct TokenFileWriter {
+    shutdown: Arc<Notify>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl TokenFileWriter {
+    /// Write the first token to `sink` and keep replacing it
+    ///
+    /// Fails if the first token cannot be generated or written. This must be called from within
+    /// a Tokio runtime.
+    pub async fn start<F, Fut>(sink: FileSink, margin: Duration, generate: F) -> Result<Self, Error>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(String, Option<Duration>), Error>> + Send,
+    {
+        let (token, ttl) = generate().await?;
+        sink.write_token(&token)?;
+
+        let shutdown = Arc::new(Notify::new());
+        let stopped = Arc::clone(&shutdown);
+        let handle = tokio::spawn(async move {
+            let mut ttl = ttl;
+            loop {
+                let delay = match ttl {
+                    Some(ttl) => ttl.checked_sub(margin).unwrap_or_default(),
+                    None => {
+                        stopped.notified().await;
+                        return;
+                    }
+                };
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = stopped.notified() => return,
+                }
+
+                ttl = loop {
+                    let written = async {
+                        let (token, ttl) = generate().await?;
+                        sink.write_token(&token)?;
+                        Ok::<_, Error>(ttl)
+                    };
+                    match written.await {
+                        Ok(ttl) => break ttl,
+                        Err(e) => warn!(
+                            "Refreshing token file {} failed, retrying: {}",
+                            sink.path().display(),
+                            e
+                        ),
+                    }
+                    tokio::select! {
+                        _ = tokio::time::sleep(RETRY_DELAY) => {}
+                        _ = stopped.notified() => return,
+                    }
+                };
+                info!("Refreshed token file {}", sink.path().display());
+            }
+        });
+
+        Ok(Self {
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    /// Stop refreshing the token and wait for the background task to finish
+    ///
+    /// The file is left in place.
+    pub async fn shutdown(mut self) {
+        self.shutdown.notify_one();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.await;
+        }
+    }
+}
+
+impl Drop for TokenFileWriter {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.handle {
+            handle.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn sinks_are_replaced_atomically() -> Result<(), crate::Error> {
+        let directory =
+            std::env::temp_dir().join(format!("aws-auth-payload-sink-{}", std::process::id()));
+        fs::create_dir_all(&directory)?;
+        let sink = FileSink::new(directory.join("token"));
+
+        sink.write_token("s.first")?;
+        sink.write_token("s.second")?;
+        assert_eq!(fs::read_to_string(sink.path())?, "s.second");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(sink.path())?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let payload = crate::client::tests::post_aws_iam_payload(None, HashMap::new()).await?;
+        sink.write_payload(&payload)?;
+        let written: AwsAuthIamPayload = serde_json::from_slice(&fs::read(sink.path())?)?;
+        assert_eq!(written, payload);
+        assert_eq!(fs::read_dir(&directory)?.count(), 1);
+
+        // Writers of the same process do not trip over each other's temporary files
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let sink = sink.clone();
+                std::thread::spawn(move || sink.write_token(&format!("s.{}", i)))
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap()?;
+        }
+        assert_eq!(fs::read_dir(&directory)?.count(), 1);
+
+        fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn token_files_are_refreshed_before_expiry() -> Result<(), crate::Error> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let directory = std::env::temp_dir().join(format!(
+            "aws-auth-payload-token-file-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory)?;
+        let sink = FileSink::new(directory.join("token"));
+        let path = sink.path().to_path_buf();
+
+        let generated = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&generated);
+        let writer = TokenFileWriter::start(sink, Duration::from_millis(900), move || {
+            let token = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            async move { Ok((format!("token-{}", token), Some(Duration::from_secs(1)))) }
+        })
+        .await?;
+        assert_eq!(fs::read_to_string(&path)?, "token-1");
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        writer.shutdown().await;
+        let written = generated.load(Ordering::SeqCst);
+        assert!(written >= 2);
+        assert_eq!(fs::read_to_string(&path)?, format!("token-{}", written));
+
+        fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
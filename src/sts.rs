@@ -0,0 +1,318 @@
+//! Calling AWS STS with the requests generated by this library
+use std::borrow::{Borrow, Cow};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use log::{debug, info};
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::request::{BufferedHttpResponse, DispatchSignedRequest, HttpClient};
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::Region;
+use serde::{Deserialize, Serialize};
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::arn::Arn;
+use crate::client::caller_identity_request;
+use crate::region::Partition;
+use crate::Error;
+
+/// Identity of a caller as reported by AWS STS `GetCallerIdentity`
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct CallerIdentity {
+    /// ARN of the principal that signed the request
+    ///
+    /// Use [`Arn::principal`] to find out what kind of principal this is.
+    pub arn: Arn,
+    /// ID of the AWS account the principal belongs to
+    pub account: String,
+    /// Unique identifier of the principal
+    pub user_id: String,
+}
+
+/// Code of an error reported by AWS STS
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum StsErrorCode {
+    /// The signer has no permission for the action
+    AccessDenied,
+    /// The session token of the signer has expired
+    ExpiredToken,
+    /// The request is missing parts of the signature
+    IncompleteSignature,
+    /// The access key ID or session token of the signer does not exist
+    InvalidClientTokenId,
+    /// The request is not signed
+    MissingAuthenticationToken,
+    /// The request was signed too long ago, or its pre-signed URL has expired
+    RequestExpired,
+    /// The signature does not match the request, for example because it was modified
+    SignatureDoesNotMatch,
+    /// Too many requests were sent
+    Throttling,
+    /// Any other code
+    Other(String),
+}
+
+impl StsErrorCode {
+    /// The code as reported by STS
+    pub fn as_str(&self) -> &str {
+        match self {
+            StsErrorCode::AccessDenied => "AccessDenied",
+            StsErrorCode::ExpiredToken => "ExpiredToken",
+            StsErrorCode::IncompleteSignature => "IncompleteSignature",
+            StsErrorCode::InvalidClientTokenId => "InvalidClientTokenId",
+            StsErrorCode::MissingAuthenticationToken => "MissingAuthenticationToken",
+            StsErrorCode::RequestExpired => "RequestExpired",
+            StsErrorCode::SignatureDoesNotMatch => "SignatureDoesNotMatch",
+            StsErrorCode::Throttling => "Throttling",
+            StsErrorCode::Other(code) => code,
+        }
+    }
+
+    /// Whether the error is caused by the credentials or signature of the request, rather than
+    /// by STS
+    pub fn is_authentication_failure(&self) -> bool {
+        !matches!(self, StsErrorCode::Throttling | StsErrorCode::Other(_))
+    }
+}
+
+impl From<&str> for StsErrorCode {
+    fn from(code: &str) -> Self {
+        match code {
+            "AccessDenied" => StsErrorCode::AccessDenied,
+            "ExpiredToken" => StsErrorCode::ExpiredToken,
+            "IncompleteSignature" => StsErrorCode::IncompleteSignature,
+            "InvalidClientTokenId" => StsErrorCode::InvalidClientTokenId,
+            "MissingAuthenticationToken" => StsErrorCode::MissingAuthenticationToken,
+            "RequestExpired" => StsErrorCode::RequestExpired,
+            "SignatureDoesNotMatch" => StsErrorCode::SignatureDoesNotMatch,
+            "Throttling" => StsErrorCode::Throttling,
+            code => StsErrorCode::Other(code.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for StsErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Calls AWS STS `GetCallerIdentity` with the provided credentials and returns their identity
+///
+/// This is a cheap sanity check to run before attempting a Vault or Kubernetes login with a
+/// payload signed by the same credentials.
+/// If you do not provide a `region`, we will use a the "global" AWS STS endpoint.
+pub async fn whoami<R>(
+    credentials: &AwsCredentials,
+    region: Option<R>,
+) -> Result<CallerIdentity, Error>
+where
+    R: Borrow<Region>,
+{
+    let dispatcher = HttpClient::new()?;
+    whoami_with_dispatcher(&dispatcher, credentials, region).await
+}
+
+pub(crate) async fn whoami_with_dispatcher<D, R>(
+    dispatcher: &D,
+    credentials: &AwsCredentials,
+    region: Option<R>,
+) -> Result<CallerIdentity, Error>
+where
+    D: DispatchSignedRequest,
+    R: Borrow<Region>,
+{
+    info!("Calling AWS STS GetCallerIdentity");
+    let region = region
+        .as_ref()
+        .map(|r| Cow::Borrowed(r.borrow()))
+        .unwrap_or_default();
+
+    let mut request = caller_identity_request(&region, HashMap::new());
+    request.sign(credentials);
+
+    let response = dispatch(dispatcher, request).await?;
+    let identity = parse_caller_identity(&response.body)?;
+    debug!("Caller identity: {:#?}", identity);
+    Ok(identity)
+}
+
+/// Host names of the FIPS endpoints of STS outside of GovCloud, whose regional endpoints are
+/// all FIPS endpoints
+pub const FIPS_ENDPOINT_HOSTS: [&str; 4] = [
+    "sts-fips.us-east-1.amazonaws.com",
+    "sts-fips.us-east-2.amazonaws.com",
+    "sts-fips.us-west-1.amazonaws.com",
+    "sts-fips.us-west-2.amazonaws.com",
+];
+
+/// Host names of the global STS endpoint, of the regional endpoints of the known partitions and
+/// of the [`FIPS_ENDPOINT_HOSTS`]
+///
+/// These are the hosts verifiers accept by default. Extend them to accept, for example, the
+/// hosts of STS VPC endpoints.
+pub fn endpoint_hosts() -> HashSet<String> {
+    let mut hosts: HashSet<String> = Partition::ALL
+        .iter()
+        .flat_map(|partition| {
+            partition
+                .regions()
+                .iter()
+                .map(move |region| format!("sts.{}.{}", region, partition.dns_suffix()))
+        })
+        .collect();
+    hosts.insert("sts.amazonaws.com".to_string());
+    hosts.extend(FIPS_ENDPOINT_HOSTS.iter().map(|host| host.to_string()));
+    hosts
+}
+
+/// Dispatches `request` and buffers the response, turning STS error responses into errors
+pub(crate) async fn dispatch<D>(
+    dispatcher: &D,
+    request: SignedRequest,
+) -> Result<BufferedHttpResponse, Error>
+where
+    D: DispatchSignedRequest,
+{
+    let response = dispatch_buffered(dispatcher, request).await?;
+    if !response.status.is_success() {
+        return Err(parse_error(response.status.as_u16(), &response.body));
+    }
+    Ok(response)
+}
+
+/// Send `request` and buffer the response, whatever its status
+pub(crate) async fn dispatch_buffered<D>(
+    dispatcher: &D,
+    request: SignedRequest,
+) -> Result<BufferedHttpResponse, Error>
+where
+    D: DispatchSignedRequest + ?Sized,
+{
+    let mut response = dispatcher.dispatch(request, None).await?;
+    Ok(response.buffer().await?)
+}
+
+/// Parses the body of a `GetCallerIdentityResponse`
+pub fn parse_caller_identity(body: &[u8]) -> Result<CallerIdentity, Error> {
+    let mut elements = leaf_elements(body)?;
+    let mut take = |name: &str| {
+        elements.remove(name).ok_or_else(|| {
+            Error::GenericError(format!("GetCallerIdentity response is missing {}", name))
+        })
+    };
+
+    Ok(CallerIdentity {
+        arn: take("Arn")?.parse()?,
+        account: take("Account")?,
+        user_id: take("UserId")?,
+    })
+}
+
+/// Parses the body of an STS `ErrorResponse` returned with the HTTP `status`
+pub fn parse_error(status: u16, body: &[u8]) -> Error {
+    let mut elements = leaf_elements(body).unwrap_or_default();
+    Error::StsError {
+        status,
+        code: elements
+            .remove("Code")
+            .as_deref()
+            .unwrap_or("Unknown")
+            .into(),
+        message: elements
+            .remove("Message")
+            .unwrap_or_else(|| String::from_utf8_lossy(body).into_owned()),
+    }
+}
+
+/// Collects the text of all elements without children, keyed by their local name
+///
+/// The STS responses this library deals with never repeat element names, so the nesting of the
+/// document can be ignored.
+fn leaf_elements(body: &[u8]) -> Result<HashMap<String, String>, Error> {
+    let mut elements = HashMap::new();
+    let mut current = None;
+
+    for event in EventReader::new(body) {
+        match event? {
+            XmlEvent::StartElement { name, .. } => current = Some(name.local_name),
+            XmlEvent::Characters(text) => {
+                if let Some(name) = current.take() {
+                    elements.insert(name, text);
+                }
+            }
+            XmlEvent::EndElement { .. } => current = None,
+            _ => {}
+        }
+    }
+
+    Ok(elements)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use rusoto_mock::MockRequestDispatcher;
+
+    pub(crate) const CALLER_IDENTITY_RESPONSE: &str = r#"<GetCallerIdentityResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <GetCallerIdentityResult>
+    <Arn>arn:aws:sts::123456789012:assumed-role/my-role/my-session</Arn>
+    <UserId>AROAEXAMPLE:my-session</UserId>
+    <Account>123456789012</Account>
+  </GetCallerIdentityResult>
+  <ResponseMetadata>
+    <RequestId>01234567-89ab-cdef-0123-456789abcdef</RequestId>
+  </ResponseMetadata>
+</GetCallerIdentityResponse>"#;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn whoami_returns_caller_identity() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let dispatcher = MockRequestDispatcher::with_status(200)
+            .with_body(CALLER_IDENTITY_RESPONSE)
+            .with_request_checker(|request| {
+                assert_eq!(request.method, "POST");
+                assert!(request.headers.contains_key("authorization"));
+            });
+
+        let identity =
+            whoami_with_dispatcher(&dispatcher, &credentials, Some(Region::UsWest2)).await?;
+
+        assert_eq!(
+            identity,
+            CallerIdentity {
+                arn: "arn:aws:sts::123456789012:assumed-role/my-role/my-session".parse()?,
+                account: "123456789012".to_string(),
+                user_id: "AROAEXAMPLE:my-session".to_string(),
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn whoami_reports_sts_errors() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let dispatcher = MockRequestDispatcher::with_status(403).with_body(
+            r#"<ErrorResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <Error>
+    <Type>Sender</Type>
+    <Code>InvalidClientTokenId</Code>
+    <Message>The security token included in the request is invalid.</Message>
+  </Error>
+  <RequestId>01234567-89ab-cdef-0123-456789abcdef</RequestId>
+</ErrorResponse>"#,
+        );
+
+        let result = whoami_with_dispatcher(&dispatcher, &credentials, None::<Region>).await;
+        match result {
+            Err(Error::StsError { status, code, .. }) => {
+                assert_eq!(status, 403);
+                assert_eq!(code, StsErrorCode::InvalidClientTokenId);
+                assert!(code.is_authentication_failure());
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        Ok(())
+    }
+}
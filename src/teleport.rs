@@ -0,0 +1,90 @@
+//! Signed identity requests for the
+//! [IAM join method](https://goteleport.com/docs/agents/join-services-to-your-cluster/aws-iam/)
+//! of Teleport
+//!
+//! When an agent joins with the IAM method, the Teleport auth server sends it a challenge. The
+//! agent answers with a `GetCallerIdentity` request that includes the challenge in a signed
+//! header, serialized as a raw HTTP/1.1 request. The auth server forwards it to STS to learn the
+//! identity of the agent.
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use log::{debug, info};
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::signature::SignedRequestPayload;
+use rusoto_core::Region;
+
+use crate::client::{caller_identity_request, signed_headers};
+
+/// Header Teleport expects the challenge of the auth server in
+pub const CHALLENGE_HEADER: &str = "X-Teleport-Challenge";
+
+/// Sign a `GetCallerIdentity` request answering `challenge` and serialize it for Teleport
+///
+/// The result is the `stsIdentityRequest` of a `RegisterUsingIAMMethod` call. Teleport only
+/// accepts requests to the public STS endpoints it knows. If you do not provide a `region`, the
+/// region from `AWS_DEFAULT_REGION` or `AWS_REGION` is used, defaulting to `us-east-1`.
+pub fn iam_join_request(
+    credentials: &AwsCredentials,
+    region: Option<&Region>,
+    challenge: &str,
+) -> Vec<u8> {
+    info!("Signing Teleport IAM join request");
+    let region = region.cloned().unwrap_or_default();
+    let mut headers = HashMap::new();
+    headers.insert(CHALLENGE_HEADER, challenge);
+    headers.insert("Accept", "application/json");
+
+    let mut request = caller_identity_request(&region, headers);
+    request.sign(credentials);
+
+    let body = match request.payload {
+        Some(SignedRequestPayload::Buffer(ref buffer)) => buffer.to_vec(),
+        _ => unreachable!("Payload was set when building the request"),
+    };
+    let mut headers: Vec<_> = signed_headers(&request).into_iter().collect();
+    headers.sort();
+
+    let mut head = format!("POST {} HTTP/1.1\r\n", request.canonical_path());
+    if !headers.iter().any(|(name, _)| name == "host") {
+        let _ = write!(head, "Host: {}\r\n", request.hostname());
+    }
+    if !headers.iter().any(|(name, _)| name == "content-length") {
+        let _ = write!(head, "Content-Length: {}\r\n", body.len());
+    }
+    for (name, values) in headers {
+        for value in values {
+            let _ = write!(head, "{}: {}\r\n", name, value);
+        }
+    }
+    head.push_str("\r\n");
+    debug!("Teleport IAM join request for {}", request.hostname());
+
+    let mut serialized = head.into_bytes();
+    serialized.extend_from_slice(&body);
+    serialized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn join_requests_sign_the_challenge() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+
+        let request = iam_join_request(&credentials, Some(&Region::UsWest2), "Y2hhbGxlbmdl");
+        let request = String::from_utf8(request).unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        let mut lines = head.split("\r\n");
+
+        assert_eq!(lines.next(), Some("POST / HTTP/1.1"));
+        let headers: HashMap<&str, &str> =
+            lines.map(|line| line.split_once(": ").unwrap()).collect();
+        assert_eq!(headers["host"], "sts.us-west-2.amazonaws.com");
+        assert_eq!(headers["x-teleport-challenge"], "Y2hhbGxlbmdl");
+        assert!(headers["authorization"].contains("x-teleport-challenge"));
+        assert_eq!(body, "Action=GetCallerIdentity&Version=2011-06-15");
+        Ok(())
+    }
+}
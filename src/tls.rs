@@ -0,0 +1,324 @@
+//! TLS configuration for the HTTP clients of this library, such as [`vault::Client`]
+//!
+//! This module requires the `consul-client` or `vault-client` feature.
+//!
+//! [`vault::Client`]: crate::vault::Client
+use std::env;
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper::Uri;
+use hyper_tls::MaybeHttpsStream;
+use log::warn;
+use native_tls::{Certificate, Identity};
+use tokio::net::TcpStream;
+
+use crate::Error;
+
+const PEM_CERTIFICATE_END: &str = "-----END CERTIFICATE-----";
+
+/// A PEM encoded client certificate and its PKCS#8 private key, for mutual TLS
+#[derive(Clone, Eq, PartialEq)]
+pub struct ClientCertificate {
+    /// PEM encoded certificate chain
+    pub certificate: Vec<u8>,
+    /// PEM encoded PKCS#8 private key
+    pub key: Vec<u8>,
+}
+
+impl std::fmt::Debug for ClientCertificate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientCertificate")
+            .field("certificate", &String::from_utf8_lossy(&self.certificate))
+            .field("key", &"<redacted>")
+            .finish()
+    }
+}
+
+/// How to establish TLS connections
+///
+/// The [`Default`] configuration trusts the certificate authorities of the system and verifies
+/// the host name of the server.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TlsConfig {
+    /// PEM encoded certificates of authorities to trust in addition to those of the system
+    pub ca_certificates: Vec<Vec<u8>>,
+    /// Certificate to present to servers that require client certificates
+    pub client_certificate: Option<ClientCertificate>,
+    /// Name to send with SNI and verify the certificate of the server against, instead of the
+    /// host of the URL
+    pub server_name: Option<String>,
+    /// Do not verify the certificate of the server at all
+    ///
+    /// This makes connections vulnerable to man-in-the-middle attacks and should never be used
+    /// outside of development.
+    pub insecure_skip_verify: bool,
+}
+
+impl TlsConfig {
+    /// Configure TLS from the environment variables used by the Vault CLI
+    ///
+    /// - `VAULT_CACERT`: PEM file with certificate authorities to trust
+    /// - `VAULT_CAPATH`: Directory of PEM files with certificate authorities to trust
+    /// - `VAULT_CLIENT_CERT` and `VAULT_CLIENT_KEY`: PEM files with the client certificate and
+    ///   its PKCS#8 private key
+    /// - `VAULT_TLS_SERVER_NAME`: Name to use for SNI and verification
+    /// - `VAULT_SKIP_VERIFY`: Disable verification of the server certificate
+    pub fn from_vault_env() -> Result<Self, Error> {
+        let mut config = Self::default();
+        if let Some(path) = env::var_os("VAULT_CACERT") {
+            config = config.with_ca_file(path)?;
+        }
+        if let Some(path) = env::var_os("VAULT_CAPATH") {
+            config = config.with_ca_directory(path)?;
+        }
+        match (
+            env::var_os("VAULT_CLIENT_CERT"),
+            env::var_os("VAULT_CLIENT_KEY"),
+        ) {
+            (Some(certificate), Some(key)) => {
+                config = config.with_client_certificate(certificate, key)?;
+            }
+            (None, None) => {}
+            _ => {
+                return Err(Error::GenericError(
+                    "VAULT_CLIENT_CERT and VAULT_CLIENT_KEY must be set together".to_string(),
+                ))
+            }
+        }
+        if let Ok(name) = env::var("VAULT_TLS_SERVER_NAME") {
+            config = config.with_server_name(&name);
+        }
+        if let Ok(skip) = env::var("VAULT_SKIP_VERIFY") {
+            let skip = match skip.to_ascii_lowercase().as_str() {
+                "" | "0" | "f" | "false" => false,
+                "1" | "t" | "true" => true,
+                _ => {
+                    return Err(Error::GenericError(format!(
+                        "Invalid value for VAULT_SKIP_VERIFY: {}",
+                        skip
+                    )))
+                }
+            };
+            config = config.danger_skip_verify(skip);
+        }
+        Ok(config)
+    }
+
+    /// Trust the certificate authorities in the PEM file at `path`
+    pub fn with_ca_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self, Error> {
+        let bundle = fs::read_to_string(path)?;
+        self.ca_certificates.extend(split_pem_bundle(&bundle));
+        Ok(self)
+    }
+
+    /// Trust the certificate authorities in all PEM files in the directory at `path`
+    pub fn with_ca_directory<P: AsRef<Path>>(mut self, path: P) -> Result<Self, Error> {
+        let mut paths = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.sort();
+        for path in paths.into_iter().filter(|path| path.is_file()) {
+            self = self.with_ca_file(path)?;
+        }
+        Ok(self)
+    }
+
+    /// Present the client certificate and PKCS#8 private key in the PEM files at the given paths
+    pub fn with_client_certificate<C, K>(mut self, certificate: C, key: K) -> Result<Self, Error>
+    where
+        C: AsRef<Path>,
+        K: AsRef<Path>,
+    {
+        self.client_certificate = Some(ClientCertificate {
+            certificate: fs::read(certificate)?,
+            key: fs::read(key)?,
+        });
+        Ok(self)
+    }
+
+    /// Use `name` for SNI and verification instead of the host of the URL
+    pub fn with_server_name(mut self, name: &str) -> Self {
+        self.server_name = Some(name.to_string()).filter(|name| !name.is_empty());
+        self
+    }
+
+    /// Disable verification of the server certificate when `skip` is true
+    ///
+    /// This makes connections vulnerable to man-in-the-middle attacks and should never be used
+    /// outside of development.
+    pub fn danger_skip_verify(mut self, skip: bool) -> Self {
+        self.insecure_skip_verify = skip;
+        self
+    }
+
+    /// Build a connector for `hyper` that applies this configuration
+    pub(crate) fn connector(&self) -> Result<Connector, Error> {
+        let invalid =
+            |e: native_tls::Error| Error::GenericError(format!("Invalid TLS configuration: {}", e));
+
+        let mut builder = native_tls::TlsConnector::builder();
+        for certificate in &self.ca_certificates {
+            builder.add_root_certificate(Certificate::from_pem(certificate).map_err(invalid)?);
+        }
+        if let Some(client) = &self.client_certificate {
+            let identity =
+                Identity::from_pkcs8(&client.certificate, &client.key).map_err(invalid)?;
+            builder.identity(identity);
+        }
+        if self.insecure_skip_verify {
+            warn!("TLS certificate verification is disabled. Connections are not secure.");
+            builder
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        Ok(Connector {
+            http,
+            tls: builder.build().map_err(invalid)?.into(),
+            server_name: self.server_name.clone(),
+        })
+    }
+}
+
+/// Split a bundle of PEM encoded certificates into individual certificates
+fn split_pem_bundle(bundle: &str) -> Vec<Vec<u8>> {
+    bundle
+        .split_inclusive(PEM_CERTIFICATE_END)
+        .filter(|certificate| certificate.contains(PEM_CERTIFICATE_END))
+        .map(|certificate| certificate.trim().as_bytes().to_vec())
+        .collect()
+}
+
+/// A `hyper` connector for HTTP and HTTPS URLs that can override the TLS server name
+#[derive(Clone)]
+pub(crate) struct Connector {
+    http: HttpConnector,
+    tls: tokio_native_tls::TlsConnector,
+    server_name: Option<String>,
+}
+
+impl std::fmt::Debug for Connector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connector")
+            .field("server_name", &self.server_name)
+            .finish()
+    }
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+impl Service<Uri> for Connector {
+    type Response = MaybeHttpsStream<TcpStream>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let https = uri.scheme_str() == Some("https");
+        let server_name = self
+            .server_name
+            .clone()
+            .or_else(|| {
+                uri.host()
+                    .map(|host| host.trim_matches(|c| c == '[' || c == ']').to_string())
+            })
+            .unwrap_or_default();
+        let tls = self.tls.clone();
+        let connecting = self.http.call(uri);
+
+        Box::pin(async move {
+            let tcp = connecting.await?;
+            if !https {
+                return Ok(MaybeHttpsStream::Http(tcp));
+            }
+            let tls = tls.connect(&server_name, tcp).await?;
+            Ok(MaybeHttpsStream::Https(tls))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+
+    use hyper::server::conn::Http;
+    use hyper::service::service_fn;
+    use hyper::{Body, Request, Response};
+    use tokio::net::TcpListener;
+
+    /// Serve HTTPS with a self-signed certificate for `name` and return the port and certificate
+    async fn serve_tls(name: &str) -> Result<(u16, Vec<u8>), Error> {
+        let certificate = rcgen::generate_simple_self_signed(vec![name.to_string()])
+            .map_err(|e| Error::GenericError(e.to_string()))?;
+        let pem = certificate
+            .serialize_pem()
+            .map_err(|e| Error::GenericError(e.to_string()))?;
+        let identity = Identity::from_pkcs8(
+            pem.as_bytes(),
+            certificate.serialize_private_key_pem().as_bytes(),
+        )
+        .map_err(|e| Error::GenericError(e.to_string()))?;
+        let acceptor: tokio_native_tls::TlsAcceptor = native_tls::TlsAcceptor::new(identity)
+            .map_err(|e| Error::GenericError(e.to_string()))?
+            .into();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    if let Ok(stream) = acceptor.accept(stream).await {
+                        let service = service_fn(|_: Request<Body>| async {
+                            Ok::<_, Infallible>(Response::new(Body::from("ok")))
+                        });
+                        let _ = Http::new().serve_connection(stream, service).await;
+                    }
+                });
+            }
+        });
+        Ok((port, pem.into_bytes()))
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn custom_ca_and_server_name_are_used() -> Result<(), crate::Error> {
+        let (port, certificate) = serve_tls("vault.test").await?;
+        let uri: Uri = format!("https://127.0.0.1:{}/", port).parse().unwrap();
+
+        let untrusted =
+            hyper::Client::builder().build::<_, Body>(TlsConfig::default().connector()?);
+        assert!(untrusted.get(uri.clone()).await.is_err());
+
+        let config = TlsConfig {
+            ca_certificates: vec![certificate],
+            ..Default::default()
+        }
+        .with_server_name("vault.test");
+        let trusted = hyper::Client::builder().build::<_, Body>(config.connector()?);
+        let response = trusted.get(uri).await?;
+        assert_eq!(hyper::body::to_bytes(response.into_body()).await?, "ok");
+        Ok(())
+    }
+
+    #[test]
+    fn pem_bundles_are_split() {
+        let bundle = "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n\
+                      -----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----\n";
+        let certificates = split_pem_bundle(bundle);
+        assert_eq!(certificates.len(), 2);
+        assert!(certificates[1].starts_with(b"-----BEGIN CERTIFICATE-----\nBBBB"));
+    }
+}
@@ -0,0 +1,1259 @@
+//! Logging in to [HashiCorp Vault](https://www.vaultproject.io/) with an AWS IAM payload
+//!
+//! This module requires the `vault-client` feature.
+pub mod config;
+pub mod session;
+pub mod token_helper;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use hyper::body::Bytes;
+use hyper::header::{CONTENT_TYPE, LOCATION};
+use hyper::{Body, Method, Request, StatusCode, Uri};
+use log::{debug, info, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::client::AwsAuthIamPayload;
+use crate::ec2::Ec2AuthPayload;
+use crate::tls::{Connector, TlsConfig};
+use crate::{http, Error};
+
+#[doc(inline)]
+pub use self::config::AuthClientConfig;
+#[doc(inline)]
+pub use self::session::{SessionConfig, VaultSession};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_MOUNT: &str = "aws";
+const MAX_REDIRECTS: usize = 5;
+
+const TOKEN_HEADER: &str = "X-Vault-Token";
+const WRAP_TTL_HEADER: &str = "X-Vault-Wrap-TTL";
+const FORWARD_HEADER: &str = "X-Vault-Forward";
+const INCONSISTENT_HEADER: &str = "X-Vault-Inconsistent";
+
+/// Header used by Vault Enterprise to select the namespace a request applies to
+pub const NAMESPACE_HEADER: &str = "X-Vault-Namespace";
+
+/// Path an AWS auth method is mounted at, such as `aws` or `aws-prod`
+///
+/// Leading and trailing slashes are ignored. The [`Default`] is `aws`, the path Vault mounts the
+/// AWS auth method at unless told otherwise.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct MountPath(String);
+
+impl MountPath {
+    /// Validate and normalize a mount path
+    ///
+    /// Each segment of the path must be non-empty and consist of alphanumeric characters, `-`,
+    /// `_` or `.`, and may not be `.` or `..`.
+    pub fn new(path: &str) -> Result<Self, Error> {
+        let path = path.trim_matches('/');
+        let invalid = |reason: &str| {
+            Error::GenericError(format!("Invalid Vault mount path {}: {}", path, reason))
+        };
+
+        if path.is_empty() {
+            return Err(invalid("path is empty"));
+        }
+        for segment in path.split('/') {
+            if segment.is_empty() || segment == "." || segment == ".." {
+                return Err(invalid("path contains an empty or relative segment"));
+            }
+            if !segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+            {
+                return Err(invalid("path contains unsupported characters"));
+            }
+        }
+
+        Ok(MountPath(path.to_string()))
+    }
+
+    /// The normalized path, without leading or trailing slashes
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for MountPath {
+    fn default() -> Self {
+        MountPath(DEFAULT_MOUNT.to_string())
+    }
+}
+
+impl FromStr for MountPath {
+    type Err = Error;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        Self::new(path)
+    }
+}
+
+impl fmt::Display for MountPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Where and as which role to log in with the AWS auth method
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LoginOptions {
+    /// Path the AWS auth method is mounted at
+    pub mount: MountPath,
+    /// Vault role to log in as
+    ///
+    /// If not set, Vault uses the role named after the IAM principal that signed the payload.
+    pub role: Option<String>,
+}
+
+impl LoginOptions {
+    /// Log in to the auth method mounted at `mount` as `role`
+    pub fn new(mount: MountPath, role: Option<&str>) -> Self {
+        Self {
+            mount,
+            role: role.map(str::to_string),
+        }
+    }
+
+    /// Log in to the auth method mounted at the default `aws` path as `role`
+    pub fn for_role(role: &str) -> Self {
+        Self::new(MountPath::default(), Some(role))
+    }
+}
+
+/// When and how often to retry failed requests to Vault
+///
+/// Vault responds with `412 Precondition Failed` or `5xx` errors while a leader election is in
+/// progress, and HashiCorp's clients retry those. Retries back off exponentially from
+/// `initial_backoff` up to `max_backoff`, with full jitter. The [`Default`] policy makes up to
+/// three attempts.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Upper bound of the delay before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay before any retry
+    pub max_backoff: Duration,
+    /// Retry `5xx` responses
+    pub retry_server_errors: bool,
+    /// Retry `412 Precondition Failed` responses, returned by performance standbys that have not
+    /// caught up yet
+    pub retry_precondition_failed: bool,
+    /// Retry `429 Too Many Requests` responses
+    pub retry_rate_limited: bool,
+    /// Retry requests that failed to connect or timed out
+    pub retry_transport_errors: bool,
+}
+
+impl RetryPolicy {
+    /// Never retry requests
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    fn should_retry(&self, error: &Error) -> bool {
+        match error {
+            Error::VaultError { status, .. } => match *status {
+                412 => self.retry_precondition_failed,
+                429 => self.retry_rate_limited,
+                500..=599 => self.retry_server_errors,
+                _ => false,
+            },
+            Error::HttpError(_) | Error::HttpTimeout => self.retry_transport_errors,
+            _ => false,
+        }
+    }
+
+    /// Upper bound of the delay after the `attempt`th failed attempt, starting from 1
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        self.initial_backoff
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(5),
+            retry_server_errors: true,
+            retry_precondition_failed: true,
+            retry_rate_limited: true,
+            retry_transport_errors: true,
+        }
+    }
+}
+
+/// A client for the HTTP API of a Vault server
+#[derive(Clone, Debug)]
+pub struct Client {
+    address: String,
+    http: hyper::Client<Connector>,
+    timeout: Duration,
+    namespace: Option<String>,
+    retry: RetryPolicy,
+    forward_to_active_node: bool,
+    forward_inconsistent: bool,
+}
+
+#[derive(Serialize)]
+struct LoginRequest<'a> {
+    #[serde(flatten)]
+    payload: &'a AwsAuthIamPayload,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'a str>,
+}
+
+/// The `auth` block of a successful Vault login
+///
+/// `Debug` output redacts the client token.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct Auth {
+    /// The Vault token issued by the login
+    pub client_token: String,
+    /// Accessor of the token, which can be used to look it up or revoke it without the token itself
+    #[serde(default)]
+    pub accessor: String,
+    /// All policies attached to the token, including identity policies
+    #[serde(default)]
+    pub policies: Vec<String>,
+    /// Policies attached to the token by the role it was issued for
+    #[serde(default)]
+    pub token_policies: Vec<String>,
+    /// Time to live of the token in seconds
+    #[serde(default)]
+    pub lease_duration: u64,
+    /// Whether the token can be renewed
+    #[serde(default)]
+    pub renewable: bool,
+    /// ID of the identity entity the token belongs to
+    #[serde(default)]
+    pub entity_id: String,
+    /// Metadata about the login, such as the `nonce` of an EC2 login
+    ///
+    /// `Debug` output only includes the keys.
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+impl Auth {
+    /// Time to live of the token
+    pub fn lease_duration(&self) -> Duration {
+        Duration::from_secs(self.lease_duration)
+    }
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Auth")
+            .field("client_token", &"<redacted>")
+            .field("accessor", &self.accessor)
+            .field("policies", &self.policies)
+            .field("token_policies", &self.token_policies)
+            .field("lease_duration", &self.lease_duration)
+            .field("renewable", &self.renewable)
+            .field("entity_id", &self.entity_id)
+            .field("metadata", &self.metadata.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for Auth {
+    fn zeroize(&mut self) {
+        self.client_token.zeroize();
+        self.accessor.zeroize();
+        for (_, mut value) in self.metadata.drain() {
+            value.zeroize();
+        }
+    }
+}
+
+/// A response wrapped in a single use token by Vault's
+/// [response wrapping](https://www.vaultproject.io/docs/concepts/response-wrapping)
+///
+/// `Debug` output redacts the wrapping token.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct WrapInfo {
+    /// Single use token to unwrap the response with
+    pub token: String,
+    /// Accessor of the wrapping token
+    #[serde(default)]
+    pub accessor: String,
+    /// Time to live of the wrapping token in seconds
+    #[serde(default)]
+    pub ttl: u64,
+    /// When the wrapping token was created, in RFC 3339 format
+    #[serde(default)]
+    pub creation_time: String,
+    /// API path that created the wrapped response, such as `auth/aws/login`
+    ///
+    /// Check this before unwrapping a token received from elsewhere to make sure it wraps the
+    /// expected kind of response.
+    #[serde(default)]
+    pub creation_path: String,
+    /// Accessor of the token inside the wrapped response, if it wraps a login
+    #[serde(default)]
+    pub wrapped_accessor: String,
+}
+
+impl WrapInfo {
+    /// Time to live of the wrapping token
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl)
+    }
+}
+
+impl fmt::Debug for WrapInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WrapInfo")
+            .field("token", &"<redacted>")
+            .field("accessor", &self.accessor)
+            .field("ttl", &self.ttl)
+            .field("creation_time", &self.creation_time)
+            .field("creation_path", &self.creation_path)
+            .field("wrapped_accessor", &self.wrapped_accessor)
+            .finish()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for WrapInfo {
+    fn zeroize(&mut self) {
+        self.token.zeroize();
+        self.accessor.zeroize();
+    }
+}
+
+#[derive(Deserialize)]
+struct WrappedResponse {
+    wrap_info: WrapInfo,
+}
+
+#[derive(Deserialize)]
+struct AuthResponse {
+    auth: Auth,
+}
+
+#[derive(Serialize)]
+struct Ec2LoginRequest<'a> {
+    #[serde(flatten)]
+    payload: &'a Ec2AuthPayload,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct RenewRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    increment: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+/// State of a Vault server as reported by `sys/health`
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone)]
+#[serde(default)]
+pub struct Health {
+    /// Whether the server has been initialized
+    pub initialized: bool,
+    /// Whether the server is sealed
+    pub sealed: bool,
+    /// Whether the server is a standby, which forwards requests to the active node
+    pub standby: bool,
+    /// Whether the server is a performance standby, which serves some requests itself
+    pub performance_standby: bool,
+    /// Disaster recovery replication mode, such as `disabled`, `primary` or `secondary`
+    pub replication_dr_mode: String,
+    /// Version of Vault the server runs
+    pub version: String,
+    /// Name of the cluster the server belongs to
+    pub cluster_name: String,
+}
+
+impl Client {
+    /// Create a client for the Vault server at `address`, such as `https://vault.example.com:8200`
+    pub fn new(address: &str) -> Result<Self, Error> {
+        let address = address.trim_end_matches('/');
+        let uri: Uri = address.parse().map_err(|e| {
+            Error::GenericError(format!("Invalid Vault address {}: {}", address, e))
+        })?;
+        if uri.scheme().is_none() || uri.host().is_none() {
+            return Err(Error::GenericError(format!(
+                "Vault address {} is not an absolute URL",
+                address
+            )));
+        }
+
+        Ok(Self {
+            address: address.to_string(),
+            http: hyper::Client::builder().build(TlsConfig::default().connector()?),
+            timeout: DEFAULT_TIMEOUT,
+            namespace: None,
+            retry: RetryPolicy::default(),
+            forward_to_active_node: false,
+            forward_inconsistent: false,
+        })
+    }
+
+    /// Ask performance standbys to forward all requests to the active node
+    ///
+    /// This sets `X-Vault-Forward: active-node` on every request.
+    pub fn with_forward_to_active_node(mut self, forward: bool) -> Self {
+        self.forward_to_active_node = forward;
+        self
+    }
+
+    /// Ask performance standbys that have not caught up with the active node to forward requests
+    /// to it instead of failing
+    ///
+    /// This sets `X-Vault-Inconsistent: forward-active-node` on every request.
+    pub fn with_forward_inconsistent(mut self, forward: bool) -> Self {
+        self.forward_inconsistent = forward;
+        self
+    }
+
+    /// Establish TLS connections according to `config`
+    ///
+    /// Use [`TlsConfig::from_vault_env`] to configure TLS like the Vault CLI does.
+    pub fn with_tls(mut self, config: &TlsConfig) -> Result<Self, Error> {
+        self.http = hyper::Client::builder().build(config.connector()?);
+        Ok(self)
+    }
+
+    /// Check that the server can serve logins to the auth method mounted at `mount`
+    ///
+    /// This is cheap compared to signing a payload and turns the states that would make a login
+    /// fail into specific errors: [`Error::VaultNotInitialized`], [`Error::VaultSealed`],
+    /// [`Error::VaultDrSecondary`] and [`Error::VaultMountNotFound`]. Standbys pass the check
+    /// because they forward logins to the active node.
+    pub async fn preflight(&self, mount: &MountPath) -> Result<Health, Error> {
+        info!("Checking the health of Vault at {}", self.address);
+        // Health is reported with a status code per state, but the body is the same for all
+        let uri = format!(
+            "{}/v1/sys/health?standbyok=true&perfstandbyok=true",
+            self.address
+        );
+        let response = self.send(Method::GET, &uri, &[], Bytes::new()).await?;
+        let health: Health =
+            serde_json::from_slice(&response.body).map_err(|_| vault_error(&response))?;
+        debug!("Vault health: {:#?}", health);
+
+        if !health.initialized {
+            return Err(Error::VaultNotInitialized);
+        }
+        if health.sealed {
+            return Err(Error::VaultSealed);
+        }
+        if health.replication_dr_mode == "secondary" {
+            return Err(Error::VaultDrSecondary);
+        }
+
+        // An empty login is rejected without side effects, but differently for unknown mounts
+        let uri = format!("{}/v1/auth/{}/login", self.address, mount);
+        let response = self
+            .send(Method::POST, &uri, &[], Bytes::from_static(b"{}"))
+            .await?;
+        if response.status == hyper::StatusCode::NOT_FOUND
+            || String::from_utf8_lossy(&response.body).contains("no handler for route")
+        {
+            return Err(Error::VaultMountNotFound {
+                mount: mount.to_string(),
+            });
+        }
+        if response.status.is_server_error() {
+            return Err(vault_error(&response));
+        }
+
+        Ok(health)
+    }
+
+    /// Retry failed requests according to `policy`
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Send all requests to the Vault Enterprise `namespace`, such as `team/prod`
+    ///
+    /// Mount paths are then relative to the namespace. Leading and trailing slashes are ignored.
+    pub fn with_namespace(mut self, namespace: &str) -> Self {
+        let namespace = namespace.trim_matches('/');
+        self.namespace = if namespace.is_empty() {
+            None
+        } else {
+            Some(namespace.to_string())
+        };
+        self
+    }
+
+    /// Address of the Vault server
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Vault Enterprise namespace requests are sent to, if any
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// Log in to the AWS auth method with `payload`
+    ///
+    /// See [Vault's API documentation](https://www.vaultproject.io/api/auth/aws#login).
+    pub async fn login(
+        &self,
+        options: &LoginOptions,
+        payload: &AwsAuthIamPayload,
+    ) -> Result<Auth, Error> {
+        info!(
+            "Logging in to Vault at {} using auth/{} with role {:?}",
+            self.address, options.mount, options.role
+        );
+        let path = format!("auth/{}/login", options.mount);
+        let request = LoginRequest {
+            payload,
+            role: options.role.as_deref(),
+        };
+
+        let response: AuthResponse = self.post(&path, &[], &request).await?;
+        Ok(response.auth)
+    }
+
+    /// Log in to the AWS auth method with `payload` and have Vault wrap the response
+    ///
+    /// The returned wrapping token is valid for `ttl` and can be handed to a workload, which
+    /// obtains the client token with [`Client::unwrap_auth`].
+    pub async fn login_wrapped(
+        &self,
+        options: &LoginOptions,
+        payload: &AwsAuthIamPayload,
+        ttl: Duration,
+    ) -> Result<WrapInfo, Error> {
+        info!(
+            "Logging in to Vault at {} using auth/{} with role {:?}, wrapped for {}s",
+            self.address,
+            options.mount,
+            options.role,
+            ttl.as_secs()
+        );
+        let path = format!("auth/{}/login", options.mount);
+        let request = LoginRequest {
+            payload,
+            role: options.role.as_deref(),
+        };
+        let ttl = format!("{}s", ttl.as_secs());
+
+        let response: WrappedResponse = self
+            .post(&path, &[(WRAP_TTL_HEADER, &ttl)], &request)
+            .await?;
+        Ok(response.wrap_info)
+    }
+
+    /// Unwrap a login response wrapped in `wrapping_token`
+    ///
+    /// The wrapping token can only be used once.
+    pub async fn unwrap_auth(&self, wrapping_token: &str) -> Result<Auth, Error> {
+        info!("Unwrapping Vault login response at {}", self.address);
+        let response: AuthResponse = self
+            .post(
+                "sys/wrapping/unwrap",
+                &[(TOKEN_HEADER, wrapping_token)],
+                &serde_json::Map::new(),
+            )
+            .await?;
+        Ok(response.auth)
+    }
+
+    /// Log in to the AWS auth method with the EC2 auth `payload` of an instance
+    ///
+    /// The role must be configured for the `ec2` auth type. When no nonce was set on `payload`,
+    /// the nonce Vault generates is returned in the `metadata` of the response and must be
+    /// provided on subsequent logins.
+    pub async fn login_ec2(
+        &self,
+        options: &LoginOptions,
+        payload: &Ec2AuthPayload,
+    ) -> Result<Auth, Error> {
+        info!(
+            "Logging in to Vault at {} using auth/{} with EC2 role {:?}",
+            self.address, options.mount, options.role
+        );
+        let path = format!("auth/{}/login", options.mount);
+        let request = Ec2LoginRequest {
+            payload,
+            role: options.role.as_deref(),
+        };
+
+        let response: AuthResponse = self.post(&path, &[], &request).await?;
+        Ok(response.auth)
+    }
+
+    /// Renew `token`, optionally asking for a new time to live of `increment`
+    ///
+    /// Vault may grant a shorter time to live than requested, for example when the token is
+    /// close to its maximum time to live. See
+    /// [Vault's API documentation](https://www.vaultproject.io/api/auth/token#renew-a-token-self).
+    pub async fn renew_self(
+        &self,
+        token: &str,
+        increment: Option<Duration>,
+    ) -> Result<Auth, Error> {
+        info!("Renewing Vault token at {}", self.address);
+        let request = RenewRequest {
+            increment: increment.map(|increment| format!("{}s", increment.as_secs())),
+        };
+
+        let response: AuthResponse = self
+            .post("auth/token/renew-self", &[(TOKEN_HEADER, token)], &request)
+            .await?;
+        Ok(response.auth)
+    }
+
+    /// Keep the token of `auth` renewed in a background task
+    ///
+    /// The token is renewed whenever `fraction` of its time to live has elapsed, so a `fraction`
+    /// of `0.5` renews a token with an hour to live after thirty minutes. `fraction` is clamped
+    /// between `0.1` and `1.0`. The task stops once the token can no longer be renewed or has
+    /// expired, which the returned [`Renewer`] reports. This must be called from within a Tokio
+    /// runtime.
+    pub fn keep_renewed(&self, auth: Auth, fraction: f64) -> Renewer {
+        let fraction = if fraction.is_nan() {
+            1.0
+        } else {
+            fraction.clamp(0.1, 1.0)
+        };
+        let (sender, receiver) = watch::channel(auth.clone());
+        let client = self.clone();
+
+        let handle = tokio::spawn(async move {
+            let mut auth = auth;
+            loop {
+                if !auth.renewable {
+                    return Error::VaultTokenNotRenewable;
+                }
+                if auth.lease_duration == 0 {
+                    return Error::VaultTokenExpired;
+                }
+
+                tokio::time::sleep(auth.lease_duration().mul_f64(fraction)).await;
+                auth = match client.renew_self(&auth.client_token, None).await {
+                    Ok(renewed) => renewed,
+                    Err(e) => return e,
+                };
+                debug!("Renewed Vault token for {}s", auth.lease_duration);
+                if sender.send(auth.clone()).is_err() {
+                    return Error::GenericError("Token renewal was abandoned".to_string());
+                }
+            }
+        });
+
+        Renewer {
+            handle: Some(handle),
+            receiver,
+        }
+    }
+
+    async fn post<B, T>(&self, path: &str, headers: &[(&str, &str)], body: &B) -> Result<T, Error>
+    where
+        B: Serialize,
+        T: for<'de> Deserialize<'de>,
+    {
+        let body = Bytes::from(serde_json::to_vec(body)?);
+        self.request(Method::POST, path, headers, body).await
+    }
+
+    pub(crate) async fn get<T>(&self, path: &str, headers: &[(&str, &str)]) -> Result<T, Error>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.request(Method::GET, path, headers, Bytes::new()).await
+    }
+
+    /// Send a request to the API at `path`, retrying according to the retry policy
+    async fn request<T>(
+        &self,
+        method: Method,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Bytes,
+    ) -> Result<T, Error>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let uri = format!("{}/v1/{}", self.address, path);
+
+        let mut attempt = 1;
+        loop {
+            match self
+                .request_once(method.clone(), &uri, headers, body.clone())
+                .await
+            {
+                Err(e) if attempt < self.retry.max_attempts && self.retry.should_retry(&e) => {
+                    let backoff = self.retry.backoff(attempt);
+                    let delay = rand::thread_rng().gen_range(Duration::default()..=backoff);
+                    warn!(
+                        "Attempt {} of {} {} failed, retrying in {:?}: {}",
+                        attempt, method, uri, delay, e
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn request_once<T>(
+        &self,
+        method: Method,
+        uri: &str,
+        headers: &[(&str, &str)],
+        body: Bytes,
+    ) -> Result<T, Error>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let response = self.send(method, uri, headers, body).await?;
+        if !response.status.is_success() {
+            return Err(vault_error(&response));
+        }
+
+        Ok(serde_json::from_slice(&response.body)?)
+    }
+
+    /// Send a request, following redirects from standbys to the active node
+    async fn send(
+        &self,
+        method: Method,
+        uri: &str,
+        headers: &[(&str, &str)],
+        body: Bytes,
+    ) -> Result<http::Response, Error> {
+        let mut uri = uri.to_string();
+        for _ in 0..=MAX_REDIRECTS {
+            debug!("{} {}", method, uri);
+            let mut request = Request::builder()
+                .method(method.clone())
+                .uri(&uri)
+                .header(CONTENT_TYPE, "application/json");
+            if let Some(namespace) = &self.namespace {
+                request = request.header(NAMESPACE_HEADER, namespace);
+            }
+            if self.forward_to_active_node {
+                request = request.header(FORWARD_HEADER, "active-node");
+            }
+            if self.forward_inconsistent {
+                request = request.header(INCONSISTENT_HEADER, "forward-active-node");
+            }
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            let request = request.body(Body::from(body.clone()))?;
+            let response = http::send(&self.http, request, self.timeout).await?;
+
+            match response.status {
+                StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => {
+                    uri = redirect_target(&uri, &response)?;
+                    info!("Vault redirected the request to {}", uri);
+                }
+                _ => return Ok(response),
+            }
+        }
+
+        Err(Error::GenericError(format!(
+            "Vault redirected the request more than {} times",
+            MAX_REDIRECTS
+        )))
+    }
+}
+
+/// Resolves the `Location` of a redirect `response` to a request for `uri`
+fn redirect_target(uri: &str, response: &http::Response) -> Result<String, Error> {
+    let location = response
+        .headers
+        .get(LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .ok_or_else(|| Error::GenericError("Vault redirect has no valid Location".to_string()))?;
+    if uri.starts_with("https://") && location.starts_with("http://") {
+        return Err(Error::GenericError(format!(
+            "Refusing to follow Vault redirect from HTTPS to {}",
+            location
+        )));
+    }
+    if location.starts_with('/') {
+        let uri: Uri = uri
+            .parse()
+            .map_err(|e| Error::GenericError(format!("{}", e)))?;
+        let origin = format!(
+            "{}://{}",
+            uri.scheme_str().unwrap_or("https"),
+            uri.authority().map(|a| a.as_str()).unwrap_or_default()
+        );
+        Ok(format!("{}{}", origin, location))
+    } else {
+        Ok(location.to_string())
+    }
+}
+
+/// Turns an unsuccessful response into an error with the errors reported by Vault
+fn vault_error(response: &http::Response) -> Error {
+    let errors = serde_json::from_slice::<ErrorResponse>(&response.body)
+        .map(|response| response.errors)
+        .unwrap_or_else(|_| vec![String::from_utf8_lossy(&response.body).into_owned()]);
+    Error::VaultError {
+        status: response.status.as_u16(),
+        errors,
+    }
+}
+
+/// Handle to a background task renewing a Vault token, created by [`Client::keep_renewed`]
+///
+/// Dropping the handle stops the renewal.
+#[derive(Debug)]
+pub struct Renewer {
+    handle: Option<JoinHandle<Error>>,
+    receiver: watch::Receiver<Auth>,
+}
+
+impl Renewer {
+    /// The most recent `auth` for the token
+    pub fn current(&self) -> Auth {
+        self.receiver.borrow().clone()
+    }
+
+    /// Wait for the token to be renewed again
+    ///
+    /// Returns the reason renewal stopped once the token can no longer be renewed, for example
+    /// [`Error::VaultTokenNotRenewable`] or [`Error::VaultTokenExpired`].
+    pub async fn renewed(&mut self) -> Result<Auth, Error> {
+        if self.receiver.changed().await.is_ok() {
+            return Ok(self.current());
+        }
+
+        match self.handle.take() {
+            Some(handle) => Err(handle.await.unwrap_or_else(|e| {
+                Error::GenericError(format!("Token renewal task failed: {}", e))
+            })),
+            None => Err(Error::GenericError("Token renewal has stopped".to_string())),
+        }
+    }
+}
+
+impl Drop for Renewer {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.handle {
+            handle.abort();
+        }
+    }
+}
+
+/// Renew `token` with the Vault server at `addr`
+///
+/// This is a shorthand for [`Client::renew_self`] that keeps the current time to live.
+pub async fn renew_self(addr: &str, token: &str) -> Result<Auth, Error> {
+    Client::new(addr)?.renew_self(token, None).await
+}
+
+/// Check that the Vault server at `addr` can serve logins to the auth method mounted at `mount`
+///
+/// This is a shorthand for [`Client::preflight`].
+pub async fn preflight(addr: &str, mount: &MountPath) -> Result<Health, Error> {
+    Client::new(addr)?.preflight(mount).await
+}
+
+/// Log in to the Vault server at `addr` using the AWS auth method
+///
+/// This is a shorthand for [`Client::login`].
+pub async fn login(
+    addr: &str,
+    options: &LoginOptions,
+    payload: &AwsAuthIamPayload,
+) -> Result<Auth, Error> {
+    Client::new(addr)?.login(options, payload).await
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use hyper::StatusCode;
+    use serde_json::json;
+
+    use crate::http::tests::Received;
+
+    /// Serve `handler`, which responds with JSON, on a random local port
+    pub(crate) fn serve<F>(handler: F) -> String
+    where
+        F: Fn(Received) -> (StatusCode, serde_json::Value) + Send + Sync + 'static,
+    {
+        crate::http::tests::serve(move |request| {
+            let (status, body) = handler(request);
+            (status, body.to_string())
+        })
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn login_returns_auth() -> Result<(), crate::Error> {
+        let address = serve(|request| {
+            assert_eq!(request.method, Method::POST);
+            assert_eq!(request.path, "/v1/auth/aws/login");
+            assert_eq!(request.headers[CONTENT_TYPE], "application/json");
+            assert!(!request.headers.contains_key(NAMESPACE_HEADER));
+            assert_eq!(request.json()["role"], "my-role");
+            assert_eq!(request.json()["iam_http_request_method"], "POST");
+            (
+                StatusCode::OK,
+                json!({
+                    "request_id": "8f6d4c0c-6a45-8f1d-9b68-ff1d7d0a1b2c",
+                    "auth": {
+                        "client_token": "s.token",
+                        "accessor": "accessor",
+                        "policies": ["default", "dev"],
+                        "token_policies": ["default", "dev"],
+                        "metadata": { "role_id": "my-role" },
+                        "lease_duration": 3600,
+                        "renewable": true,
+                        "entity_id": "entity"
+                    }
+                }),
+            )
+        });
+        let payload = crate::client::tests::post_aws_iam_payload(None, HashMap::new()).await?;
+
+        let auth = login(&address, &LoginOptions::for_role("my-role"), &payload).await?;
+        assert_eq!(auth.client_token, "s.token");
+        assert_eq!(auth.accessor, "accessor");
+        assert_eq!(auth.policies, vec!["default", "dev"]);
+        assert_eq!(auth.lease_duration(), Duration::from_secs(3600));
+        assert!(auth.renewable);
+        assert_eq!(auth.entity_id, "entity");
+        assert!(!format!("{:?}", auth).contains("s.token"));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn login_uses_custom_mount_without_role() -> Result<(), crate::Error> {
+        let address = serve(|request| {
+            assert_eq!(request.path, "/v1/auth/aws-prod/login");
+            assert!(request.json().get("role").is_none());
+            (
+                StatusCode::OK,
+                json!({ "auth": { "client_token": "s.token" } }),
+            )
+        });
+        let payload = crate::client::tests::post_aws_iam_payload(None, HashMap::new()).await?;
+        let options = LoginOptions::new("/aws-prod/".parse()?, None);
+
+        assert_eq!(
+            login(&address, &options, &payload).await?.client_token,
+            "s.token"
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn requests_are_retried_during_leader_elections() -> Result<(), crate::Error> {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&attempts);
+        let address = serve(move |request| match request.path.as_str() {
+            "/v1/auth/aws/login" if counter.fetch_add(1, Ordering::SeqCst) == 0 => (
+                StatusCode::PRECONDITION_FAILED,
+                json!({ "errors": ["required index state not present"] }),
+            ),
+            "/v1/auth/aws/login" => (
+                StatusCode::OK,
+                json!({ "auth": { "client_token": "s.token" } }),
+            ),
+            _ => (
+                StatusCode::FORBIDDEN,
+                json!({ "errors": ["permission denied"] }),
+            ),
+        });
+        let payload = crate::client::tests::post_aws_iam_payload(None, HashMap::new()).await?;
+        let client = Client::new(&address)?.with_retry_policy(RetryPolicy {
+            initial_backoff: Duration::from_millis(10),
+            ..Default::default()
+        });
+
+        let auth = client
+            .login(&LoginOptions::for_role("my-role"), &payload)
+            .await?;
+        assert_eq!(auth.client_token, "s.token");
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+        match client.renew_self("s.token", None).await {
+            Err(Error::VaultError { status: 403, .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn preflight_reports_unusable_servers() -> Result<(), crate::Error> {
+        let health = |sealed: bool| {
+            json!({
+                "initialized": true,
+                "sealed": sealed,
+                "standby": true,
+                "replication_dr_mode": "disabled",
+                "version": "1.9.0"
+            })
+        };
+        let address = serve(move |request| match request.path.as_str() {
+            "/v1/sys/health" => (StatusCode::OK, health(false)),
+            "/v1/auth/aws/login" => (
+                StatusCode::BAD_REQUEST,
+                json!({ "errors": ["missing iam_request_url"] }),
+            ),
+            path => (
+                StatusCode::NOT_FOUND,
+                json!({ "errors": [format!("no handler for route '{}'", path)] }),
+            ),
+        });
+
+        let status = preflight(&address, &MountPath::default()).await?;
+        assert!(status.standby);
+        assert_eq!(status.version, "1.9.0");
+        match preflight(&address, &"aws-missing".parse()?).await {
+            Err(Error::VaultMountNotFound { mount }) => assert_eq!(mount, "aws-missing"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let sealed = serve(move |_| (StatusCode::SERVICE_UNAVAILABLE, health(true)));
+        match preflight(&sealed, &MountPath::default()).await {
+            Err(Error::VaultSealed) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn standby_redirects_are_followed() -> Result<(), crate::Error> {
+        let active = serve(|request| {
+            assert_eq!(request.path, "/v1/auth/aws/login");
+            assert_eq!(request.json()["role"], "my-role");
+            assert_eq!(request.headers[FORWARD_HEADER], "active-node");
+            assert_eq!(request.headers[INCONSISTENT_HEADER], "forward-active-node");
+            (
+                StatusCode::OK,
+                json!({ "auth": { "client_token": "s.token" } }),
+            )
+        });
+        let standby = crate::http::tests::serve_responses(move |request| {
+            hyper::Response::builder()
+                .status(StatusCode::TEMPORARY_REDIRECT)
+                .header(LOCATION, format!("{}{}", active, request.path))
+                .body(Body::empty())
+                .unwrap()
+        });
+        let payload = crate::client::tests::post_aws_iam_payload(None, HashMap::new()).await?;
+        let client = Client::new(&standby)?
+            .with_forward_to_active_node(true)
+            .with_forward_inconsistent(true);
+
+        let auth = client
+            .login(&LoginOptions::for_role("my-role"), &payload)
+            .await?;
+        assert_eq!(auth.client_token, "s.token");
+        Ok(())
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_the_maximum() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_millis(250));
+        assert_eq!(policy.backoff(3), Duration::from_secs(1));
+        assert_eq!(policy.backoff(40), Duration::from_secs(5));
+        assert!(!policy.should_retry(&Error::VaultError {
+            status: 400,
+            errors: vec![],
+        }));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn wrapped_logins_can_be_unwrapped() -> Result<(), crate::Error> {
+        let address = serve(|request| match request.path.as_str() {
+            "/v1/auth/aws/login" => {
+                assert_eq!(request.headers[WRAP_TTL_HEADER], "300s");
+                (
+                    StatusCode::OK,
+                    json!({
+                        "auth": null,
+                        "wrap_info": {
+                            "token": "s.wrapping",
+                            "accessor": "wrapping-accessor",
+                            "ttl": 300,
+                            "creation_time": "2021-06-01T00:00:00Z",
+                            "creation_path": "auth/aws/login",
+                            "wrapped_accessor": "accessor"
+                        }
+                    }),
+                )
+            }
+            "/v1/sys/wrapping/unwrap" => {
+                assert_eq!(request.headers[TOKEN_HEADER], "s.wrapping");
+                (
+                    StatusCode::OK,
+                    json!({ "auth": { "client_token": "s.token", "accessor": "accessor" } }),
+                )
+            }
+            path => panic!("Unexpected request to {}", path),
+        });
+        let payload = crate::client::tests::post_aws_iam_payload(None, HashMap::new()).await?;
+        let client = Client::new(&address)?;
+
+        let wrap_info = client
+            .login_wrapped(
+                &LoginOptions::for_role("my-role"),
+                &payload,
+                Duration::from_secs(300),
+            )
+            .await?;
+        assert_eq!(wrap_info.creation_path, "auth/aws/login");
+        assert_eq!(wrap_info.ttl(), Duration::from_secs(300));
+        assert!(!format!("{:?}", wrap_info).contains("s.wrapping"));
+
+        let auth = client.unwrap_auth(&wrap_info.token).await?;
+        assert_eq!(auth.client_token, "s.token");
+        assert_eq!(auth.accessor, wrap_info.wrapped_accessor);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn ec2_login_returns_nonce() -> Result<(), crate::Error> {
+        let address = serve(|request| {
+            assert_eq!(request.json()["pkcs7"], "MIAGCSqGSIb3DQEH");
+            assert_eq!(request.json()["role"], "my-ec2-role");
+            (
+                StatusCode::OK,
+                json!({
+                    "auth": {
+                        "client_token": "s.token",
+                        "metadata": { "instance_id": "i-0123", "nonce": "5defbfe3" }
+                    }
+                }),
+            )
+        });
+        let payload = Ec2AuthPayload::Pkcs7 {
+            pkcs7: "MIAGCSqGSIb3DQEH".to_string(),
+            nonce: None,
+        };
+
+        let auth = Client::new(&address)?
+            .login_ec2(&LoginOptions::for_role("my-ec2-role"), &payload)
+            .await?;
+        assert_eq!(auth.metadata["nonce"], "5defbfe3");
+        assert!(!format!("{:?}", auth).contains("5defbfe3"));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn login_sends_namespace_header() -> Result<(), crate::Error> {
+        let address = serve(|request| {
+            assert_eq!(request.headers[NAMESPACE_HEADER], "team/prod");
+            (
+                StatusCode::OK,
+                json!({ "auth": { "client_token": "s.token" } }),
+            )
+        });
+        let payload = crate::client::tests::post_aws_iam_payload(None, HashMap::new()).await?;
+        let client = Client::new(&address)?.with_namespace("/team/prod/");
+
+        assert_eq!(client.namespace(), Some("team/prod"));
+        let auth = client
+            .login(&LoginOptions::for_role("my-role"), &payload)
+            .await?;
+        assert_eq!(auth.client_token, "s.token");
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn renewer_renews_until_token_is_not_renewable() -> Result<(), crate::Error> {
+        let renewals = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&renewals);
+        let address = serve(move |request| {
+            assert_eq!(request.path, "/v1/auth/token/renew-self");
+            assert_eq!(request.headers[TOKEN_HEADER], "s.token");
+            let renewable = counter.fetch_add(1, Ordering::SeqCst) == 0;
+            (
+                StatusCode::OK,
+                json!({
+                    "auth": {
+                        "client_token": "s.token",
+                        "lease_duration": 1,
+                        "renewable": renewable
+                    }
+                }),
+            )
+        });
+        let client = Client::new(&address)?;
+        let auth = client
+            .renew_self("s.token", Some(Duration::from_secs(1)))
+            .await?;
+        assert!(auth.renewable);
+
+        let mut renewer = client.keep_renewed(auth, 0.1);
+        assert!(!renewer.renewed().await?.renewable);
+        match renewer.renewed().await {
+            Err(Error::VaultTokenNotRenewable) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert_eq!(renewals.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn mount_paths_are_validated() {
+        assert_eq!(MountPath::default().as_str(), "aws");
+        assert_eq!(
+            MountPath::new("team/aws-ec2/").unwrap().as_str(),
+            "team/aws-ec2"
+        );
+        assert!(MountPath::new("/").is_err());
+        assert!(MountPath::new("aws/../sys").is_err());
+        assert!(MountPath::new("aws?x=1").is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn login_reports_vault_errors() -> Result<(), crate::Error> {
+        let address = serve(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                json!({ "errors": ["entry for role my-role not found"] }),
+            )
+        });
+        let payload = crate::client::tests::post_aws_iam_payload(None, HashMap::new()).await?;
+
+        match login(&address, &LoginOptions::for_role("my-role"), &payload).await {
+            Err(Error::VaultError { status, errors }) => {
+                assert_eq!(status, 400);
+                assert_eq!(errors, vec!["entry for role my-role not found".to_string()]);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+//! Matching payloads to the client configuration of a Vault AWS auth method
+use std::collections::HashMap;
+
+use log::{debug, info};
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::Region;
+use serde::{Deserialize, Serialize};
+
+use super::{Client, MountPath, TOKEN_HEADER};
+use crate::client::{AwsAuthIamPayload, VAULT_SERVER_ID_HEADER};
+use crate::region::Partition;
+use crate::Error;
+
+/// Endpoint Vault forwards payloads to unless `sts_endpoint` is configured
+const DEFAULT_STS_ENDPOINT: &str = "https://sts.amazonaws.com";
+
+/// The parts of `auth/<mount>/config/client` that decide which payloads Vault accepts
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone)]
+#[serde(default)]
+pub struct AuthClientConfig {
+    /// STS endpoint Vault forwards payloads to. Empty for the global endpoint
+    pub sts_endpoint: String,
+    /// Region Vault signs its own STS requests for
+    pub sts_region: String,
+    /// Value of the [`VAULT_SERVER_ID_HEADER`] that payloads must be signed with, if any
+    pub iam_server_id_header_value: String,
+}
+
+#[derive(Deserialize)]
+struct ConfigResponse {
+    data: AuthClientConfig,
+}
+
+impl Client {
+    /// Read the client configuration of the AWS auth method mounted at `mount`
+    ///
+    /// `token` must be allowed to read `auth/<mount>/config/client`.
+    pub async fn auth_client_config(
+        &self,
+        token: &str,
+        mount: &MountPath,
+    ) -> Result<AuthClientConfig, Error> {
+        info!("Reading the client configuration of auth/{}", mount);
+        let path = format!("auth/{}/config/client", mount);
+        let response: ConfigResponse = self.get(&path, &[(TOKEN_HEADER, token)]).await?;
+        debug!("Vault AWS auth client configuration: {:#?}", response.data);
+        Ok(response.data)
+    }
+}
+
+impl AuthClientConfig {
+    /// The STS endpoint payloads must be signed for
+    pub fn endpoint(&self) -> &str {
+        if self.sts_endpoint.is_empty() {
+            DEFAULT_STS_ENDPOINT
+        } else {
+            self.sts_endpoint.trim_end_matches('/')
+        }
+    }
+
+    /// The region to sign payloads for so that they are sent to [`endpoint`](Self::endpoint)
+    ///
+    /// Returns `None` for the global endpoint. Without an `sts_region`, the region is read from
+    /// the host of the endpoint, such as `sts.eu-west-1.amazonaws.com`, and is `us-east-1` for
+    /// hosts that do not name one.
+    pub fn region(&self) -> Option<Region> {
+        if self.endpoint() == DEFAULT_STS_ENDPOINT {
+            return None;
+        }
+        let name = if self.sts_region.is_empty() {
+            endpoint_region(self.endpoint()).unwrap_or("us-east-1")
+        } else {
+            &self.sts_region
+        };
+        Some(Region::Custom {
+            name: name.to_string(),
+            endpoint: self.endpoint().to_string(),
+        })
+    }
+
+    /// Sign a payload that this configuration accepts
+    pub fn payload(&self, credentials: &AwsCredentials) -> AwsAuthIamPayload {
+        let mut headers = HashMap::new();
+        if !self.iam_server_id_header_value.is_empty() {
+            headers.insert(
+                VAULT_SERVER_ID_HEADER,
+                self.iam_server_id_header_value.as_str(),
+            );
+        }
+        AwsAuthIamPayload::new(credentials, self.region(), headers)
+    }
+
+    /// Check that Vault accepts the endpoint and server ID header `payload` was signed with
+    ///
+    /// Returns [`Error::VaultConfigMismatch`] describing the first mismatch found.
+    pub fn check(&self, payload: &AwsAuthIamPayload) -> Result<(), Error> {
+        let url = base64::decode(&payload.iam_request_url)
+            .ok()
+            .and_then(|url| String::from_utf8(url).ok())
+            .ok_or_else(|| {
+                Error::VaultConfigMismatch("iam_request_url is not valid base64".to_string())
+            })?;
+        if url.trim_end_matches('/') != self.endpoint() {
+            return Err(Error::VaultConfigMismatch(format!(
+                "payload was signed for {} but Vault uses {}",
+                url,
+                self.endpoint()
+            )));
+        }
+
+        let server_id = payload
+            .iam_request_headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(VAULT_SERVER_ID_HEADER))
+            .and_then(|(_, values)| values.first());
+        match (self.iam_server_id_header_value.as_str(), server_id) {
+            ("", _) => Ok(()),
+            (expected, Some(actual)) if expected == actual => Ok(()),
+            (expected, actual) => Err(Error::VaultConfigMismatch(format!(
+                "Vault requires the server ID {} but the payload has {:?}",
+                expected, actual
+            ))),
+        }
+    }
+}
+
+/// The first label of the host of `endpoint` that is a known region, which is where regional
+/// and VPC endpoints like `vpce-0123.sts.eu-west-1.vpce.amazonaws.com` name it
+fn endpoint_region(endpoint: &str) -> Option<&str> {
+    let host = endpoint
+        .split_once("://")
+        .map_or(endpoint, |(_, rest)| rest)
+        .split(['/', ':'])
+        .next()?;
+    host.split('.')
+        .find(|label| Partition::of_region(label).regions().contains(label))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::StatusCode;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn payloads_are_checked_against_the_client_config() -> Result<(), crate::Error> {
+        let address = crate::vault::tests::serve(|request| {
+            assert_eq!(request.path, "/v1/auth/aws/config/client");
+            assert_eq!(request.headers[TOKEN_HEADER], "s.admin");
+            (
+                StatusCode::OK,
+                json!({
+                    "data": {
+                        "sts_endpoint": "https://sts.eu-west-1.amazonaws.com",
+                        "sts_region": "eu-west-1",
+                        "iam_server_id_header_value": "vault.example.com",
+                        "max_retries": -1
+                    }
+                }),
+            )
+        });
+        let config = Client::new(&address)?
+            .auth_client_config("s.admin", &MountPath::default())
+            .await?;
+        let credentials = crate::client::tests::credentials().await?;
+
+        let global = AwsAuthIamPayload::new(&credentials, None::<Region>, HashMap::new());
+        assert!(matches!(
+            config.check(&global),
+            Err(Error::VaultConfigMismatch(_))
+        ));
+
+        let payload = config.payload(&credentials);
+        config.check(&payload)?;
+        assert_eq!(
+            base64::decode(&payload.iam_request_url).unwrap(),
+            b"https://sts.eu-west-1.amazonaws.com/"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn regions_are_read_from_regional_endpoints() {
+        let region = |sts_endpoint: &str| {
+            AuthClientConfig {
+                sts_endpoint: sts_endpoint.to_string(),
+                ..Default::default()
+            }
+            .region()
+            .map(|region| region.name().to_string())
+        };
+        assert_eq!(region(""), None);
+        assert_eq!(
+            region("https://sts.eu-west-1.amazonaws.com"),
+            Some("eu-west-1".to_string())
+        );
+        assert_eq!(
+            region("https://vpce-0123.sts.ap-southeast-2.vpce.amazonaws.com/"),
+            Some("ap-southeast-2".to_string())
+        );
+        assert_eq!(
+            region("https://sts.cn-north-1.amazonaws.com.cn:443"),
+            Some("cn-north-1".to_string())
+        );
+        assert_eq!(
+            region("https://sts.example.com"),
+            Some("us-east-1".to_string())
+        );
+    }
+}
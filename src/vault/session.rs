@@ -0,0 +1,320 @@
+//! Keeping a Vault token valid for the lifetime of a process
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use rusoto_core::credential::ProvideAwsCredentials;
+use rusoto_core::Region;
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinHandle;
+
+use super::{Auth, Client, LoginOptions};
+use crate::client::{AwsAuthIamPayload, VAULT_SERVER_ID_HEADER};
+use crate::Error;
+
+/// How a [`VaultSession`] logs in and keeps its token valid
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionConfig {
+    /// Where and as which role to log in
+    pub login: LoginOptions,
+    /// Region of the STS endpoint to sign payloads for. The global endpoint is used if not set
+    pub region: Option<Region>,
+    /// Value of the [`VAULT_SERVER_ID_HEADER`] to sign into payloads, if any
+    pub server_id: Option<String>,
+    /// Fraction of the time to live of the token after which it is renewed or replaced
+    pub renew_fraction: f64,
+    /// Delay before retrying a failed renewal or login
+    pub retry_delay: Duration,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            login: LoginOptions::default(),
+            region: None,
+            server_id: None,
+            renew_fraction: 0.5,
+            retry_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+/// A Vault token obtained with the AWS auth method, kept valid in a background task
+///
+/// The token is renewed whenever `renew_fraction` of its time to live has elapsed. Once it can
+/// no longer be renewed, because it is not renewable, has reached its maximum time to live, or
+/// Vault rejects the renewal with `403 Forbidden`, a new payload is signed with fresh credentials
+/// and the session logs in again. Failures are retried after `retry_delay` for as long as the
+/// session lives, or sooner while the current token is about to expire.
+///
+/// Dropping the session stops the background task.
+#[derive(Debug)]
+pub struct VaultSession {
+    receiver: watch::Receiver<Auth>,
+    revoked: Arc<Notify>,
+    handle: JoinHandle<()>,
+}
+
+impl VaultSession {
+    /// Log in with credentials from `credentials` and keep the token valid
+    ///
+    /// Fails if the first login fails. This must be called from within a Tokio runtime.
+    pub async fn start<P>(
+        client: Client,
+        credentials: P,
+        config: SessionConfig,
+    ) -> Result<Self, Error>
+    where
+        P: ProvideAwsCredentials + Send + Sync + 'static,
+    {
+        let fraction = if config.renew_fraction.is_nan() {
+            1.0
+        } else {
+            config.renew_fraction.clamp(0.1, 1.0)
+        };
+        let lifecycle = Lifecycle {
+            client,
+            credentials,
+            config: SessionConfig {
+                renew_fraction: fraction,
+                ..config
+            },
+        };
+
+        let auth = lifecycle.login().await?;
+        let (sender, receiver) = watch::channel(auth);
+        let revoked = Arc::new(Notify::new());
+        let handle = tokio::spawn(lifecycle.run(sender, Arc::clone(&revoked)));
+
+        Ok(Self {
+            receiver,
+            revoked,
+            handle,
+        })
+    }
+
+    /// The current token
+    pub fn token(&self) -> String {
+        self.receiver.borrow().client_token.clone()
+    }
+
+    /// The `auth` of the current token
+    pub fn auth(&self) -> Auth {
+        self.receiver.borrow().clone()
+    }
+
+    /// Receive the `auth` of every new or renewed token
+    pub fn subscribe(&self) -> watch::Receiver<Auth> {
+        self.receiver.clone()
+    }
+
+    /// Log in again right away, for example after Vault rejected the token with `403 Forbidden`
+    pub fn revoked(&self) {
+        self.revoked.notify_one();
+    }
+}
+
+impl Drop for VaultSession {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+struct Lifecycle<P> {
+    client: Client,
+    credentials: P,
+    config: SessionConfig,
+}
+
+impl<P> Lifecycle<P>
+where
+    P: ProvideAwsCredentials + Send + Sync + 'static,
+{
+    async fn login(&self) -> Result<Auth, Error> {
+        let credentials = self.credentials.credentials().await?;
+        let headers: HashMap<&str, &str> = self
+            .config
+            .server_id
+            .iter()
+            .map(|server_id| (VAULT_SERVER_ID_HEADER, server_id.as_str()))
+            .collect();
+        let payload = AwsAuthIamPayload::new(&credentials, self.config.region.as_ref(), headers);
+        self.client.login(&self.config.login, &payload).await
+    }
+
+    /// Renew the token of `auth`, returning `None` if a new login is needed instead
+    async fn renew(&self, auth: &Auth) -> Result<Option<Auth>, Error> {
+        if !auth.renewable {
+            return Ok(None);
+        }
+        match self.client.renew_self(&auth.client_token, None).await {
+            // A shrinking lease means the token is approaching its maximum time to live
+            Ok(renewed) if renewed.lease_duration < auth.lease_duration => Ok(None),
+            Ok(renewed) => Ok(Some(renewed)),
+            Err(Error::VaultError { status: 403, .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// How long to wait before retrying a failed renewal or login, so that retries still land
+    /// within the lease of the current token expiring at `expires_at`
+    fn retry_delay(&self, expires_at: Instant) -> Duration {
+        match expires_at.checked_duration_since(Instant::now()) {
+            Some(remaining) if !remaining.is_zero() => self.config.retry_delay.min(remaining / 2),
+            _ => self.config.retry_delay,
+        }
+    }
+
+    async fn run(self, sender: watch::Sender<Auth>, revoked: Arc<Notify>) {
+        let mut auth = sender.borrow().clone();
+        let mut expires_at = Instant::now() + auth.lease_duration();
+        let mut delay = auth.lease_duration().mul_f64(self.config.renew_fraction);
+        loop {
+            let mut relogin = false;
+            if auth.lease_duration == 0 {
+                // Tokens without a time to live only need replacing once they are revoked
+                revoked.notified().await;
+                relogin = true;
+            } else {
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = revoked.notified() => relogin = true,
+                }
+            }
+
+            if !relogin {
+                match self.renew(&auth).await {
+                    Ok(Some(renewed)) => {
+                        auth = renewed;
+                        expires_at = Instant::now() + auth.lease_duration();
+                        delay = auth.lease_duration().mul_f64(self.config.renew_fraction);
+                        let _ = sender.send(auth.clone());
+                        continue;
+                    }
+                    Ok(None) => {}
+                    Err(e) if expires_at > Instant::now() => {
+                        delay = self.retry_delay(expires_at);
+                        warn!(
+                            "Renewing the Vault token failed, retrying in {:?}: {}",
+                            delay, e
+                        );
+                        continue;
+                    }
+                    Err(e) => warn!("Renewing the Vault token failed before it expired: {}", e),
+                }
+            }
+
+            info!("Logging in to Vault again");
+            auth = loop {
+                match self.login().await {
+                    Ok(auth) => break auth,
+                    Err(e) => {
+                        let delay = self.retry_delay(expires_at);
+                        warn!("Logging in to Vault failed, retrying in {:?}: {}", delay, e);
+                        tokio::time::sleep(delay).await;
+                    }
+                }
+            };
+            expires_at = Instant::now() + auth.lease_duration();
+            delay = auth.lease_duration().mul_f64(self.config.renew_fraction);
+            let _ = sender.send(auth.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use hyper::StatusCode;
+    use rusoto_core::credential::StaticProvider;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn sessions_log_in_again_when_tokens_are_revoked() -> Result<(), crate::Error> {
+        let logins = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&logins);
+        let address = crate::vault::tests::serve(move |request| {
+            assert_eq!(request.path, "/v1/auth/aws/login");
+            assert!(request.json()["iam_request_headers"]
+                .get("x-vault-aws-iam-server-id")
+                .is_some());
+            let login = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            (
+                StatusCode::OK,
+                json!({
+                    "auth": {
+                        "client_token": format!("s.{}", login),
+                        "lease_duration": 3600,
+                        "renewable": true
+                    }
+                }),
+            )
+        });
+        let credentials =
+            StaticProvider::new_minimal("AKIDEXAMPLE".to_string(), "secret".to_string());
+        let config = SessionConfig {
+            server_id: Some("vault.example.com".to_string()),
+            ..Default::default()
+        };
+
+        let session = VaultSession::start(Client::new(&address)?, credentials, config).await?;
+        assert_eq!(session.token(), "s.1");
+
+        let mut updates = session.subscribe();
+        session.revoked();
+        updates
+            .changed()
+            .await
+            .map_err(|e| Error::GenericError(e.to_string()))?;
+        assert_eq!(updates.borrow().client_token, "s.2");
+        assert_eq!(session.token(), "s.2");
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn failed_renewals_are_retried_before_tokens_expire() -> Result<(), crate::Error> {
+        let renewals = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&renewals);
+        let address = crate::vault::tests::serve(move |request| {
+            if request.path == "/v1/auth/aws/login" {
+                return (
+                    StatusCode::OK,
+                    json!({
+                        "auth": { "client_token": "s.1", "lease_duration": 2, "renewable": true }
+                    }),
+                );
+            }
+            assert_eq!(request.path, "/v1/auth/token/renew-self");
+            if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                return (StatusCode::INTERNAL_SERVER_ERROR, json!({ "errors": [] }));
+            }
+            (
+                StatusCode::OK,
+                json!({
+                    "auth": { "client_token": "s.1", "lease_duration": 2, "renewable": true }
+                }),
+            )
+        });
+        let client = Client::new(&address)?.with_retry_policy(crate::vault::RetryPolicy::none());
+        let credentials =
+            StaticProvider::new_minimal("AKIDEXAMPLE".to_string(), "secret".to_string());
+        let config = SessionConfig {
+            retry_delay: Duration::from_secs(60),
+            ..Default::default()
+        };
+
+        let session = VaultSession::start(client, credentials, config).await?;
+        let mut updates = session.subscribe();
+        // The token expires after two seconds, long before the retry delay
+        tokio::time::timeout(Duration::from_secs(2), updates.changed())
+            .await
+            .map_err(|e| Error::GenericError(e.to_string()))?
+            .map_err(|e| Error::GenericError(e.to_string()))?;
+        assert_eq!(renewals.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+//! The [token helper](https://www.vaultproject.io/docs/commands/token-helper) protocol of the
+//! Vault CLI
+//!
+//! The Vault CLI runs the program configured as `token_helper` in `~/.vault` with one of the
+//! arguments `get`, `store` or `erase`, and the address of the server in `VAULT_ADDR`. `get`
+//! prints the token to standard output, `store` reads it from standard input and `erase` deletes
+//! it. A binary can implement the protocol with:
+//!
+//! ```rust,no_run
+//! use aws_auth_payload::vault::token_helper::{self, TokenStore};
+//!
+//! fn main() -> Result<(), aws_auth_payload::Error> {
+//!     let store = TokenStore::new("/var/lib/my-helper/tokens");
+//!     token_helper::run_from_env(&store)
+//! }
+//! ```
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::sink::FileSink;
+use crate::Error;
+
+/// Address the Vault CLI uses when `VAULT_ADDR` is not set
+const DEFAULT_ADDRESS: &str = "https://127.0.0.1:8200";
+
+/// An operation requested by the Vault CLI
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Command {
+    /// Print the stored token
+    Get,
+    /// Store the token read from standard input
+    Store,
+    /// Delete the stored token
+    Erase,
+}
+
+impl FromStr for Command {
+    type Err = Error;
+
+    fn from_str(command: &str) -> Result<Self, Self::Err> {
+        match command {
+            "get" => Ok(Command::Get),
+            "store" => Ok(Command::Store),
+            "erase" => Ok(Command::Erase),
+            _ => Err(Error::GenericError(format!(
+                "Unknown token helper command {}",
+                command
+            ))),
+        }
+    }
+}
+
+/// Tokens stored as files in a directory, one per Vault address
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TokenStore {
+    directory: PathBuf,
+}
+
+impl TokenStore {
+    /// Store tokens in `directory`, which is created when the first token is stored
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// Directory the tokens are stored in
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// The token stored for the Vault server at `address`, if any
+    pub fn get(&self, address: &str) -> Result<Option<String>, Error> {
+        match fs::read_to_string(self.path(address)) {
+            Ok(token) => Ok(Some(token)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Store `token` for the Vault server at `address`
+    pub fn store(&self, address: &str, token: &str) -> Result<(), Error> {
+        fs::create_dir_all(&self.directory)?;
+        FileSink::new(self.path(address)).write_token(token)
+    }
+
+    /// Delete the token stored for the Vault server at `address`, if any
+    pub fn erase(&self, address: &str) -> Result<(), Error> {
+        match fs::remove_file(self.path(address)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn path(&self, address: &str) -> PathBuf {
+        let address = address.trim_end_matches('/');
+        self.directory
+            .join(base64::encode_config(address, base64::URL_SAFE_NO_PAD))
+    }
+}
+
+/// Perform `command` for the Vault server at `address`
+///
+/// Tokens are read from `input` and written to `output`. Surrounding whitespace is removed from
+/// tokens that are stored, and nothing is written for `get` if no token is stored.
+pub fn run<R, W>(
+    store: &TokenStore,
+    command: Command,
+    address: &str,
+    mut input: R,
+    mut output: W,
+) -> Result<(), Error>
+where
+    R: Read,
+    W: Write,
+{
+    match command {
+        Command::Get => {
+            if let Some(token) = store.get(address)? {
+                output.write_all(token.as_bytes())?;
+                output.flush()?;
+            }
+            Ok(())
+        }
+        Command::Store => {
+            let mut token = String::new();
+            input.read_to_string(&mut token)?;
+            store.store(address, token.trim())
+        }
+        Command::Erase => store.erase(address),
+    }
+}
+
+/// Perform the command in the first argument of the process, like the Vault CLI expects
+///
+/// The address is read from `VAULT_ADDR`, and tokens from standard input and to standard output.
+pub fn run_from_env(store: &TokenStore) -> Result<(), Error> {
+    let command: Command = env::args()
+        .nth(1)
+        .ok_or_else(|| Error::GenericError("Missing token helper command".to_string()))?
+        .parse()?;
+    let address = env::var("VAULT_ADDR").unwrap_or_else(|_| DEFAULT_ADDRESS.to_string());
+
+    run(store, command, &address, io::stdin(), io::stdout())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_stored_per_address() -> Result<(), crate::Error> {
+        let directory = env::temp_dir().join(format!(
+            "aws-auth-payload-token-helper-{}",
+            std::process::id()
+        ));
+        let store = TokenStore::new(&directory);
+        let address = "https://vault.example.com:8200";
+
+        run(
+            &store,
+            "store".parse()?,
+            address,
+            &b"s.token\n"[..],
+            io::sink(),
+        )?;
+        let mut output = Vec::new();
+        run(&store, Command::Get, address, io::empty(), &mut output)?;
+        assert_eq!(output, b"s.token");
+        assert_eq!(store.get("https://other.example.com")?, None);
+
+        run(&store, Command::Erase, address, io::empty(), io::sink())?;
+        assert_eq!(store.get(address)?, None);
+        run(&store, Command::Erase, address, io::empty(), io::sink())?;
+
+        fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+//! Logging in to Vault with [`vaultrs`](https://docs.rs/vaultrs)
+//!
+//! This module requires the `vaultrs` feature.
+use ::vaultrs::api::auth::aws::requests::IamLoginRequest;
+use ::vaultrs::api::AuthInfo;
+use ::vaultrs::client::Client;
+use log::info;
+
+use crate::client::AwsAuthIamPayload;
+use crate::Error;
+
+impl From<&AwsAuthIamPayload> for IamLoginRequest {
+    /// Convert `payload` into a login request for the auth method mounted at `aws` with the
+    /// default role
+    ///
+    /// Set `mount` and `role` on the request to log in elsewhere.
+    fn from(payload: &AwsAuthIamPayload) -> Self {
+        IamLoginRequest {
+            mount: "aws".to_string(),
+            role: None,
+            iam_http_request_method: payload.iam_http_request_method.clone(),
+            iam_request_url: payload.iam_request_url.clone(),
+            iam_request_body: payload.iam_request_body.clone(),
+            iam_request_headers: payload.encoded_headers(),
+        }
+    }
+}
+
+/// Log in to the AWS auth method mounted at `mount` as `role` with a `vaultrs` client
+///
+/// If `role` is not set, Vault uses the role named after the IAM principal that signed the
+/// payload. The token is not set on `client`.
+pub async fn login_with_vaultrs<C: Client>(
+    client: &C,
+    mount: &str,
+    role: Option<&str>,
+    payload: &AwsAuthIamPayload,
+) -> Result<AuthInfo, Error> {
+    info!(
+        "Logging in to Vault using auth/{} with role {:?}",
+        mount, role
+    );
+    let headers = payload.encoded_headers();
+    Ok(::vaultrs::auth::aws::iam_login(
+        client,
+        mount.trim_matches('/'),
+        &payload.iam_http_request_method,
+        &payload.iam_request_url,
+        &headers,
+        &payload.iam_request_body,
+        role,
+    )
+    .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn payloads_convert_to_iam_login_requests() -> Result<(), crate::Error> {
+        let payload = crate::client::tests::post_aws_iam_payload(None, HashMap::new()).await?;
+
+        let request = IamLoginRequest::from(&payload);
+        assert_eq!(request.mount, "aws");
+        assert_eq!(request.iam_request_url, payload.iam_request_url);
+        let headers: HashMap<String, Vec<String>> = serde_json::from_slice(
+            &base64::decode(&request.iam_request_headers)
+                .map_err(|e| Error::GenericError(e.to_string()))?,
+        )?;
+        assert_eq!(headers, payload.iam_request_headers);
+        Ok(())
+    }
+}
@@ -0,0 +1,1235 @@
+//! Verifying signed `GetCallerIdentity` requests like the server side of Vault's AWS auth method
+//! and of Kubernetes AWS IAM Authenticator
+//!
+//! A client proves its identity with a `GetCallerIdentity` request it signed, either as an
+//! [`AwsAuthIamPayload`] holding a POST request, or as a pre-signed GET URL. The verifier
+//! checks that the request can only reach STS and can only call `GetCallerIdentity`, then sends
+//! it to STS as is to learn who signed it. Without these checks, a request could make the
+//! verifier send requests to arbitrary hosts, or perform other actions with the credentials of
+//! the signer.
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration as StdDuration, Instant};
+
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use hyper::body::Bytes;
+use hyper::Uri;
+use log::{debug, info, warn};
+use rand::Rng;
+use rusoto_core::request::{DispatchSignedRequest, HttpClient};
+use rusoto_core::Region;
+
+use self::audit::{FailureReason, RequestKind, RequestMetadata};
+use crate::client::{AwsAuthIamPayload, EKS_TOKEN_PREFIX, VAULT_SERVER_ID_HEADER};
+use crate::sigv4;
+use crate::sts::{self, CallerIdentity};
+use crate::Error;
+
+#[cfg(feature = "actix-web")]
+pub mod actix;
+pub mod audit;
+#[cfg(feature = "axum")]
+pub mod axum;
+pub mod bindings;
+mod rate_limit;
+mod replay;
+mod retry;
+#[cfg(feature = "tonic")]
+pub mod tonic;
+
+pub use self::audit::{VerificationEvent, VerificationMetrics, VerificationObserver};
+pub use self::bindings::{Binding, Bindings};
+pub use self::rate_limit::{RateLimiter, ShedPolicy};
+pub use self::replay::{MemoryReplayCache, ReplayCache};
+pub use self::retry::{CircuitBreaker, RetryPolicy};
+
+/// The only body a payload may have, once decoded and parsed
+const EXPECTED_BODY: [(&str, &str); 2] =
+    [("Action", "GetCallerIdentity"), ("Version", "2011-06-15")];
+
+/// Headers that change how STS handles a request, which must be signed if they are present.
+/// The host must always be signed
+const CRITICAL_HEADERS: [&str; 5] = [
+    "content-type",
+    "host",
+    "x-amz-content-sha256",
+    "x-amz-date",
+    "x-amz-security-token",
+];
+
+/// How long STS accepts requests signed with headers, which can also be signed up to this long in
+/// the future
+const SIGNATURE_VALIDITY_MINUTES: i64 = 15;
+
+/// Query parameters a pre-signed URL may contain. Any other parameter is rejected
+pub(crate) const ALLOWED_PARAMS: [&str; 9] = [
+    "Action",
+    "Version",
+    "X-Amz-Algorithm",
+    "X-Amz-Credential",
+    "X-Amz-Date",
+    "X-Amz-Expires",
+    "X-Amz-Security-Token",
+    "X-Amz-Signature",
+    "X-Amz-SignedHeaders",
+];
+
+/// Query parameters a pre-signed URL must contain
+pub(crate) const REQUIRED_PARAMS: [&str; 7] = [
+    "Action",
+    "X-Amz-Algorithm",
+    "X-Amz-Credential",
+    "X-Amz-Date",
+    "X-Amz-Expires",
+    "X-Amz-Signature",
+    "X-Amz-SignedHeaders",
+];
+
+/// Header carrying a payload as base64 encoded JSON, for the integrations with servers
+///
+/// Clients can send EKS tokens as bearer tokens in the `authorization` header instead.
+pub const PAYLOAD_HEADER: &str = "x-aws-auth-payload";
+
+/// A signed `GetCallerIdentity` request presented by a client
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SignedIdentityRequest {
+    /// A POST request, as sent to Vault
+    Payload(AwsAuthIamPayload),
+    /// A pre-signed GET URL, as in EKS tokens
+    PresignedUrl(String),
+}
+
+impl SignedIdentityRequest {
+    /// The pre-signed URL of an EKS token
+    pub fn from_eks_token(token: &str) -> Result<Self, Error> {
+        crate::kubernetes::decode_eks_token(token).map(SignedIdentityRequest::PresignedUrl)
+    }
+
+    /// Find the request in the headers of a request to a server, looked up by `header`
+    ///
+    /// The request is either an EKS token sent as bearer token in the `authorization` header,
+    /// or a payload in the [`PAYLOAD_HEADER`]. Returns `None` if there is neither.
+    pub fn from_headers<'a, F>(header: F) -> Result<Option<Self>, Error>
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        let token = header("authorization")
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+            .filter(|token| token.starts_with(EKS_TOKEN_PREFIX));
+        if let Some(token) = token {
+            return Self::from_eks_token(token).map(Some);
+        }
+        match header(PAYLOAD_HEADER) {
+            Some(payload) => {
+                let invalid =
+                    || Error::InvalidAuthPayload(format!("{} is malformed", PAYLOAD_HEADER));
+                let json = base64::decode(payload).map_err(|_| invalid())?;
+                let payload = serde_json::from_slice(&json).map_err(|_| invalid())?;
+                Ok(Some(SignedIdentityRequest::Payload(payload)))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl From<AwsAuthIamPayload> for SignedIdentityRequest {
+    fn from(payload: AwsAuthIamPayload) -> Self {
+        SignedIdentityRequest::Payload(payload)
+    }
+}
+
+/// The identity of the signer of a verified request, as provided to the handlers of servers
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VerifiedCaller(pub CallerIdentity);
+
+/// The HTTP status code to answer a request whose verification failed with `error`
+///
+/// Requests that are invalid or rejected by STS are unauthorized. Other errors, such as failures
+/// to reach STS, make the service unavailable.
+#[cfg(any(feature = "actix-web", feature = "axum", feature = "tonic"))]
+pub(crate) fn rejection_status(error: &Error) -> u16 {
+    match error {
+        Error::InvalidAuthPayload(_)
+        | Error::AuthPayloadExpired
+        | Error::AuthPayloadReplayed
+        | Error::AuthPayloadDuplicateParameter(_)
+        | Error::AuthPayloadMixedCaseHost(_)
+        | Error::AuthPayloadPathTraversal(_)
+        | Error::AuthPayloadPort(_)
+        | Error::AuthPayloadUnsignedHeader(_)
+        | Error::InvalidEksToken(_)
+        | Error::JsonError(_) => 401,
+        Error::StsError { code, .. } if code.is_authentication_failure() => 401,
+        _ => 503,
+    }
+}
+
+/// Sends the requests of a [`PayloadVerifier`] to STS
+///
+/// This is the `DispatchSignedRequest` trait of rusoto, which `rusoto_core::HttpClient`
+/// implements for any connector, such as one going through an egress proxy, and which
+/// `rusoto_mock` implements for tests. Transports receive requests exactly as they must be
+/// sent: they must not sign them again.
+pub type StsTransport = dyn DispatchSignedRequest + Send + Sync;
+
+/// Verifies the payloads generated by [`AwsAuthIamPayload::new`] and the URLs generated by
+/// [`presigned_url`](crate::client::presigned_url) with the same policy
+#[derive(Clone)]
+pub struct PayloadVerifier {
+    allowed_hosts: HashSet<String>,
+    required_headers: Vec<(String, String)>,
+    max_age: Option<Duration>,
+    replay_cache: Option<Arc<dyn ReplayCache>>,
+    retry: RetryPolicy,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    transport: Option<Arc<StsTransport>>,
+    observers: Vec<Arc<dyn VerificationObserver>>,
+}
+
+impl fmt::Debug for PayloadVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PayloadVerifier")
+            .field("allowed_hosts", &self.allowed_hosts)
+            .field("required_headers", &self.required_headers)
+            .field("max_age", &self.max_age)
+            .field("replay_cache", &self.replay_cache)
+            .field("retry", &self.retry)
+            .field("circuit_breaker", &self.circuit_breaker)
+            .field("rate_limiter", &self.rate_limiter)
+            .field("transport", &self.transport.as_ref().map(|_| "<custom>"))
+            .field("observers", &self.observers.len())
+            .finish()
+    }
+}
+
+impl Default for PayloadVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PayloadVerifier {
+    /// Verify requests sent to any of the [`sts::endpoint_hosts`]
+    pub fn new() -> Self {
+        Self {
+            allowed_hosts: sts::endpoint_hosts(),
+            required_headers: vec![],
+            max_age: None,
+            replay_cache: None,
+            retry: RetryPolicy::default(),
+            circuit_breaker: None,
+            rate_limiter: None,
+            transport: None,
+            observers: vec![],
+        }
+    }
+
+    /// Only accept requests for the given STS hosts, such as `sts.us-west-2.amazonaws.com`
+    ///
+    /// Requests for any other host are rejected before anything is sent, so that the verifier
+    /// cannot be made to send requests to hosts chosen by the client.
+    pub fn with_allowed_hosts<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.allowed_hosts = hosts
+            .into_iter()
+            .map(|host| host.as_ref().to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// STS hosts requests may be sent to
+    pub fn allowed_hosts(&self) -> &HashSet<String> {
+        &self.allowed_hosts
+    }
+
+    /// Only accept requests with a signed `header` whose value is exactly `value`
+    ///
+    /// Binding requests to an audience this way keeps requests signed for another verifier
+    /// from being replayed against this one. Pre-signed URLs cannot carry headers: they must
+    /// have signed `header`, and the verifier sends `value` with them.
+    pub fn with_required_header(mut self, header: &str, value: &str) -> Self {
+        self.required_headers
+            .push((header.to_ascii_lowercase(), value.to_string()));
+        self
+    }
+
+    /// Only accept requests bound to `server_id` with the [`VAULT_SERVER_ID_HEADER`], like
+    /// Vault's `iam_server_id_header_value`
+    pub fn with_server_id(self, server_id: &str) -> Self {
+        self.with_required_header(VAULT_SERVER_ID_HEADER, server_id)
+    }
+
+    /// Only accept requests signed at most `max_age` ago
+    ///
+    /// STS accepts POST requests for 15 minutes after they were signed, and pre-signed URLs
+    /// until they expire, which is also what the verifier accepts by default.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Reject requests whose signature is already in `cache`
+    ///
+    /// Signatures are added to the cache before requests are sent to STS, and remembered for as
+    /// long as STS would accept them.
+    pub fn with_replay_cache(mut self, cache: Arc<dyn ReplayCache>) -> Self {
+        self.replay_cache = Some(cache);
+        self
+    }
+
+    /// Retry the requests to STS according to `policy`
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Stop sending requests to STS while `breaker` is open
+    ///
+    /// The same breaker can be shared by verifiers, which are then throttled together, as they
+    /// are by STS.
+    pub fn with_circuit_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.circuit_breaker = Some(breaker);
+        self
+    }
+
+    /// Limit the rate of the requests to STS with `limiter`
+    ///
+    /// Requests are shed before the replay cache sees them, so that clients can retry them.
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Send requests to STS with `transport` instead of a new `rusoto_core::HttpClient`
+    pub fn with_transport(mut self, transport: Arc<StsTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Tell `observer` about every decision, in addition to the other observers
+    ///
+    /// Observers see malformed requests too, as well as the requests rejected before calling STS.
+    pub fn with_observer(mut self, observer: Arc<dyn VerificationObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Check `payload` and ask STS who signed it
+    pub async fn verify(&self, payload: &AwsAuthIamPayload) -> Result<CallerIdentity, Error> {
+        let transport = self.transport()?;
+        self.verify_with_dispatcher(&*transport, payload).await
+    }
+
+    /// Check the pre-signed `url` and ask STS who signed it
+    pub async fn verify_presigned_url(&self, url: &str) -> Result<CallerIdentity, Error> {
+        let transport = self.transport()?;
+        self.verify_observed(
+            &*transport,
+            RequestKind::PresignedUrl,
+            self.check_presigned_url(url),
+        )
+        .await
+    }
+
+    /// Check `request`, in whichever form, and ask STS who signed it
+    pub async fn verify_request(
+        &self,
+        request: &SignedIdentityRequest,
+    ) -> Result<CallerIdentity, Error> {
+        let transport = self.transport()?;
+        self.verify_request_with_dispatcher(&*transport, request)
+            .await
+    }
+
+    fn transport(&self) -> Result<Arc<StsTransport>, Error> {
+        match self.transport {
+            Some(ref transport) => Ok(Arc::clone(transport)),
+            None => Ok(Arc::new(HttpClient::new()?)),
+        }
+    }
+
+    pub(crate) async fn verify_with_dispatcher<D>(
+        &self,
+        dispatcher: &D,
+        payload: &AwsAuthIamPayload,
+    ) -> Result<CallerIdentity, Error>
+    where
+        D: DispatchSignedRequest + ?Sized,
+    {
+        self.verify_observed(dispatcher, RequestKind::Payload, self.check(payload))
+            .await
+    }
+
+    pub(crate) async fn verify_request_with_dispatcher<D>(
+        &self,
+        dispatcher: &D,
+        request: &SignedIdentityRequest,
+    ) -> Result<CallerIdentity, Error>
+    where
+        D: DispatchSignedRequest + ?Sized,
+    {
+        let (kind, checked) = match request {
+            SignedIdentityRequest::Payload(payload) => (RequestKind::Payload, self.check(payload)),
+            SignedIdentityRequest::PresignedUrl(url) => {
+                (RequestKind::PresignedUrl, self.check_presigned_url(url))
+            }
+        };
+        self.verify_observed(dispatcher, kind, checked).await
+    }
+
+    /// Verify `checked`, if the checks passed, and tell the observers about the decision
+    async fn verify_observed<D>(
+        &self,
+        dispatcher: &D,
+        kind: RequestKind,
+        checked: Result<CheckedRequest, Error>,
+    ) -> Result<CallerIdentity, Error>
+    where
+        D: DispatchSignedRequest + ?Sized,
+    {
+        let mut calls = StsCalls::default();
+        let request = checked.as_ref().ok().map(CheckedRequest::metadata);
+        let result = match checked {
+            Ok(checked) => self.verify_checked(dispatcher, checked, &mut calls).await,
+            Err(e) => Err(e),
+        };
+
+        if !self.observers.is_empty() {
+            let event = VerificationEvent {
+                at: Utc::now(),
+                kind,
+                request,
+                outcome: result
+                    .as_ref()
+                    .map(Clone::clone)
+                    .map_err(FailureReason::from),
+                sts_attempts: calls.attempts,
+                sts_latency: calls.latency,
+            };
+            for observer in &self.observers {
+                observer.observe(&event);
+            }
+        }
+        result
+    }
+
+    /// Send a request that passed the checks to STS
+    async fn verify_checked<D>(
+        &self,
+        dispatcher: &D,
+        checked: CheckedRequest,
+        calls: &mut StsCalls,
+    ) -> Result<CallerIdentity, Error>
+    where
+        D: DispatchSignedRequest + ?Sized,
+    {
+        info!("Verifying signed GetCallerIdentity request");
+        if let Some(ref breaker) = self.circuit_breaker {
+            if breaker.is_open() {
+                return Err(Error::StsCircuitOpen);
+            }
+        }
+        self.acquire().await?;
+        if let Some(ref cache) = self.replay_cache {
+            if !cache.insert(&checked.signature, checked.expires_at) {
+                return Err(Error::AuthPayloadReplayed);
+            }
+        }
+
+        // Requests that STS did not verify can be presented again
+        let response = match self.send_with_retries(dispatcher, &checked, calls).await {
+            Ok(response) => response,
+            Err(e) => {
+                if let Some(ref cache) = self.replay_cache {
+                    cache.remove(&checked.signature);
+                }
+                return Err(e);
+            }
+        };
+
+        let identity = sts::parse_caller_identity(&response)?;
+        debug!(
+            "Verified signed GetCallerIdentity request of {}",
+            identity.arn
+        );
+        Ok(identity)
+    }
+
+    /// Send `checked` to STS, retrying according to the retry policy
+    async fn send_with_retries<D>(
+        &self,
+        dispatcher: &D,
+        checked: &CheckedRequest,
+        calls: &mut StsCalls,
+    ) -> Result<Bytes, Error>
+    where
+        D: DispatchSignedRequest + ?Sized,
+    {
+        let mut attempt = 1;
+        loop {
+            let started = Instant::now();
+            let (result, retry_after) = self.send(dispatcher, checked).await;
+            calls.attempts += 1;
+            calls.latency += started.elapsed();
+            if let Some(ref breaker) = self.circuit_breaker {
+                breaker.record(result.as_ref().map(|_| ()));
+            }
+            match result {
+                Err(e) if attempt < self.retry.max_attempts && self.retry.should_retry(&e) => {
+                    let backoff = self.retry.backoff(attempt);
+                    let delay = match retry_after {
+                        Some(delay) if delay > self.retry.max_backoff => return Err(e),
+                        Some(delay) => delay,
+                        None => rand::thread_rng().gen_range(StdDuration::default()..=backoff),
+                    };
+                    if self
+                        .circuit_breaker
+                        .as_ref()
+                        .is_some_and(|breaker| breaker.is_open())
+                    {
+                        return Err(e);
+                    }
+                    warn!(
+                        "Attempt {} of verifying a signed request failed, retrying in {:?}: {}",
+                        attempt, delay, e
+                    );
+                    tokio::time::sleep(delay).await;
+                    self.acquire().await?;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Wait for the rate limiter to let a request to STS through, if there is one
+    async fn acquire(&self) -> Result<(), Error> {
+        match self.rate_limiter {
+            Some(ref limiter) => limiter.acquire().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Send `checked` to STS once, with how long the response asks to wait before retrying
+    async fn send<D>(
+        &self,
+        dispatcher: &D,
+        checked: &CheckedRequest,
+    ) -> (Result<Bytes, Error>, Option<StdDuration>)
+    where
+        D: DispatchSignedRequest + ?Sized,
+    {
+        let method = if checked.body.is_some() {
+            "POST"
+        } else {
+            "GET"
+        };
+        let mut request = rusoto_core::signature::SignedRequest::new(
+            method,
+            "sts",
+            &Region::Custom {
+                name: "us-east-1".to_string(),
+                endpoint: format!("https://{}", checked.host),
+            },
+            "/",
+        );
+        request.set_hostname(Some(checked.host.clone()));
+        request.canonical_query_string = checked.query.clone();
+        for (name, value) in &checked.headers {
+            request.add_header(name, value);
+        }
+        request.set_payload(checked.body.clone());
+
+        match sts::dispatch_buffered(dispatcher, request).await {
+            Ok(response) if response.status.is_success() => (Ok(response.body), None),
+            Ok(response) => (
+                Err(sts::parse_error(response.status.as_u16(), &response.body)),
+                retry::retry_after(&response.headers),
+            ),
+            Err(e) => (Err(e), None),
+        }
+    }
+
+    /// Validate everything about `payload` that can be validated without calling STS
+    fn check(&self, payload: &AwsAuthIamPayload) -> Result<CheckedRequest, Error> {
+        let invalid = |reason: String| Err(Error::InvalidAuthPayload(reason));
+
+        if payload.iam_http_request_method != "POST" {
+            return invalid(format!(
+                "method {} is not POST",
+                payload.iam_http_request_method
+            ));
+        }
+
+        let url = match base64::decode(&payload.iam_request_url) {
+            Ok(url) => url,
+            Err(_) => return invalid("iam_request_url is not valid base64".to_string()),
+        };
+        let url = match String::from_utf8(url) {
+            Ok(url) => url,
+            Err(_) => return invalid("iam_request_url is not a URL".to_string()),
+        };
+        let (uri, host) = self.check_url(&url)?;
+        if uri.query().is_some() {
+            return invalid("URL has a query".to_string());
+        }
+
+        let body = match base64::decode(&payload.iam_request_body) {
+            Ok(body) => body,
+            Err(_) => return invalid("iam_request_body is not valid base64".to_string()),
+        };
+        let mut params: Vec<(String, String)> = match serde_urlencoded::from_bytes(&body) {
+            Ok(params) => params,
+            Err(_) => return invalid("malformed body".to_string()),
+        };
+        params.sort();
+        check_unique(params.iter().map(|(name, _)| name.as_str()))?;
+        if params
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .ne(EXPECTED_BODY.iter().cloned())
+        {
+            return invalid("body is not a GetCallerIdentity request".to_string());
+        }
+
+        let header = |name: &str| {
+            payload
+                .iam_request_headers
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, values)| values.as_slice())
+        };
+        let authorization = match header("authorization") {
+            Some([authorization]) => authorization,
+            _ => return invalid("request is not signed".to_string()),
+        };
+        let part = |name: &str| {
+            authorization
+                .split(", ")
+                .find_map(|part| part.strip_prefix(name))
+                .unwrap_or_default()
+        };
+        // The credential follows the algorithm rather than a comma
+        let credential = authorization
+            .split_whitespace()
+            .find_map(|part| part.trim_end_matches(',').strip_prefix("Credential="))
+            .unwrap_or_default();
+        let signature = part("Signature=");
+        if signature.is_empty() {
+            return invalid("authorization has no signature".to_string());
+        }
+        for (name, expected) in &self.required_headers {
+            match header(name) {
+                Some([value]) if value == expected => {}
+                Some([_]) => return invalid(format!("{} has the wrong value", name)),
+                _ => return invalid(format!("{} is missing or repeated", name)),
+            }
+        }
+        let present: Vec<&str> = payload
+            .iam_request_headers
+            .keys()
+            .map(String::as_str)
+            .collect();
+        self.check_signed_headers(part("SignedHeaders="), &present)?;
+        let signed_at = match header("x-amz-date") {
+            Some([date]) => parse_date(date)?,
+            _ => return invalid("x-amz-date is missing or repeated".to_string()),
+        };
+        let validity = Duration::minutes(SIGNATURE_VALIDITY_MINUTES);
+        let max_age = self.max_age.unwrap_or(validity).min(validity);
+        self.check_age(signed_at, max_age)?;
+
+        let headers = payload
+            .iam_request_headers
+            .iter()
+            // The length is set from the body when the request is sent
+            .filter(|(name, _)| !name.eq_ignore_ascii_case("content-length"))
+            .flat_map(|(name, values)| {
+                values
+                    .iter()
+                    .map(move |value| (name.clone(), value.clone()))
+            })
+            .collect();
+        Ok(CheckedRequest {
+            host,
+            query: String::new(),
+            body: Some(body),
+            headers,
+            credential: credential.to_string(),
+            signature: signature.to_string(),
+            signed_at,
+            expires_at: signed_at + max_age,
+        })
+    }
+
+    /// Validate everything about the pre-signed `url` that can be validated without calling STS
+    fn check_presigned_url(&self, url: &str) -> Result<CheckedRequest, Error> {
+        let invalid = |reason: String| Err(Error::InvalidAuthPayload(reason));
+
+        let (uri, host) = self.check_url(url)?;
+        let query = uri.query().unwrap_or_default();
+        let params: Vec<(String, String)> = match serde_urlencoded::from_str(query) {
+            Ok(params) => params,
+            Err(_) => return invalid("malformed query".to_string()),
+        };
+        check_unique(params.iter().map(|(name, _)| name.as_str()))?;
+        if let Some((name, _)) = params
+            .iter()
+            .find(|(name, _)| !ALLOWED_PARAMS.contains(&name.as_str()))
+        {
+            return invalid(format!("unexpected query parameter {}", name));
+        }
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        if let Some(missing) = REQUIRED_PARAMS.iter().find(|name| param(name).is_none()) {
+            return invalid(format!("missing query parameter {}", missing));
+        }
+        if param("Action") != Some("GetCallerIdentity") {
+            return invalid("action is not GetCallerIdentity".to_string());
+        }
+        if param("Version").is_some_and(|version| version != EXPECTED_BODY[1].1) {
+            return invalid("unexpected API version".to_string());
+        }
+
+        self.check_signed_headers(param("X-Amz-SignedHeaders").unwrap_or_default(), &[])?;
+        let signed_at = parse_date(param("X-Amz-Date").unwrap_or_default())?;
+        let expires = match param("X-Amz-Expires").and_then(|e| e.parse::<i64>().ok()) {
+            Some(expires) => Duration::seconds(expires),
+            None => return invalid("malformed X-Amz-Expires".to_string()),
+        };
+        let max_age = self.max_age.unwrap_or(expires).min(expires);
+        self.check_age(signed_at, max_age)?;
+
+        Ok(CheckedRequest {
+            host,
+            query: query.to_string(),
+            body: None,
+            headers: self.required_headers.clone(),
+            credential: param("X-Amz-Credential").unwrap_or_default().to_string(),
+            signature: param("X-Amz-Signature").unwrap_or_default().to_string(),
+            signed_at,
+            expires_at: signed_at + max_age,
+        })
+    }
+
+    /// Check that `url` is the root of an allowed STS endpoint, returning the URL and its host
+    fn check_url(&self, url: &str) -> Result<(Uri, String), Error> {
+        let invalid = |reason: String| Err(Error::InvalidAuthPayload(reason));
+
+        let uri: Uri = match url.parse() {
+            Ok(uri) => uri,
+            Err(_) => return invalid("not a URL".to_string()),
+        };
+        if uri.scheme_str() != Some("https") {
+            return invalid("URL is not HTTPS".to_string());
+        }
+        let host = match uri.host() {
+            Some(host) => host.to_string(),
+            None => return invalid("URL has no host".to_string()),
+        };
+        if let Some(port) = non_https_port(&uri) {
+            return Err(Error::AuthPayloadPort(port));
+        }
+        if host.bytes().any(|b| b.is_ascii_uppercase()) {
+            return Err(Error::AuthPayloadMixedCaseHost(host));
+        }
+        if !self.allowed_hosts.contains(&host) {
+            return invalid(format!("{} is not an allowed STS host", host));
+        }
+        let path = uri.path();
+        let dot_segment = |segment: &str| {
+            let segment = segment.to_ascii_lowercase().replace("%2e", ".");
+            segment == "." || segment == ".."
+        };
+        if path.contains("//")
+            || path.contains('\\')
+            || path.to_ascii_lowercase().contains("%2f")
+            || path.to_ascii_lowercase().contains("%5c")
+            || path.split('/').any(dot_segment)
+        {
+            return Err(Error::AuthPayloadPathTraversal(path.to_string()));
+        }
+        if path != "/" {
+            return invalid("URL path is not /".to_string());
+        }
+        Ok((uri, host))
+    }
+
+    /// Check that the required headers, the host and the critical headers among `present` are
+    /// in `signed_headers`
+    fn check_signed_headers(&self, signed_headers: &str, present: &[&str]) -> Result<(), Error> {
+        let signed_headers: HashSet<_> = signed_headers.split(';').collect();
+        if let Some((name, _)) = self
+            .required_headers
+            .iter()
+            .find(|(name, _)| !signed_headers.contains(name.as_str()))
+        {
+            return Err(Error::InvalidAuthPayload(format!("{} is not signed", name)));
+        }
+        let critical = present
+            .iter()
+            .map(|name| name.to_ascii_lowercase())
+            .filter(|name| CRITICAL_HEADERS.contains(&name.as_str()))
+            .chain(std::iter::once("host".to_string()));
+        for name in critical {
+            if !signed_headers.contains(name.as_str()) {
+                return Err(Error::AuthPayloadUnsignedHeader(name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that a request signed at `signed_at` is not older than `max_age`
+    fn check_age(&self, signed_at: DateTime<Utc>, max_age: Duration) -> Result<(), Error> {
+        if Utc::now() > signed_at + max_age {
+            return Err(Error::AuthPayloadExpired);
+        }
+        Ok(())
+    }
+}
+
+/// Check that no name in `names` appears twice
+fn check_unique<'a, I>(names: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut seen = HashSet::new();
+    match names.into_iter().find(|name| !seen.insert(*name)) {
+        Some(name) => Err(Error::AuthPayloadDuplicateParameter(name.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Parse an `X-Amz-Date`
+fn parse_date(date: &str) -> Result<DateTime<Utc>, Error> {
+    let date = NaiveDateTime::parse_from_str(date, sigv4::DATE_TIME_FORMAT)
+        .map_err(|_| Error::InvalidAuthPayload("malformed X-Amz-Date".to_string()))?;
+    Ok(Utc.from_utc_datetime(&date))
+}
+
+/// The parts of a request that passed the checks of [`PayloadVerifier`], to send to STS
+struct CheckedRequest {
+    host: String,
+    query: String,
+    body: Option<Vec<u8>>,
+    headers: Vec<(String, String)>,
+    credential: String,
+    signature: String,
+    signed_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+impl CheckedRequest {
+    fn metadata(&self) -> RequestMetadata {
+        let (access_key_id, credential_scope) = self
+            .credential
+            .split_once('/')
+            .unwrap_or((&self.credential, ""));
+        RequestMetadata {
+            host: self.host.clone(),
+            access_key_id: access_key_id.to_string(),
+            credential_scope: credential_scope.to_string(),
+            signed_at: self.signed_at,
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+/// How many requests a verification sent to STS, and how long they took
+#[derive(Default)]
+struct StsCalls {
+    attempts: u32,
+    latency: StdDuration,
+}
+
+/// The port of `uri` unless it is the HTTPS port STS listens on, explicitly or implicitly
+pub(crate) fn non_https_port(uri: &Uri) -> Option<u16> {
+    uri.port_u16().filter(|port| *port != 443)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use rusoto_mock::{MockRequestDispatcher, MultipleMockRequestDispatcher};
+
+    use crate::sts::tests::CALLER_IDENTITY_RESPONSE;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn payloads_are_verified_with_sts() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let payload = AwsAuthIamPayload::new(&credentials, Some(Region::UsWest2), HashMap::new());
+        let dispatcher = MockRequestDispatcher::with_status(200)
+            .with_body(CALLER_IDENTITY_RESPONSE)
+            .with_request_checker(|request| {
+                assert_eq!(request.method, "POST");
+                assert_eq!(request.hostname(), "sts.us-west-2.amazonaws.com");
+                assert!(request.headers.contains_key("authorization"));
+                assert!(!request.headers.contains_key("content-length"));
+            });
+
+        let identity = PayloadVerifier::new()
+            .with_transport(Arc::new(dispatcher))
+            .verify(&payload)
+            .await?;
+        assert_eq!(identity.account, "123456789012");
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn malicious_payloads_are_rejected() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let verifier = PayloadVerifier::new();
+        let payload = AwsAuthIamPayload::new(&credentials, Some(Region::UsWest2), HashMap::new());
+        assert!(verifier.check(&payload).is_ok());
+
+        let with_url = |url: &str| AwsAuthIamPayload {
+            iam_request_url: base64::encode(url),
+            ..payload.clone()
+        };
+        let with_body = |body: &str| AwsAuthIamPayload {
+            iam_request_body: base64::encode(body),
+            ..payload.clone()
+        };
+        let mut unsigned = payload.clone();
+        unsigned.iam_request_headers.remove("authorization");
+
+        for payload in [
+            AwsAuthIamPayload {
+                iam_http_request_method: "GET".to_string(),
+                ..payload.clone()
+            },
+            with_url("https://attacker.example.com/"),
+            with_url("http://sts.us-west-2.amazonaws.com/"),
+            with_url("https://sts.us-west-2.amazonaws.com/?Action=AssumeRole"),
+            with_body("Action=AssumeRole&Version=2011-06-15"),
+            unsigned,
+        ]
+        .iter()
+        {
+            match verifier.check(payload) {
+                Err(Error::InvalidAuthPayload(_)) => {}
+                _ => panic!("{:?} was not rejected", payload),
+            }
+        }
+
+        let mut unsigned_token = payload.clone();
+        unsigned_token.iam_request_headers.insert(
+            "X-Amz-Security-Token".to_string(),
+            vec!["token".to_string()],
+        );
+        assert!(matches!(
+            verifier.check(&with_body(
+                "Action=GetCallerIdentity&Version=2011-06-15&Action=GetCallerIdentity"
+            )),
+            Err(Error::AuthPayloadDuplicateParameter(name)) if name == "Action"
+        ));
+        assert!(matches!(
+            verifier.check(&with_url("https://STS.us-west-2.amazonaws.com/")),
+            Err(Error::AuthPayloadMixedCaseHost(_))
+        ));
+        for path in ["/./", "/a/../", "/%2e%2E/", "//", "/%2F"] {
+            let url = format!("https://sts.us-west-2.amazonaws.com{}", path);
+            assert!(matches!(
+                verifier.check(&with_url(&url)),
+                Err(Error::AuthPayloadPathTraversal(_))
+            ));
+        }
+        assert!(matches!(
+            verifier.check(&with_url("https://sts.us-west-2.amazonaws.com:8443/")),
+            Err(Error::AuthPayloadPort(8443))
+        ));
+        assert!(verifier
+            .check(&with_url("https://sts.us-west-2.amazonaws.com:443/"))
+            .is_ok());
+        assert!(matches!(
+            verifier.check(&unsigned_token),
+            Err(Error::AuthPayloadUnsignedHeader(name)) if name == "x-amz-security-token"
+        ));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn only_allowed_hosts_are_accepted() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let payload = AwsAuthIamPayload::new(&credentials, Some(Region::UsWest2), HashMap::new());
+        assert!(PayloadVerifier::new()
+            .allowed_hosts()
+            .contains("sts-fips.us-east-1.amazonaws.com"));
+
+        let fips = PayloadVerifier::new().with_allowed_hosts(sts::FIPS_ENDPOINT_HOSTS);
+        assert!(matches!(
+            fips.check(&payload),
+            Err(Error::InvalidAuthPayload(_))
+        ));
+        let regional = PayloadVerifier::new().with_allowed_hosts(["STS.us-west-2.amazonaws.com"]);
+        assert!(regional.check(&payload).is_ok());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn required_headers_must_be_signed() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let verifier = PayloadVerifier::new().with_server_id("vault.example.com");
+        let sign = |server_id: &str| {
+            AwsAuthIamPayload::new(
+                &credentials,
+                Some(Region::UsWest2),
+                [(VAULT_SERVER_ID_HEADER, server_id)]
+                    .iter()
+                    .cloned()
+                    .collect(),
+            )
+        };
+        assert!(verifier.check(&sign("vault.example.com")).is_ok());
+
+        let mut unsigned =
+            AwsAuthIamPayload::new(&credentials, Some(Region::UsWest2), HashMap::new());
+        unsigned.iam_request_headers.insert(
+            VAULT_SERVER_ID_HEADER.to_string(),
+            vec!["vault.example.com".to_string()],
+        );
+        let unbound = AwsAuthIamPayload::new(&credentials, Some(Region::UsWest2), HashMap::new());
+        for payload in [sign("other.example.com"), unsigned, unbound].iter() {
+            match verifier.check(payload) {
+                Err(Error::InvalidAuthPayload(_)) => {}
+                _ => panic!("{:?} was not rejected", payload),
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn presigned_urls_share_the_policy() -> Result<(), crate::Error> {
+        use crate::client::presigned_url;
+
+        let credentials = crate::client::tests::credentials().await?;
+        let verifier = PayloadVerifier::new().with_server_id("vault.example.com");
+        let url = presigned_url(
+            &credentials,
+            Some(Region::UsWest2),
+            [(VAULT_SERVER_ID_HEADER, "vault.example.com")]
+                .iter()
+                .cloned()
+                .collect(),
+            None,
+        );
+        let dispatcher = MockRequestDispatcher::with_status(200)
+            .with_body(CALLER_IDENTITY_RESPONSE)
+            .with_request_checker(|request| {
+                assert_eq!(request.method, "GET");
+                assert!(request
+                    .canonical_query_string()
+                    .contains("Action=GetCallerIdentity"));
+                assert_eq!(
+                    request.headers["x-vault-aws-iam-server-id"],
+                    vec![b"vault.example.com".to_vec()]
+                );
+            });
+        let identity = verifier
+            .verify_request_with_dispatcher(
+                &dispatcher,
+                &SignedIdentityRequest::PresignedUrl(url.clone()),
+            )
+            .await?;
+        assert_eq!(identity.account, "123456789012");
+
+        let unbound = presigned_url(&credentials, Some(Region::UsWest2), HashMap::new(), None);
+        assert!(matches!(
+            verifier.check_presigned_url(&unbound),
+            Err(Error::InvalidAuthPayload(_))
+        ));
+        let mut expired = url::Url::parse(&url).unwrap();
+        let params: Vec<(String, String)> = expired
+            .query_pairs()
+            .map(|(k, v)| match k.as_ref() {
+                "X-Amz-Date" => (k.into_owned(), "20200101T000000Z".to_string()),
+                _ => (k.into_owned(), v.into_owned()),
+            })
+            .collect();
+        expired.query_pairs_mut().clear().extend_pairs(params);
+        assert!(matches!(
+            verifier.check_presigned_url(expired.as_str()),
+            Err(Error::AuthPayloadExpired)
+        ));
+
+        let payload = AwsAuthIamPayload::new(&credentials, Some(Region::UsWest2), HashMap::new());
+        assert!(matches!(
+            PayloadVerifier::new()
+                .with_max_age(Duration::seconds(-1))
+                .check(&payload),
+            Err(Error::AuthPayloadExpired)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn replays_are_rejected() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let payload = AwsAuthIamPayload::new(&credentials, Some(Region::UsWest2), HashMap::new());
+        let cache = Arc::new(MemoryReplayCache::new());
+        let verifier = PayloadVerifier::new().with_replay_cache(cache.clone());
+        let dispatcher =
+            MockRequestDispatcher::with_status(200).with_body(CALLER_IDENTITY_RESPONSE);
+
+        verifier
+            .verify_with_dispatcher(&dispatcher, &payload)
+            .await?;
+        assert_eq!(cache.len(), 1);
+        assert!(matches!(
+            verifier.verify_with_dispatcher(&dispatcher, &payload).await,
+            Err(Error::AuthPayloadReplayed)
+        ));
+
+        // Requests shed by the rate limiter can be retried
+        let cache = Arc::new(MemoryReplayCache::new());
+        let verifier = PayloadVerifier::new()
+            .with_replay_cache(cache.clone())
+            .with_rate_limiter(Arc::new(RateLimiter::new(0.001, 1, ShedPolicy::Reject)));
+        verifier
+            .verify_with_dispatcher(&dispatcher, &payload)
+            .await?;
+        let other = AwsAuthIamPayload::new(&credentials, Some(Region::UsEast2), HashMap::new());
+        assert!(matches!(
+            verifier.verify_with_dispatcher(&dispatcher, &other).await,
+            Err(Error::StsRateLimited)
+        ));
+        assert_eq!(cache.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn requests_sts_did_not_verify_can_be_presented_again() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let payload = AwsAuthIamPayload::new(&credentials, Some(Region::UsWest2), HashMap::new());
+        let cache = Arc::new(MemoryReplayCache::new());
+        // The retry of the server error is shed, as the only token was taken by the first attempt
+        let verifier = PayloadVerifier::new()
+            .with_replay_cache(cache.clone())
+            .with_rate_limiter(Arc::new(RateLimiter::new(20.0, 1, ShedPolicy::Reject)))
+            .with_retry_policy(RetryPolicy {
+                initial_backoff: StdDuration::from_millis(1),
+                ..Default::default()
+            });
+        let dispatcher = MultipleMockRequestDispatcher::new(vec![
+            MockRequestDispatcher::with_status(500),
+            MockRequestDispatcher::with_status(200).with_body(CALLER_IDENTITY_RESPONSE),
+        ]);
+
+        assert!(matches!(
+            verifier.verify_with_dispatcher(&dispatcher, &payload).await,
+            Err(Error::StsRateLimited)
+        ));
+        assert!(cache.is_empty());
+
+        tokio::time::sleep(StdDuration::from_millis(100)).await;
+        verifier
+            .verify_with_dispatcher(&dispatcher, &payload)
+            .await?;
+        tokio::time::sleep(StdDuration::from_millis(100)).await;
+        assert!(matches!(
+            verifier.verify_with_dispatcher(&dispatcher, &payload).await,
+            Err(Error::AuthPayloadReplayed)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn decisions_are_observed() -> Result<(), crate::Error> {
+        let credentials = crate::client::tests::credentials().await?;
+        let payload = AwsAuthIamPayload::new(&credentials, Some(Region::UsWest2), HashMap::new());
+        let metrics = Arc::new(VerificationMetrics::new());
+        let events = Arc::new(std::sync::Mutex::new(vec![]));
+        let trail = events.clone();
+        let verifier = PayloadVerifier::new()
+            .with_observer(metrics.clone())
+            .with_observer(Arc::new(move |event: &VerificationEvent| {
+                trail.lock().unwrap().push(event.clone())
+            }));
+        let dispatcher =
+            MockRequestDispatcher::with_status(200).with_body(CALLER_IDENTITY_RESPONSE);
+
+        verifier
+            .verify_with_dispatcher(&dispatcher, &payload)
+            .await?;
+        let malformed = SignedIdentityRequest::PresignedUrl("https://example.com/".to_string());
+        assert!(verifier
+            .verify_request_with_dispatcher(&dispatcher, &malformed)
+            .await
+            .is_err());
+
+        let events = events.lock().unwrap();
+        let request = events[0].request.as_ref().unwrap();
+        assert_eq!(request.host, "sts.us-west-2.amazonaws.com");
+        assert_eq!(request.access_key_id, credentials.aws_access_key_id());
+        assert!(request
+            .credential_scope
+            .ends_with("/us-west-2/sts/aws4_request"));
+        assert_eq!(events[0].sts_attempts, 1);
+        assert_eq!(events[0].outcome.as_ref().unwrap().account, "123456789012");
+        assert_eq!(events[1].kind, RequestKind::PresignedUrl);
+        assert!(events[1].request.is_none());
+        assert!(matches!(events[1].outcome, Err(FailureReason::Invalid(_))));
+
+        assert_eq!(metrics.verifications(), 2);
+        assert_eq!(metrics.failures()["invalid"], 1);
+        assert_eq!(metrics.sts_calls(), 1);
+        assert_eq!(metrics.sts_latency().iter().sum::<u64>(), 1);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn throttled_requests_are_retried() -> Result<(), crate::Error> {
+        const THROTTLED: &str = "<ErrorResponse><Error><Code>Throttling</Code>\
+                                 <Message>Rate exceeded</Message></Error></ErrorResponse>";
+        let credentials = crate::client::tests::credentials().await?;
+        let payload = AwsAuthIamPayload::new(&credentials, Some(Region::UsWest2), HashMap::new());
+        let breaker = Arc::new(CircuitBreaker::new(2, StdDuration::from_secs(60)));
+        let verifier = PayloadVerifier::new()
+            .with_retry_policy(RetryPolicy {
+                initial_backoff: StdDuration::from_millis(10),
+                ..Default::default()
+            })
+            .with_circuit_breaker(breaker.clone());
+
+        let dispatcher = MultipleMockRequestDispatcher::new(vec![
+            MockRequestDispatcher::with_status(400)
+                .with_body(THROTTLED)
+                .with_header("Retry-After", "0"),
+            MockRequestDispatcher::with_status(200).with_body(CALLER_IDENTITY_RESPONSE),
+        ]);
+        verifier
+            .verify_with_dispatcher(&dispatcher, &payload)
+            .await?;
+        assert!(!breaker.is_open());
+
+        // A second throttled request in a row opens the circuit, which stops the retries
+        let dispatcher = MultipleMockRequestDispatcher::new(vec![
+            MockRequestDispatcher::with_status(400).with_body(THROTTLED),
+            MockRequestDispatcher::with_status(400).with_body(THROTTLED),
+        ]);
+        assert!(matches!(
+            verifier.verify_with_dispatcher(&dispatcher, &payload).await,
+            Err(Error::StsError {
+                code: sts::StsErrorCode::Throttling,
+                ..
+            })
+        ));
+        assert!(matches!(
+            verifier.verify_with_dispatcher(&dispatcher, &payload).await,
+            Err(Error::StsCircuitOpen)
+        ));
+        Ok(())
+    }
+}
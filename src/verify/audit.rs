@@ -0,0 +1,206 @@
+//! Observing the decisions of a verifier, for audit trails and metrics
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::sts::{CallerIdentity, StsErrorCode};
+use crate::Error;
+
+/// Upper bounds of the buckets of the STS latency histogram of [`VerificationMetrics`]
+pub const LATENCY_BUCKETS: [Duration; 9] = [
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_millis(2500),
+    Duration::from_secs(5),
+];
+
+/// Form of a verified request
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RequestKind {
+    /// A POST payload
+    Payload,
+    /// A pre-signed GET URL
+    PresignedUrl,
+}
+
+/// What a request claims, once it passed the checks made before calling STS
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RequestMetadata {
+    /// STS host the request is for
+    pub host: String,
+    /// ID of the access key the request claims to be signed with
+    pub access_key_id: String,
+    /// Scope of the signature, such as `20150830/us-east-1/sts/aws4_request`
+    pub credential_scope: String,
+    /// When the request was signed
+    pub signed_at: DateTime<Utc>,
+    /// When the verifier stops accepting the request
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Why a verification failed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FailureReason {
+    /// The request is malformed or could reach other things than STS `GetCallerIdentity`
+    Invalid(String),
+    /// The request was signed too long ago
+    Expired,
+    /// The request was already verified
+    Replayed,
+    /// The request was shed by the rate limiter
+    RateLimited,
+    /// The circuit breaker is open
+    CircuitOpen,
+    /// STS did not authenticate the signer
+    Rejected(StsErrorCode),
+    /// STS could not be asked
+    Unavailable(String),
+}
+
+impl FailureReason {
+    /// A short label for the reason, such as `invalid`, fit for metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureReason::Invalid(_) => "invalid",
+            FailureReason::Expired => "expired",
+            FailureReason::Replayed => "replayed",
+            FailureReason::RateLimited => "rate_limited",
+            FailureReason::CircuitOpen => "circuit_open",
+            FailureReason::Rejected(_) => "rejected",
+            FailureReason::Unavailable(_) => "unavailable",
+        }
+    }
+}
+
+impl From<&Error> for FailureReason {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::InvalidAuthPayload(reason) | Error::InvalidEksToken(reason) => {
+                FailureReason::Invalid(reason.clone())
+            }
+            Error::JsonError(_)
+            | Error::AuthPayloadDuplicateParameter(_)
+            | Error::AuthPayloadMixedCaseHost(_)
+            | Error::AuthPayloadPathTraversal(_)
+            | Error::AuthPayloadPort(_)
+            | Error::AuthPayloadUnsignedHeader(_) => FailureReason::Invalid(error.to_string()),
+            Error::AuthPayloadExpired => FailureReason::Expired,
+            Error::AuthPayloadReplayed => FailureReason::Replayed,
+            Error::StsRateLimited => FailureReason::RateLimited,
+            Error::StsCircuitOpen => FailureReason::CircuitOpen,
+            Error::StsError { code, .. } if code.is_authentication_failure() => {
+                FailureReason::Rejected(code.clone())
+            }
+            error => FailureReason::Unavailable(error.to_string()),
+        }
+    }
+}
+
+/// Everything a verifier knows about one of its decisions
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VerificationEvent {
+    /// When the decision was made
+    pub at: DateTime<Utc>,
+    /// Form of the request
+    pub kind: RequestKind,
+    /// What the request claims, if it passed the checks made before calling STS
+    pub request: Option<RequestMetadata>,
+    /// The identity of the signer, or why the request was rejected
+    pub outcome: Result<CallerIdentity, FailureReason>,
+    /// Number of requests sent to STS
+    pub sts_attempts: u32,
+    /// Time spent waiting for STS, over all attempts
+    pub sts_latency: Duration,
+}
+
+/// Receives every decision of a [`PayloadVerifier`](super::PayloadVerifier)
+///
+/// Observers are called synchronously once the decision is made, before it is returned, and
+/// should hand events off rather than block. Closures taking a `&VerificationEvent` are
+/// observers.
+pub trait VerificationObserver: Send + Sync {
+    /// Record `event`
+    fn observe(&self, event: &VerificationEvent);
+}
+
+impl<F> VerificationObserver for F
+where
+    F: Fn(&VerificationEvent) + Send + Sync,
+{
+    fn observe(&self, event: &VerificationEvent) {
+        self(event)
+    }
+}
+
+/// Counters and a histogram of the decisions of a verifier, to export to a metrics system
+#[derive(Debug, Default)]
+pub struct VerificationMetrics {
+    verifications: AtomicU64,
+    failures: Mutex<BTreeMap<&'static str, u64>>,
+    sts_calls: AtomicU64,
+    sts_latency: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+}
+
+impl VerificationMetrics {
+    /// Metrics with every count at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of decisions, successful or not
+    pub fn verifications(&self) -> u64 {
+        self.verifications.load(Ordering::Relaxed)
+    }
+
+    /// Number of failed verifications by [`FailureReason::as_str`]
+    pub fn failures(&self) -> BTreeMap<&'static str, u64> {
+        self.failures
+            .lock()
+            .expect("failures not to be poisoned")
+            .clone()
+    }
+
+    /// Number of verifications that sent requests to STS
+    pub fn sts_calls(&self) -> u64 {
+        self.sts_calls.load(Ordering::Relaxed)
+    }
+
+    /// Number of verifications by the time they waited for STS, in buckets bounded by
+    /// [`LATENCY_BUCKETS`] and a last bucket for the slower ones
+    pub fn sts_latency(&self) -> Vec<u64> {
+        self.sts_latency
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect()
+    }
+}
+
+impl VerificationObserver for VerificationMetrics {
+    fn observe(&self, event: &VerificationEvent) {
+        self.verifications.fetch_add(1, Ordering::Relaxed);
+        if let Err(ref reason) = event.outcome {
+            *self
+                .failures
+                .lock()
+                .expect("failures not to be poisoned")
+                .entry(reason.as_str())
+                .or_default() += 1;
+        }
+        if event.sts_attempts > 0 {
+            self.sts_calls.fetch_add(1, Ordering::Relaxed);
+            let bucket = LATENCY_BUCKETS
+                .iter()
+                .position(|bound| event.sts_latency <= *bound)
+                .unwrap_or(LATENCY_BUCKETS.len());
+            self.sts_latency[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Mapping verified identities to roles
+//!
+//! Verifying a request only tells who signed it. [`Bindings`] decide what the signer is allowed
+//! to be, like the `bound_*` options of the roles of Vault's AWS auth method. `GetCallerIdentity`
+//! does not return the session tags of the caller, so bindings can only match on the account,
+//! the ARN and the session name.
+use serde::{Deserialize, Serialize};
+
+use crate::arn::Principal;
+use crate::sts::CallerIdentity;
+
+/// A role granted to the identities matching all of its conditions
+///
+/// Empty conditions match every identity. Patterns can contain `*`, matching any number of
+/// characters, and `?`, matching one.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    /// Name of the role granted
+    pub role: String,
+    /// IDs of the accounts the identity must belong to
+    #[serde(default)]
+    pub account_ids: Vec<String>,
+    /// Patterns one of which the ARN of the identity must match
+    ///
+    /// Sessions of assumed roles also match the patterns matching the ARN of their role, such as
+    /// `arn:aws:iam::123456789012:role/deploy-*`. Session ARNs do not carry the paths of roles,
+    /// so roles with paths are better matched by patterns such as
+    /// `arn:aws:sts::123456789012:assumed-role/deploy-*`.
+    #[serde(default)]
+    pub principal_arns: Vec<String>,
+    /// Patterns one of which the session name of an assumed role must match. Identities that
+    /// are not assumed role sessions never match if set
+    #[serde(default)]
+    pub session_names: Vec<String>,
+}
+
+impl Binding {
+    /// Grant `role` to every identity
+    pub fn new(role: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            account_ids: vec![],
+            principal_arns: vec![],
+            session_names: vec![],
+        }
+    }
+
+    /// Only grant the role to identities of the account `account_id`
+    pub fn with_account_id(mut self, account_id: &str) -> Self {
+        self.account_ids.push(account_id.to_string());
+        self
+    }
+
+    /// Only grant the role to identities whose ARN matches `pattern`
+    pub fn with_principal_arn(mut self, pattern: &str) -> Self {
+        self.principal_arns.push(pattern.to_string());
+        self
+    }
+
+    /// Only grant the role to assumed role sessions whose name matches `pattern`
+    pub fn with_session_name(mut self, pattern: &str) -> Self {
+        self.session_names.push(pattern.to_string());
+        self
+    }
+
+    /// Whether `identity` matches all the conditions of the binding
+    pub fn matches(&self, identity: &CallerIdentity) -> bool {
+        if !self.account_ids.is_empty() && !self.account_ids.contains(&identity.account) {
+            return false;
+        }
+
+        if !self.principal_arns.is_empty() {
+            let arn = identity.arn.to_string();
+            let role_arn = identity.arn.role_arn().map(|arn| arn.to_string());
+            let matched = self.principal_arns.iter().any(|pattern| {
+                glob_matches(pattern, &arn)
+                    || role_arn
+                        .as_ref()
+                        .is_some_and(|role_arn| glob_matches(pattern, role_arn))
+            });
+            if !matched {
+                return false;
+            }
+        }
+
+        if !self.session_names.is_empty() {
+            match identity.arn.principal() {
+                Some(Principal::AssumedRole { session_name, .. }) => self
+                    .session_names
+                    .iter()
+                    .any(|pattern| glob_matches(pattern, &session_name)),
+                _ => false,
+            }
+        } else {
+            true
+        }
+    }
+}
+
+/// Bindings evaluated in order, the first binding matching an identity deciding its role
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Bindings(pub Vec<Binding>);
+
+impl Bindings {
+    /// Evaluate `bindings` in order
+    pub fn new(bindings: Vec<Binding>) -> Self {
+        Self(bindings)
+    }
+
+    /// The first binding matching `identity`, or `None` if the identity must be denied
+    pub fn evaluate(&self, identity: &CallerIdentity) -> Option<&Binding> {
+        self.0.iter().find(|binding| binding.matches(identity))
+    }
+
+    /// The names of the roles of all bindings matching `identity`, in order
+    pub fn roles<'a>(&'a self, identity: &'a CallerIdentity) -> impl Iterator<Item = &'a str> {
+        self.0
+            .iter()
+            .filter(move |binding| binding.matches(identity))
+            .map(|binding| binding.role.as_str())
+    }
+}
+
+/// Whether `text` matches `pattern`, in which `*` matches any number of characters and `?` one
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` in the pattern and of the text it was matched at
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(arn: &str) -> CallerIdentity {
+        CallerIdentity {
+            arn: arn.parse().unwrap(),
+            account: "123456789012".to_string(),
+            user_id: "AROAEXAMPLE:session".to_string(),
+        }
+    }
+
+    #[test]
+    fn first_matching_binding_decides() {
+        let bindings = Bindings::new(vec![
+            Binding::new("admin")
+                .with_account_id("123456789012")
+                .with_principal_arn("arn:aws:iam::123456789012:role/admin")
+                .with_session_name("alice@*"),
+            Binding::new("deployer").with_principal_arn("arn:aws:iam::123456789012:role/deploy-*"),
+            Binding::new("reader").with_account_id("123456789012"),
+        ]);
+
+        let admin = identity("arn:aws:sts::123456789012:assumed-role/admin/alice@example.com");
+        assert_eq!(bindings.evaluate(&admin).unwrap().role, "admin");
+        assert_eq!(
+            bindings.roles(&admin).collect::<Vec<_>>(),
+            ["admin", "reader"]
+        );
+        let impostor = identity("arn:aws:sts::123456789012:assumed-role/admin/mallory");
+        assert_eq!(bindings.evaluate(&impostor).unwrap().role, "reader");
+        let deployer = identity("arn:aws:sts::123456789012:assumed-role/deploy-prod/ci");
+        assert_eq!(bindings.evaluate(&deployer).unwrap().role, "deployer");
+
+        let other = CallerIdentity {
+            account: "210987654321".to_string(),
+            ..identity("arn:aws:iam::210987654321:user/bob")
+        };
+        assert!(bindings.evaluate(&other).is_none());
+
+        assert!(glob_matches("a*b?c", "axxbyc"));
+        assert!(glob_matches("*", ""));
+        assert!(!glob_matches("a*b", "axxc"));
+    }
+}
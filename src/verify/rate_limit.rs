@@ -0,0 +1,144 @@
+//! Limiting the rate of the requests a verifier sends to STS
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::Error;
+
+/// What to do with the requests that exceed the rate of a [`RateLimiter`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ShedPolicy {
+    /// Fail them right away
+    Reject,
+    /// Delay them until they fit the rate, unless more than `max_queued` requests are already
+    /// waiting or they would have to wait longer than `max_wait`
+    Queue {
+        /// Maximum number of requests waiting at once
+        max_queued: u32,
+        /// Maximum delay of a request
+        max_wait: Duration,
+    },
+}
+
+/// A token bucket limiting the rate of the requests sent to STS
+///
+/// STS limits the rate of requests per account, and a burst of logins would otherwise take
+/// its share from every other workload of the account. Requests that exceed the rate are shed
+/// according to the [`ShedPolicy`], failing with
+/// [`Error::StsRateLimited`](crate::Error::StsRateLimited). Retries count as requests.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    shed: ShedPolicy,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Tokens left, negative when requests are waiting for tokens that are yet to come
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// Allow `rate` requests per second on average, and bursts of up to `burst` requests
+    ///
+    /// A `rate` that is not positive, or not a number, only lets the first burst through.
+    pub fn new(rate: f64, burst: u32, shed: ShedPolicy) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            rate: rate.max(f64::MIN_POSITIVE),
+            burst,
+            shed,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Wait until a request may be sent, or fail if it must be shed
+    pub(crate) async fn acquire(&self) -> Result<(), Error> {
+        let delay = self.reserve()?;
+        if delay > Duration::default() {
+            tokio::time::sleep(delay).await;
+        }
+        Ok(())
+    }
+
+    /// Take a token, and return how long to wait for it
+    fn reserve(&self) -> Result<Duration, Error> {
+        let mut bucket = self.bucket.lock().expect("bucket not to be poisoned");
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(Duration::default());
+        }
+        if let ShedPolicy::Queue {
+            max_queued,
+            max_wait,
+        } = self.shed
+        {
+            let queued = (-bucket.tokens).max(0.0).ceil();
+            // Waits too long to represent, with rates close to zero, are shed
+            let wait = Duration::try_from_secs_f64((1.0 - bucket.tokens) / self.rate);
+            match wait {
+                Ok(wait) if queued < f64::from(max_queued) && wait <= max_wait => {
+                    bucket.tokens -= 1.0;
+                    return Ok(wait);
+                }
+                _ => {}
+            }
+        }
+        warn!("Shedding a verification exceeding the STS rate limit");
+        Err(Error::StsRateLimited)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_over_the_rate_are_shed() {
+        let limiter = RateLimiter::new(0.001, 2, ShedPolicy::Reject);
+        assert_eq!(limiter.reserve().unwrap(), Duration::default());
+        assert_eq!(limiter.reserve().unwrap(), Duration::default());
+        assert!(matches!(limiter.reserve(), Err(Error::StsRateLimited)));
+
+        let limiter = RateLimiter::new(
+            10.0,
+            1,
+            ShedPolicy::Queue {
+                max_queued: 1,
+                max_wait: Duration::from_secs(1),
+            },
+        );
+        assert_eq!(limiter.reserve().unwrap(), Duration::default());
+        let wait = limiter.reserve().unwrap();
+        assert!(wait > Duration::from_millis(90) && wait <= Duration::from_millis(100));
+        assert!(matches!(limiter.reserve(), Err(Error::StsRateLimited)));
+    }
+
+    #[test]
+    fn requests_are_shed_without_a_rate() {
+        for rate in [0.0, -1.0, f64::NAN] {
+            let limiter = RateLimiter::new(
+                rate,
+                1,
+                ShedPolicy::Queue {
+                    max_queued: 10,
+                    max_wait: Duration::MAX,
+                },
+            );
+            assert_eq!(limiter.reserve().unwrap(), Duration::default());
+            assert!(matches!(limiter.reserve(), Err(Error::StsRateLimited)));
+        }
+    }
+}
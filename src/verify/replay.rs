@@ -0,0 +1,91 @@
+//! Rejecting requests that were already verified
+//!
+//! A signed request stays valid for a while, so anyone who intercepts it can present it again
+//! until it expires. Binding requests to an audience with a required header limits where they
+//! can be replayed; remembering their signatures keeps them from being replayed at all.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+/// Remembers the signatures of verified requests
+///
+/// Implement this trait to share the signatures between the instances of a verifier, for
+/// example in Redis.
+pub trait ReplayCache: fmt::Debug + Send + Sync {
+    /// Remember `signature` until `expires_at`, returning `false` if it was remembered already
+    fn insert(&self, signature: &str, expires_at: DateTime<Utc>) -> bool;
+
+    /// Forget `signature`, which was inserted for a request STS did not verify, such as one
+    /// that failed with a server error, so that it can be presented again
+    fn remove(&self, signature: &str);
+}
+
+/// A [`ReplayCache`] that only lives as long as the process
+///
+/// Expired signatures are forgotten as new ones are inserted.
+#[derive(Debug, Default)]
+pub struct MemoryReplayCache {
+    signatures: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl MemoryReplayCache {
+    /// An empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of signatures remembered, including expired ones that were not forgotten yet
+    pub fn len(&self) -> usize {
+        self.signatures().len()
+    }
+
+    /// Whether no signature is remembered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn signatures(&self) -> std::sync::MutexGuard<'_, HashMap<String, DateTime<Utc>>> {
+        self.signatures
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ReplayCache for MemoryReplayCache {
+    fn insert(&self, signature: &str, expires_at: DateTime<Utc>) -> bool {
+        let now = Utc::now();
+        let mut signatures = self.signatures();
+        signatures.retain(|_, expires_at| *expires_at > now);
+        if signatures.contains_key(signature) {
+            return false;
+        }
+        signatures.insert(signature.to_string(), expires_at);
+        true
+    }
+
+    fn remove(&self, signature: &str) {
+        self.signatures().remove(signature);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Duration;
+
+    #[test]
+    fn signatures_are_remembered_until_they_expire() {
+        let cache = MemoryReplayCache::new();
+        let now = Utc::now();
+        assert!(cache.insert("a", now + Duration::minutes(15)));
+        assert!(!cache.insert("a", now + Duration::minutes(15)));
+        assert!(cache.insert("b", now - Duration::seconds(1)));
+        assert!(cache.insert("c", now + Duration::minutes(15)));
+        assert_eq!(cache.len(), 2);
+        cache.remove("a");
+        assert!(cache.insert("a", now + Duration::minutes(15)));
+    }
+}
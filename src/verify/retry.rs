@@ -0,0 +1,196 @@
+//! Retrying the requests a verifier sends to STS
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use hyper::HeaderMap;
+
+use crate::sts::StsErrorCode;
+use crate::Error;
+
+/// When and how often to retry the requests a [`PayloadVerifier`](super::PayloadVerifier)
+/// sends to STS
+///
+/// Retries back off exponentially from `initial_backoff` up to `max_backoff`, with full jitter.
+/// A `Retry-After` from STS replaces the backoff, and requests that are told to wait longer than
+/// `max_backoff` are not retried: the client is better off being told to try again. The
+/// [`Default`] policy makes up to three attempts.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Upper bound of the delay before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay before any retry
+    pub max_backoff: Duration,
+    /// Retry requests STS throttled
+    pub retry_throttling: bool,
+    /// Retry `5xx` responses
+    pub retry_server_errors: bool,
+    /// Retry requests that could not be sent
+    pub retry_transport_errors: bool,
+}
+
+impl RetryPolicy {
+    /// Never retry requests
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn should_retry(&self, error: &Error) -> bool {
+        match error {
+            error if is_throttling(error) => self.retry_throttling,
+            Error::StsError { status, .. } if *status >= 500 => self.retry_server_errors,
+            Error::HttpDispatchError(_) => self.retry_transport_errors,
+            _ => false,
+        }
+    }
+
+    /// Upper bound of the delay after the `attempt`th failed attempt, starting from 1
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        self.initial_backoff
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            retry_throttling: true,
+            retry_server_errors: true,
+            retry_transport_errors: true,
+        }
+    }
+}
+
+/// Stops sending requests to STS for a while once it throttled too many in a row
+///
+/// While the circuit is open, verification fails right away with
+/// [`Error::StsCircuitOpen`](crate::Error::StsCircuitOpen) instead of adding to the load. Once
+/// `cooldown` has passed, requests are sent again, and the circuit opens again at the first
+/// one that is throttled. A request that is not throttled closes the circuit. The state is
+/// shared by all the clones of a verifier.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<CircuitState>,
+}
+
+#[derive(Debug, Default)]
+struct CircuitState {
+    /// Number of throttled requests since the last request that was not
+    throttled: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// Open the circuit for `cooldown` after `threshold` throttled requests in a row
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            state: Mutex::new(CircuitState::default()),
+        }
+    }
+
+    /// Whether requests are currently not sent to STS
+    pub fn is_open(&self) -> bool {
+        let state = self.state.lock().expect("circuit state not to be poisoned");
+        state
+            .open_until
+            .is_some_and(|open_until| Instant::now() < open_until)
+    }
+
+    /// Record the outcome of a request to STS
+    pub(crate) fn record(&self, result: Result<(), &Error>) {
+        let mut state = self.state.lock().expect("circuit state not to be poisoned");
+        match result {
+            Err(error) if is_throttling(error) => {
+                state.throttled = state.throttled.saturating_add(1);
+                if state.throttled >= self.threshold {
+                    state.open_until = Some(Instant::now() + self.cooldown);
+                }
+            }
+            _ => *state = CircuitState::default(),
+        }
+    }
+}
+
+/// Whether STS rejected a request because too many were sent
+fn is_throttling(error: &Error) -> bool {
+    match error {
+        Error::StsError { status, code, .. } => *status == 429 || *code == StsErrorCode::Throttling,
+        _ => false,
+    }
+}
+
+/// How long a response asks to wait with its `Retry-After` header, in seconds or as an HTTP
+/// date
+pub(crate) fn retry_after(headers: &HeaderMap<String>) -> Option<Duration> {
+    let value = headers.get("retry-after")?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now())
+        .to_std()
+        .ok()
+        .or(Some(Duration::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttled() -> Error {
+        Error::StsError {
+            status: 400,
+            code: StsErrorCode::Throttling,
+            message: "Rate exceeded".to_string(),
+        }
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        let policy = RetryPolicy::default();
+        assert!(policy.should_retry(&throttled()));
+        assert!(!policy.should_retry(&Error::StsError {
+            status: 403,
+            code: StsErrorCode::SignatureDoesNotMatch,
+            message: "".to_string(),
+        }));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(10), Duration::from_secs(1));
+
+        let mut headers = HeaderMap::<String>::default();
+        headers.insert("retry-after", "2".to_string());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(2)));
+        headers.insert("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT".to_string());
+        assert_eq!(retry_after(&headers), Some(Duration::default()));
+    }
+
+    #[test]
+    fn sustained_throttling_opens_the_circuit() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        breaker.record(Err(&throttled()));
+        assert!(!breaker.is_open());
+        breaker.record(Err(&throttled()));
+        assert!(breaker.is_open());
+
+        let breaker = CircuitBreaker::new(2, Duration::default());
+        breaker.record(Err(&throttled()));
+        breaker.record(Ok(()));
+        breaker.record(Err(&throttled()));
+        assert!(!breaker.is_open());
+    }
+}
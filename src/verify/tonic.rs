@@ -0,0 +1,235 @@
+//! Authenticating gRPC calls to and from [tonic](https://docs.rs/tonic) servers
+//!
+//! This module requires the `tonic` feature. Clients attach a freshly signed payload to every
+//! call with a [`PayloadInterceptor`]. Servers verify what clients sent, either a payload in the
+//! `x-aws-auth-payload` metadata or an EKS token as bearer token in the `authorization` metadata,
+//! with a [`VerifyLayer`]: the interceptors of tonic are synchronous, but verifying calls STS.
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use aws_auth_payload::verify::tonic::VerifyLayer;
+//! use aws_auth_payload::verify::PayloadVerifier;
+//!
+//! let layer = VerifyLayer::new(Arc::new(PayloadVerifier::new()));
+//! // tonic::transport::Server::builder().layer(layer).add_service(...)
+//! ```
+//! Handlers find the identity of the caller in the extensions of their requests, as a
+//! [`VerifiedCaller`].
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use ::tonic::body::BoxBody;
+use ::tonic::metadata::MetadataValue;
+use ::tonic::service::Interceptor;
+use ::tonic::{Code, Status};
+use hyper::{Request, Response};
+use log::debug;
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::Region;
+use tower::{Layer, Service};
+
+use super::{
+    rejection_status, PayloadVerifier, SignedIdentityRequest, VerifiedCaller, PAYLOAD_HEADER,
+};
+use crate::client::{AwsAuthIamPayload, VAULT_SERVER_ID_HEADER};
+use crate::Error;
+
+/// A client interceptor attaching a payload signed with `credentials` to every call
+///
+/// The `Debug` output redacts the credentials.
+#[derive(Clone)]
+pub struct PayloadInterceptor {
+    credentials: AwsCredentials,
+    region: Option<Region>,
+    headers: Vec<(String, String)>,
+}
+
+impl PayloadInterceptor {
+    /// Sign payloads with `credentials` for the STS endpoint of `region`, or the global
+    /// endpoint if `None`
+    pub fn new(credentials: AwsCredentials, region: Option<Region>) -> Self {
+        Self {
+            credentials,
+            region,
+            headers: vec![],
+        }
+    }
+
+    /// Sign `header` with `value` into the payloads, as servers may require
+    pub fn with_header(mut self, header: &str, value: &str) -> Self {
+        self.headers.push((header.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sign the `X-Vault-AWS-IAM-Server-ID` header with `server_id` into the payloads
+    pub fn with_server_id(self, server_id: &str) -> Self {
+        self.with_header(VAULT_SERVER_ID_HEADER, server_id)
+    }
+
+    /// A payload signed now
+    fn payload(&self) -> AwsAuthIamPayload {
+        let headers: HashMap<&str, &str> = self
+            .headers
+            .iter()
+            .map(|(header, value)| (header.as_str(), value.as_str()))
+            .collect();
+        AwsAuthIamPayload::new(&self.credentials, self.region.as_ref(), headers)
+    }
+}
+
+impl Interceptor for PayloadInterceptor {
+    fn call(&mut self, mut request: ::tonic::Request<()>) -> Result<::tonic::Request<()>, Status> {
+        let json = serde_json::to_vec(&self.payload())
+            .map_err(|e| Status::internal(format!("Unable to serialize payload: {}", e)))?;
+        let mut value =
+            MetadataValue::try_from(base64::encode(json)).expect("base64 to be valid metadata");
+        value.set_sensitive(true);
+        request.metadata_mut().insert(PAYLOAD_HEADER, value);
+        Ok(request)
+    }
+}
+
+impl std::fmt::Debug for PayloadInterceptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PayloadInterceptor")
+            .field("credentials", &"<redacted>")
+            .field("region", &self.region)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+/// A `tower` layer verifying the calls to a tonic server with a [`PayloadVerifier`]
+#[derive(Clone, Debug)]
+pub struct VerifyLayer {
+    verifier: Arc<PayloadVerifier>,
+}
+
+impl VerifyLayer {
+    /// Verify calls with `verifier`
+    pub fn new(verifier: Arc<PayloadVerifier>) -> Self {
+        Self { verifier }
+    }
+}
+
+impl<S> Layer<S> for VerifyLayer {
+    type Service = Verify<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Verify {
+            inner,
+            verifier: Arc::clone(&self.verifier),
+        }
+    }
+}
+
+/// A `tower` service only passing on verified calls, with the identity of the caller in their
+/// extensions as a [`VerifiedCaller`]
+///
+/// Other calls are answered with the `UNAUTHENTICATED` status or, if STS could not be asked,
+/// `UNAVAILABLE`.
+#[derive(Clone, Debug)]
+pub struct Verify<S> {
+    inner: S,
+    verifier: Arc<PayloadVerifier>,
+}
+
+impl<S, B> Service<Request<B>> for Verify<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        // The clone may not be ready, so use the service that was polled and leave the clone
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let verifier = Arc::clone(&self.verifier);
+        let signed = from_headers(&request);
+
+        Box::pin(async move {
+            let identity = match signed {
+                Ok(signed) => verifier.verify_request(&signed).await,
+                Err(error) => Err(error),
+            };
+            match identity {
+                Ok(identity) => {
+                    debug!("Authenticated call of {}", identity.arn);
+                    request.extensions_mut().insert(VerifiedCaller(identity));
+                    inner.call(request).await
+                }
+                Err(error) => {
+                    let code = match rejection_status(&error) {
+                        401 => Code::Unauthenticated,
+                        _ => Code::Unavailable,
+                    };
+                    Ok(Status::new(code, error.to_string()).to_http())
+                }
+            }
+        })
+    }
+}
+
+fn from_headers<B>(request: &Request<B>) -> Result<SignedIdentityRequest, Error> {
+    let headers = request.headers();
+    SignedIdentityRequest::from_headers(|name| {
+        headers.get(name).and_then(|value| value.to_str().ok())
+    })?
+    .ok_or_else(|| Error::InvalidAuthPayload("call carries no signed request".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+
+    use tower::ServiceExt;
+
+    #[test]
+    fn payloads_are_attached_to_calls() {
+        let credentials = AwsCredentials::new("AKIDEXAMPLE", "secret", None, None);
+        let mut interceptor =
+            PayloadInterceptor::new(credentials, Some(Region::UsWest2)).with_server_id("vault");
+        let request = interceptor.call(::tonic::Request::new(())).unwrap();
+        let value = request.metadata().get(PAYLOAD_HEADER).unwrap();
+        let signed = SignedIdentityRequest::from_headers(|name| {
+            Some(name)
+                .filter(|name| *name == PAYLOAD_HEADER)
+                .and(value.to_str().ok())
+        })
+        .unwrap();
+        match signed {
+            Some(SignedIdentityRequest::Payload(payload)) => {
+                assert!(payload
+                    .iam_request_headers
+                    .contains_key(&VAULT_SERVER_ID_HEADER.to_lowercase()));
+            }
+            signed => panic!("Unexpected request {:?}", signed),
+        }
+        assert!(!format!("{:?}", interceptor).contains("secret"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn unauthenticated_calls_are_rejected() {
+        let service = tower::service_fn(|_: Request<()>| async {
+            Ok::<_, Infallible>(Response::new(::tonic::body::empty_body()))
+        });
+        let service = VerifyLayer::new(Arc::new(PayloadVerifier::new())).layer(service);
+        let response = service.oneshot(Request::new(())).await.unwrap();
+        let status = Status::from_header_map(response.headers()).unwrap();
+        assert_eq!(status.code(), Code::Unauthenticated);
+    }
+}